@@ -26,6 +26,7 @@ interface StackConfig {
   useHttps?: boolean;
   acmeEmail?: string;
   buildPlatform?: string;
+  networkSubnet?: string;
 }
 
 interface ServiceConfig {
@@ -114,6 +115,7 @@ stack:
   keycloakDevMode: true          # false for production
   # useHttps: false              # true for production
   # acmeEmail: admin@example.com # Required if useHttps is true
+  # networkSubnet: 172.30.0.0/24 # Docker network; distinct per deployment on a host
 
 services:
   demo:
@@ -189,6 +191,7 @@ function validateStackConfig(value: unknown): StackConfig {
     useHttps: value.useHttps === true,
     acmeEmail: typeof value.acmeEmail === "string" ? value.acmeEmail : undefined,
     buildPlatform: typeof value.buildPlatform === "string" ? value.buildPlatform : undefined,
+    networkSubnet: typeof value.networkSubnet === "string" ? value.networkSubnet : undefined,
   };
 }
 
@@ -323,6 +326,9 @@ function main(): void {
   if (config.stack.buildPlatform) {
     setConfig("buildPlatform", config.stack.buildPlatform);
   }
+  if (config.stack.networkSubnet) {
+    setConfig("networkSubnet", config.stack.networkSubnet);
+  }
 
   // Set services as YAML
   const servicesYaml = yaml.dump(config.services, { flowLevel: -1 });
@@ -9,6 +9,7 @@ import * as pulumi from "@pulumi/pulumi";
 import * as fs from "fs";
 import * as path from "path";
 import { isValidSlug } from "./constants";
+import { DEFAULT_NETWORK_SUBNET, validateNetworkSubnet } from "./network";
 
 /**
  * Read portal version from Cargo.toml
@@ -94,6 +95,12 @@ export interface DeploymentConfig {
    * - false: Use production start with strict hostname validation (default)
    */
   keycloakDevMode: boolean;
+  /**
+   * IPv4 subnet of the deployment's Docker network (default 172.30.0.0/24)
+   * Traefik gets a static address in it, which the portal trusts as proxy.
+   * Deployments sharing a Docker host need distinct subnets.
+   */
+  networkSubnet: string;
 }
 
 /**
@@ -133,6 +140,10 @@ export function getConfig(): DeploymentConfig {
   }
   const buildPlatform: BuildPlatform = buildPlatformRaw;
 
+  // Docker network subnet (Traefik's static address must fit in it)
+  const networkSubnet = config.get("networkSubnet") ?? DEFAULT_NETWORK_SUBNET;
+  validateNetworkSubnet(networkSubnet);
+
   // ACME configuration (required when useHttps=true)
   const acmeEmail = config.get("acmeEmail");
   const acmeStaging = config.getBoolean("acmeStaging") ?? false;
@@ -170,6 +181,7 @@ export function getConfig(): DeploymentConfig {
     acme,
     descriptorInjection,
     buildPlatform,
+    networkSubnet,
   };
 }
 
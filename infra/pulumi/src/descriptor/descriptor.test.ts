@@ -14,6 +14,10 @@ import {
   serializeDescriptor,
   PortalDescriptor,
   getDeploymentInfoFromEnv,
+  descriptorPublicKey,
+  signDescriptor,
+  serializeSignedEnvelope,
+  DESCRIPTOR_SIGNATURE_BASE64_LENGTH,
 } from "./index";
 import { DeploymentConfig } from "../config";
 import { PortalService } from "../types";
@@ -376,6 +380,45 @@ describe("serializeDescriptor", () => {
   });
 });
 
+describe("descriptor signing", () => {
+  // RFC 8032 Ed25519 test vector 1
+  const seed = Buffer.from(
+    "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+    "hex"
+  ).toString("base64");
+
+  it("derives the public key the portal verifies with", () => {
+    expect(Buffer.from(descriptorPublicKey(seed), "base64").toString("hex")).toBe(
+      "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+    );
+  });
+
+  it("produces standard Ed25519 signatures", () => {
+    const signature = signDescriptor("", seed);
+    expect(signature).toHaveLength(DESCRIPTOR_SIGNATURE_BASE64_LENGTH);
+    expect(Buffer.from(signature, "base64").toString("hex")).toBe(
+      "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+    );
+  });
+
+  it("wraps the exact signed text in an envelope", () => {
+    const payload = serializeDescriptor(generateDescriptor(testConfig, []));
+    const envelope = JSON.parse(
+      serializeSignedEnvelope(payload, signDescriptor(payload, seed))
+    ) as { payload: string; signature: string };
+
+    expect(Object.keys(envelope)).toEqual(["payload", "signature"]);
+    expect(envelope.payload).toBe(payload);
+    expect(envelope.signature).toBe(signDescriptor(payload, seed));
+  });
+
+  it("rejects keys that are not 32 bytes", () => {
+    expect(() => signDescriptor("", Buffer.alloc(16).toString("base64"))).toThrow(
+      /must be 32 bytes/
+    );
+  });
+});
+
 describe("integration: full descriptor generation", () => {
   it("generates a complete, valid descriptor", () => {
     const services: PortalService[] = [
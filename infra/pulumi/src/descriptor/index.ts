@@ -12,6 +12,7 @@
  */
 
 import Ajv2020, { AnySchema } from "ajv/dist/2020";
+import * as crypto from "crypto";
 import * as fs from "fs";
 import * as path from "path";
 import { DeploymentConfig, buildUrl } from "../config";
@@ -254,3 +255,58 @@ export function serializeDescriptor(descriptor: PortalDescriptor): string {
 export function serializeDescriptorMinified(descriptor: PortalDescriptor): string {
   return JSON.stringify(descriptor);
 }
+
+// =============================================================================
+// SIGNING
+// =============================================================================
+
+/** PKCS#8 DER prefix of an Ed25519 private key; the 32-byte seed follows */
+const ED25519_PKCS8_PREFIX = Buffer.from("302e020100300506032b657004220420", "hex");
+
+/** Length of a base64-encoded Ed25519 signature (64 bytes) */
+export const DESCRIPTOR_SIGNATURE_BASE64_LENGTH = 88;
+
+/**
+ * Ed25519 key from a base64-encoded 32-byte seed
+ *
+ * Same key format as `generate-types sign-descriptor --generate-key`.
+ */
+function descriptorSigningKey(seedBase64: string): crypto.KeyObject {
+  const seed = Buffer.from(seedBase64, "base64");
+  if (seed.length !== 32) {
+    throw new Error(`Descriptor signing key must be 32 bytes, got ${String(seed.length)}`);
+  }
+  return crypto.createPrivateKey({
+    key: Buffer.concat([ED25519_PKCS8_PREFIX, seed]),
+    format: "der",
+    type: "pkcs8",
+  });
+}
+
+/**
+ * Base64 public key for PORTAL_DESCRIPTOR_PUBLIC_KEY
+ */
+export function descriptorPublicKey(seedBase64: string): string {
+  const spki = crypto
+    .createPublicKey(descriptorSigningKey(seedBase64))
+    .export({ format: "der", type: "spki" });
+  // The raw 32-byte key ends the SubjectPublicKeyInfo
+  return spki.subarray(spki.length - 32).toString("base64");
+}
+
+/**
+ * Base64 Ed25519 signature of the exact descriptor text
+ * (the content of a detached `<descriptor>.sig` file)
+ */
+export function signDescriptor(payload: string, seedBase64: string): string {
+  return crypto
+    .sign(null, Buffer.from(payload, "utf-8"), descriptorSigningKey(seedBase64))
+    .toString("base64");
+}
+
+/**
+ * Signed envelope `{"payload": ..., "signature": ...}` for PORTAL_DESCRIPTOR_JSON
+ */
+export function serializeSignedEnvelope(payload: string, signature: string): string {
+  return JSON.stringify({ payload, signature });
+}
@@ -294,6 +294,7 @@ const portal = createPortal({
   keycloakInternalUrl: keycloak.internalUrl,
   clientId: "portal",
   clientSecret: generatedSecrets.portalClientSecret,
+  descriptorSigningKey: generatedSecrets.descriptorSigningKey,
   image: portalImage.imageName,
 });
 
@@ -0,0 +1,25 @@
+/**
+ * Tests for the deployment network addressing
+ */
+
+import { describe, it, expect } from "vitest";
+import { DEFAULT_NETWORK_SUBNET, subnetHostAddress, validateNetworkSubnet } from "./network";
+
+describe("subnetHostAddress", () => {
+  it("returns the host's address in the subnet", () => {
+    expect(subnetHostAddress(DEFAULT_NETWORK_SUBNET, 2)).toBe("172.30.0.2");
+    expect(subnetHostAddress("10.8.0.0/16", 300)).toBe("10.8.1.44");
+  });
+
+  it("rejects invalid subnets", () => {
+    expect(() => subnetHostAddress("172.30.0.0", 2)).toThrow("IPv4 CIDR");
+    expect(() => subnetHostAddress("fd00::/64", 2)).toThrow("IPv4 CIDR");
+    expect(() => subnetHostAddress("172.30.0.256/24", 2)).toThrow("IPv4 CIDR");
+    expect(() => subnetHostAddress("172.30.0.1/24", 2)).toThrow("host bits");
+  });
+
+  it("keeps the network and broadcast addresses free", () => {
+    expect(() => subnetHostAddress("172.30.0.0/31", 2)).toThrow("too small");
+    expect(() => validateNetworkSubnet("172.30.0.0/30")).not.toThrow();
+  });
+});
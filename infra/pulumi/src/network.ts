@@ -3,12 +3,22 @@
  *
  * Creates a bridge network for all containers in the deployment.
  * All services communicate over this network using container names as hostnames.
+ *
+ * The network has a fixed subnet (config.networkSubnet) so Traefik can get a
+ * static address: the portal trusts X-Forwarded-For from that address only
+ * (PORTAL_TRUSTED_PROXIES), not from every container on the network.
  */
 
 import * as docker from "@pulumi/docker";
 import { DeploymentConfig } from "./config";
 import { networkName } from "./types";
 
+/** Default subnet of the deployment network */
+export const DEFAULT_NETWORK_SUBNET = "172.30.0.0/24";
+
+/** Host number of Traefik in the subnet (1 is Docker's gateway) */
+const TRAEFIK_HOST = 2;
+
 export interface NetworkResources {
   network: docker.Network;
 }
@@ -21,7 +31,55 @@ export function createNetwork(config: DeploymentConfig): NetworkResources {
   const network = new docker.Network(name, {
     name,
     driver: "bridge",
+    ipamConfigs: [{ subnet: config.networkSubnet }],
+  }, {
+    // The name is fixed, so a subnet change must remove the old network
+    // (and the containers on it) before creating the new one
+    deleteBeforeReplace: true,
   });
 
   return { network };
 }
+
+/**
+ * Address of host number `host` in an IPv4 subnet ("a.b.c.d/prefix")
+ *
+ * @throws Error if the subnet is not a valid IPv4 CIDR or too small for the host
+ */
+export function subnetHostAddress(subnet: string, host: number): string {
+  const match = /^(\d{1,3})\.(\d{1,3})\.(\d{1,3})\.(\d{1,3})\/(\d{1,2})$/.exec(subnet);
+  const octets = match ? match.slice(1, 5).map(Number) : [];
+  const prefix = match ? Number(match[5]) : NaN;
+  if (!match || octets.some((o) => o > 255) || prefix > 32) {
+    throw new Error(`Invalid subnet "${subnet}": must be an IPv4 CIDR like ${DEFAULT_NETWORK_SUBNET}`);
+  }
+
+  const base = octets.reduce((acc, octet) => acc * 256 + octet, 0);
+  const size = 2 ** (32 - prefix);
+  if (base % size !== 0) {
+    throw new Error(`Invalid subnet "${subnet}": host bits must be zero`);
+  }
+  // Keep the network and broadcast addresses free
+  if (host < 1 || host >= size - 1) {
+    throw new Error(`Invalid subnet "${subnet}": too small for host ${String(host)}`);
+  }
+
+  const address = base + host;
+  return [24, 16, 8, 0].map((shift) => String(Math.floor(address / 2 ** shift) % 256)).join(".");
+}
+
+/**
+ * Check that a subnet is a valid IPv4 CIDR with room for Traefik's address
+ *
+ * @throws Error if it is not
+ */
+export function validateNetworkSubnet(subnet: string): void {
+  subnetHostAddress(subnet, TRAEFIK_HOST);
+}
+
+/**
+ * Static address of the Traefik container in the deployment network
+ */
+export function traefikAddress(config: DeploymentConfig): string {
+  return subnetHostAddress(config.networkSubnet, TRAEFIK_HOST);
+}
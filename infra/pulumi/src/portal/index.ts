@@ -7,16 +7,30 @@
  * - JSON env var injection (PORTAL_DESCRIPTOR_JSON) - for small descriptors only
  *
  * The injection method is controlled by config.descriptorInjection.
+ *
+ * The descriptor is signed with the deployment's descriptor signing key
+ * (detached `.sig` file for file injection, signed envelope for JSON
+ * injection) and the portal gets the public key, as production requires.
+ *
+ * Requests reach the portal through Traefik, so Traefik's static address is
+ * passed as PORTAL_TRUSTED_PROXIES: rate limits and the audit log then see
+ * the client address from X-Forwarded-For instead of Traefik's. Other
+ * containers on the network are not trusted to set that header.
  */
 
 import * as docker from "@pulumi/docker";
 import * as pulumi from "@pulumi/pulumi";
 import { DeploymentConfig, buildUrl, DESCRIPTOR_JSON_MAX_SIZE, getPortalVersion } from "../config";
 import {
+  DESCRIPTOR_SIGNATURE_BASE64_LENGTH,
   PortalDescriptor,
+  descriptorPublicKey,
   serializeDescriptor,
   serializeDescriptorMinified,
+  serializeSignedEnvelope,
+  signDescriptor,
 } from "../descriptor/index";
+import { traefikAddress } from "../network";
 import { createContainer, ContainerIdentity, shortName } from "../types";
 import * as path from "path";
 
@@ -36,6 +50,8 @@ export interface PortalInputs {
   clientId: string;
   /** Portal OAuth2 client secret */
   clientSecret: pulumi.Input<string>;
+  /** Descriptor signing key (base64 Ed25519 seed) */
+  descriptorSigningKey: pulumi.Input<string>;
   /** Portal image name (required - built by images module) */
   image: pulumi.Input<string>;
 }
@@ -60,6 +76,7 @@ export function createPortal(inputs: PortalInputs): PortalResources {
     keycloakInternalUrl,
     clientId,
     clientSecret,
+    descriptorSigningKey,
     image,
   } = inputs;
 
@@ -71,9 +88,14 @@ export function createPortal(inputs: PortalInputs): PortalResources {
   const descriptorJsonPretty = serializeDescriptor(descriptor);
   const descriptorJsonMinified = serializeDescriptorMinified(descriptor);
 
-  // Enforce 64KB size guard for JSON injection (per plan.md)
+  // Enforce 64KB size guard for JSON injection (per plan.md), on the signed
+  // envelope the portal receives (the signature length is fixed)
   if (config.descriptorInjection === "json") {
-    const descriptorSize = Buffer.byteLength(descriptorJsonMinified, "utf-8");
+    const envelope = serializeSignedEnvelope(
+      descriptorJsonMinified,
+      "A".repeat(DESCRIPTOR_SIGNATURE_BASE64_LENGTH)
+    );
+    const descriptorSize = Buffer.byteLength(envelope, "utf-8");
     if (descriptorSize > DESCRIPTOR_JSON_MAX_SIZE) {
       throw new Error(
         `Descriptor size (${String(descriptorSize)} bytes) exceeds maximum for JSON injection ` +
@@ -83,7 +105,7 @@ export function createPortal(inputs: PortalInputs): PortalResources {
   }
 
   // Build environment variables based on injection method
-  const envs = pulumi.all([clientSecret]).apply(([secret]) => {
+  const envs = pulumi.all([clientSecret, descriptorSigningKey]).apply(([secret, signingKey]) => {
     const baseEnvs = [
       // Environment
       config.environment === "prod"
@@ -114,12 +136,24 @@ export function createPortal(inputs: PortalInputs): PortalResources {
       // The portal probes services through Traefik using Host headers since
       // public URLs (e.g., dozzle.localhost) are not resolvable inside Docker.
       `TRAEFIK_INTERNAL_URL=http://${shortName(config.deploymentId, "traefik")}:80`,
+
+      // Descriptors must be signed with the deployment's signing key
+      `PORTAL_DESCRIPTOR_PUBLIC_KEY=${descriptorPublicKey(signingKey)}`,
+
+      // Trust X-Forwarded-For from Traefik only; without it every client
+      // would share Traefik's rate limit budget
+      `PORTAL_TRUSTED_PROXIES=${traefikAddress(config)}/32`,
     ];
 
     // Add descriptor injection env var based on method
     if (config.descriptorInjection === "json") {
-      // JSON injection via environment variable (minified to avoid quoting issues)
-      baseEnvs.push(`PORTAL_DESCRIPTOR_JSON=${descriptorJsonMinified}`);
+      // JSON injection via environment variable (minified to avoid quoting
+      // issues), as a signed envelope
+      const envelope = serializeSignedEnvelope(
+        descriptorJsonMinified,
+        signDescriptor(descriptorJsonMinified, signingKey)
+      );
+      baseEnvs.push(`PORTAL_DESCRIPTOR_JSON=${envelope}`);
     } else {
       // File injection via path (default, preferred)
       baseEnvs.push(`PORTAL_DESCRIPTOR_PATH=${DESCRIPTOR_CONTAINER_PATH}`);
@@ -129,7 +163,8 @@ export function createPortal(inputs: PortalInputs): PortalResources {
   });
 
   // Build uploads array - only include descriptor file for file injection
-  // Use pretty-printed JSON for file injection (easier to debug/inspect)
+  // Use pretty-printed JSON for file injection (easier to debug/inspect),
+  // with its detached signature next to it
   const uploads: docker.types.input.ContainerUpload[] =
     config.descriptorInjection === "file"
       ? [
@@ -137,6 +172,12 @@ export function createPortal(inputs: PortalInputs): PortalResources {
             file: DESCRIPTOR_CONTAINER_PATH,
             content: descriptorJsonPretty,
           },
+          {
+            file: `${DESCRIPTOR_CONTAINER_PATH}.sig`,
+            content: pulumi
+              .output(descriptorSigningKey)
+              .apply((signingKey) => signDescriptor(descriptorJsonPretty, signingKey)),
+          },
         ]
       : [];
 
@@ -156,6 +197,9 @@ export function createPortal(inputs: PortalInputs): PortalResources {
       // Upload descriptor directly into container (only for file injection)
       uploads: uploads.length > 0 ? uploads : undefined,
       restart: "unless-stopped",
+      // Graceful shutdown: drain (5s) + in-flight requests (20s) must fit
+      // before Docker sends SIGKILL
+      stopTimeout: 30,
       healthcheck: {
         // Use /readyz for readiness: checks JWKS cache is populated (Keycloak reachable)
         // This ensures container is only "healthy" when it can authenticate users
@@ -57,6 +57,21 @@ export function generateCookieSecret(name: string): pulumi.Output<string> {
   return secret.result;
 }
 
+/**
+ * Generate an Ed25519 descriptor signing key (base64-encoded 32-byte seed)
+ *
+ * The portal only accepts descriptors signed with this key, so Pulumi is
+ * the trusted signer. Rotating it re-signs the descriptor and updates
+ * PORTAL_DESCRIPTOR_PUBLIC_KEY in the same deploy.
+ */
+export function generateDescriptorSigningKey(name: string): pulumi.Output<string> {
+  const seed = new random.RandomBytes(name, {
+    length: 32,
+  });
+
+  return seed.base64;
+}
+
 /**
  * Generated secrets for the deployment
  */
@@ -67,6 +82,8 @@ export interface GeneratedSecrets {
   oauth2ProxyCookieSecret: pulumi.Output<string>;
   /** Per-service oauth2-proxy client secrets (keyed by serviceId) */
   serviceClientSecrets: Record<string, pulumi.Output<string>>;
+  /** Descriptor signing key (base64 Ed25519 seed) */
+  descriptorSigningKey: pulumi.Output<string>;
 }
 
 /**
@@ -96,9 +113,15 @@ export function generateDeploymentSecrets(
     );
   }
 
+  // Descriptor signing key (production refuses unsigned descriptors)
+  const descriptorSigningKey = generateDescriptorSigningKey(
+    `${deploymentId}-descriptor-signing-key`
+  );
+
   return {
     portalClientSecret,
     oauth2ProxyCookieSecret,
     serviceClientSecrets,
+    descriptorSigningKey,
   };
 }
@@ -11,6 +11,7 @@ import * as docker from "@pulumi/docker";
 import * as pulumi from "@pulumi/pulumi";
 import { DeploymentConfig } from "../config";
 import { RouteRequest, volumeName, createContainer, ContainerIdentity } from "../types";
+import { traefikAddress } from "../network";
 import { generateTraefikConfigYaml } from "./dynamic-config";
 
 /** Traefik version (from image tag) */
@@ -152,6 +153,8 @@ export function createTraefik(inputs: TraefikInputs): TraefikResources {
     identity,
     {
       network,
+      // Static, so the portal can trust forwarded headers from Traefik alone
+      ipv4Address: traefikAddress(config),
       image: `traefik:${TRAEFIK_VERSION}`,
       command: staticConfig,
       ports,
@@ -191,6 +191,8 @@ export interface CreateContainerArgs extends Omit<docker.ContainerArgs, "name" |
   network: docker.Network;
   /** Additional network aliases (optional, container name is always added) */
   additionalAliases?: string[];
+  /** Static IPv4 address in the network (optional, Docker assigns one otherwise) */
+  ipv4Address?: string;
 }
 
 /**
@@ -214,7 +216,7 @@ export function createContainer(
 ): docker.Container {
   const fullName = nameWithVersion(identity);
   const stableAlias = shortName(identity.deploymentId, identity.serviceId);
-  const { network, additionalAliases, ...containerArgs } = args;
+  const { network, additionalAliases, ipv4Address, ...containerArgs } = args;
 
   // Build network aliases:
   // - stableAlias (shortName, without version) for Traefik routing
@@ -231,6 +233,7 @@ export function createContainer(
         {
           name: network.name,
           aliases,
+          ipv4Address,
         },
       ],
     },
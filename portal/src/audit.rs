@@ -0,0 +1,428 @@
+//! Security audit log (PORTAL_AUDIT_LOG_PATH)
+//!
+//! Security-relevant events go to a dedicated append-only file, one JSON
+//! object per line, separate from the `tracing` application logs. The schema
+//! is stable: every line has `timestamp` (RFC 3339, UTC) and `event`; the
+//! other fields are omitted when not known.
+//!
+//! With `PORTAL_AUDIT_LOG_MAX_BYTES` set, the file is rotated before it would
+//! grow past that size (`audit.log` → `audit.log.1` → ... →
+//! `audit.log.<PORTAL_AUDIT_LOG_MAX_FILES>`, the oldest is dropped).
+//!
+//! Writing never fails a request: errors are reported through `tracing`.
+
+use anyhow::{Context, Result};
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::auth::tokens::format_unix_date;
+use crate::rate_limit::ClientIp;
+use crate::request_id::RequestId;
+
+/// Audit event types (the `event` field)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    /// Login callback completed, session cookies set
+    Login,
+    /// Login callback failed (`reason` says why)
+    LoginFailed,
+    /// A presented token failed validation (`token_hash` identifies it)
+    TokenRejected,
+    /// Logout started
+    Logout,
+    /// An authenticated request was refused
+    AccessDenied,
+    /// Signed out of a single oauth2-proxy service (`target` = service id)
+    ServiceSignOut,
+    /// Personal access token minted (`target` = token id)
+    TokenMinted,
+    /// Personal access token revoked (`target` = token id)
+    TokenRevoked,
+    /// Device code approved on the portal (`target` = user code)
+    DeviceApproved,
+    /// Device code denied on the portal (`target` = user code)
+    DeviceDenied,
+}
+
+/// Where a request came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditContext {
+    /// Client address resolved with PORTAL_TRUSTED_PROXIES (see
+    /// [`crate::rate_limit`]), else the peer address
+    pub client_ip: Option<String>,
+    /// Request ID (see [`crate::request_id`])
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    pub fn from_parts(parts: &Parts) -> Self {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        // Forwarded headers are only believed from trusted proxies, which
+        // the rate limit middleware checked when resolving `ClientIp`
+        let client_ip = match parts.extensions.get::<ClientIp>() {
+            Some(ClientIp(ip)) => Some(ip.to_string()),
+            None => parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string()),
+        };
+
+        Self {
+            client_ip,
+            request_id: parts
+                .extensions
+                .get::<RequestId>()
+                .map(|id| id.0.clone())
+                .or_else(|| header("x-request-id")),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
+/// One audit log line
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: String,
+    pub event: AuditEventType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// Identity provider id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Why a login, token or request was refused
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// MD5 of a rejected token (same as `token_hash` in application logs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_hash: Option<String>,
+    /// Object of the action (service id, token id, user code, path)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(event: AuditEventType, context: &AuditContext) -> Self {
+        Self {
+            timestamp: format_timestamp(SystemTime::now()),
+            event,
+            sub: None,
+            provider: None,
+            client_ip: context.client_ip.clone(),
+            request_id: context.request_id.clone(),
+            reason: None,
+            token_hash: None,
+            target: None,
+        }
+    }
+
+    pub fn provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
+    }
+
+    pub fn user(mut self, provider: &str, sub: &str) -> Self {
+        self.sub = Some(sub.to_string());
+        self.provider(provider)
+    }
+
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Identify a rejected token by its MD5, never the token itself
+    pub fn token(mut self, token: &str) -> Self {
+        self.token_hash = Some(token_hash(token));
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+}
+
+/// MD5 hex of a token, as logged by the JWT validator
+pub fn token_hash(token: &str) -> String {
+    format!("{:x}", md5::compute(token))
+}
+
+struct AuditFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// Rotate before the file would grow past this (None = never)
+    max_bytes: Option<u64>,
+    /// Rotated files kept next to the live one
+    max_files: usize,
+}
+
+impl AuditFile {
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if let Some(max_bytes) = self.max_bytes {
+            if self.size > 0 && self.size + line.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    std::fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Append-only audit log (a no-op unless configured)
+#[derive(Default)]
+pub struct AuditLog {
+    file: Option<Mutex<AuditFile>>,
+}
+
+impl AuditLog {
+    /// Open (or create) the audit log file
+    ///
+    /// # Arguments
+    /// * `path` - Log file, appended to
+    /// * `max_bytes` - Rotation threshold (None = no rotation)
+    /// * `max_files` - Rotated files to keep
+    pub fn open(
+        path: impl Into<PathBuf>,
+        max_bytes: Option<u64>,
+        max_files: usize,
+    ) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file =
+            open_append(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        tracing::info!(
+            path = %path.display(),
+            max_bytes = ?max_bytes,
+            max_files = max_files,
+            "Audit log opened"
+        );
+        Ok(Self {
+            file: Some(Mutex::new(AuditFile {
+                path,
+                file,
+                size,
+                max_bytes,
+                max_files,
+            })),
+        })
+    }
+
+    /// Append an event
+    pub fn record(&self, event: AuditEvent) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut line = match serde_json::to_vec(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to serialize audit event");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = file.write_line(&line) {
+            tracing::error!(
+                error = %e,
+                path = %file.path.display(),
+                event = ?event.event,
+                "Failed to write audit event"
+            );
+        }
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    path.with_file_name(name)
+}
+
+/// RFC 3339 UTC timestamp with milliseconds (e.g. `2026-02-02T15:30:00.123Z`)
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        format_unix_date(secs),
+        secs % 86_400 / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use std::time::Duration;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("test_audit_{}.log", name));
+        for index in 0..4 {
+            std::fs::remove_file(rotated_path(&path, index)).ok();
+        }
+        std::fs::remove_file(&path).ok();
+        path
+    }
+
+    #[test]
+    fn test_writes_json_lines() {
+        let path = temp_log("lines");
+        let log = AuditLog::open(&path, None, 0).unwrap();
+        let context = AuditContext {
+            client_ip: Some("203.0.113.7".to_string()),
+            request_id: Some("req-1".to_string()),
+        };
+        log.record(AuditEvent::new(AuditEventType::Login, &context).user("default", "alice"));
+        log.record(
+            AuditEvent::new(AuditEventType::TokenRejected, &AuditContext::default())
+                .reason("expired")
+                .token("abc"),
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "login");
+        assert_eq!(lines[0]["sub"], "alice");
+        assert_eq!(lines[0]["provider"], "default");
+        assert_eq!(lines[0]["client_ip"], "203.0.113.7");
+        assert_eq!(lines[0]["request_id"], "req-1");
+        assert_eq!(lines[1]["event"], "token_rejected");
+        assert_eq!(lines[1]["token_hash"], "900150983cd24fb0d6963f7d28e17f72");
+        assert!(lines[1].get("sub").is_none());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rotates_by_size() {
+        let path = temp_log("rotate");
+        let log = AuditLog::open(&path, Some(100), 2).unwrap();
+        for _ in 0..10 {
+            log.record(AuditEvent::new(
+                AuditEventType::Logout,
+                &AuditContext::default(),
+            ));
+        }
+
+        assert!(std::fs::metadata(&path).unwrap().len() <= 100);
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        for index in 0..3 {
+            std::fs::remove_file(rotated_path(&path, index)).ok();
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_context_uses_resolved_client_ip() {
+        let (mut parts, _) = Request::builder()
+            .header("x-real-ip", "203.0.113.7")
+            .header("x-request-id", "req-1")
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+            .extensions
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 443))));
+        // Proxy headers alone are not believed
+        assert_eq!(
+            AuditContext::from_parts(&parts).client_ip.as_deref(),
+            Some("10.0.0.2")
+        );
+
+        parts
+            .extensions
+            .insert(ClientIp("198.51.100.4".parse().unwrap()));
+        assert_eq!(
+            AuditContext::from_parts(&parts).client_ip.as_deref(),
+            Some("198.51.100.4")
+        );
+
+        parts.headers.clear();
+        parts.extensions.remove::<ClientIp>();
+        assert_eq!(
+            AuditContext::from_parts(&parts),
+            AuditContext {
+                client_ip: Some("10.0.0.2".to_string()),
+                request_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(1_770_046_200_123)),
+            "2026-02-02T15:30:00.123Z"
+        );
+    }
+}
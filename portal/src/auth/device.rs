@@ -0,0 +1,814 @@
+//! OAuth 2.0 device authorization grant (RFC 8628), brokered by the portal
+//!
+//! Lets CLIs on headless machines sign in without a local browser:
+//!
+//! 1. The CLI posts to `/auth/device/code`; the portal starts a device
+//!    authorization at Keycloak and answers with its own `device_code` and
+//!    Keycloak's `user_code`, pointing `verification_uri` at `/auth/device`.
+//! 2. The user opens `/auth/device` in a browser where they are signed in to
+//!    the portal and approves the code, then confirms it at Keycloak (already
+//!    signed in, so this is a consent click).
+//! 3. The CLI polls `/auth/device/token` and receives the access token.
+//!
+//! Keycloak's device code never leaves the portal, which polls Keycloak in
+//! the background once the user approved. The token is only handed out if it
+//! belongs to the user who approved the code on the portal.
+
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use oauth2::{
+    DeviceCodeErrorResponseType, RequestTokenError, StandardDeviceAuthorizationResponse,
+    TokenResponse,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+use super::handlers::{create_oauth_client, ConfiguredOAuthClient};
+use super::helpers::create_http_client;
+use super::providers::IdentityProviders;
+use crate::shutdown::Shutdown;
+use crate::telemetry::TracedHttpClient;
+
+/// Grant type CLIs send to `/auth/device/token`
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Upper bound on concurrently pending device authorizations
+///
+/// `/auth/device/code` is unauthenticated; this keeps the map bounded.
+const MAX_PENDING_DEVICES: usize = 1000;
+
+// =============================================================================
+// Types
+// =============================================================================
+
+/// Device authorization response for the CLI (RFC 8628 section 3.2)
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+/// Token handed to the CLI once the user approved
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceToken {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: u64,
+}
+
+/// Token endpoint errors (RFC 8628 section 3.5)
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceError {
+    AuthorizationPending,
+    SlowDown,
+    AccessDenied,
+    ExpiredToken,
+    /// Unknown device code
+    InvalidGrant,
+    UnsupportedGrantType,
+    /// Too many pending device authorizations; the CLI should retry later
+    TemporarilyUnavailable,
+    /// Keycloak could not be reached or refused the request
+    Upstream(String),
+}
+
+impl DeviceError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DeviceError::AuthorizationPending => "authorization_pending",
+            DeviceError::SlowDown => "slow_down",
+            DeviceError::AccessDenied => "access_denied",
+            DeviceError::ExpiredToken => "expired_token",
+            DeviceError::InvalidGrant => "invalid_grant",
+            DeviceError::UnsupportedGrantType => "unsupported_grant_type",
+            DeviceError::TemporarilyUnavailable => "temporarily_unavailable",
+            DeviceError::Upstream(_) => "server_error",
+        }
+    }
+}
+
+impl IntoResponse for DeviceError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            DeviceError::TemporarilyUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            DeviceError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(serde_json::json!({ "error": self.code() }))).into_response()
+    }
+}
+
+/// Where a pending device authorization stands
+enum DeviceState {
+    /// Waiting for the user to approve the code on the portal
+    AwaitingApproval,
+    /// Approved on the portal, polling Keycloak in the background
+    Approved,
+    /// Handed to the CLI on its next poll
+    Done(Result<DeviceToken, DeviceError>),
+}
+
+struct PendingDevice {
+    provider: String,
+    /// Keycloak's response, including its device code
+    keycloak: StandardDeviceAuthorizationResponse,
+    expires_at: Instant,
+    last_poll: Option<Instant>,
+    state: DeviceState,
+}
+
+impl PendingDevice {
+    fn user_code(&self) -> &str {
+        self.keycloak.user_code().secret()
+    }
+
+    fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+}
+
+/// The user approving or denying a code on the portal
+pub struct Approver<'a> {
+    pub provider: &'a str,
+    pub sub: &'a str,
+}
+
+/// Pending device authorizations, keyed by the portal's device code
+#[derive(Default)]
+pub struct DeviceFlows {
+    devices: Mutex<HashMap<String, PendingDevice>>,
+}
+
+// =============================================================================
+// Device Flows
+// =============================================================================
+
+impl DeviceFlows {
+    /// Start a device authorization at the provider's Keycloak realm
+    ///
+    /// # Arguments
+    /// * `provider` - Identity provider id the user has to sign in with
+    /// * `client` - OAuth client of that provider
+    /// * `http_client` - Client for the Keycloak request
+    /// * `verification_uri` - Portal page where the user approves the code
+    pub async fn start(
+        &self,
+        provider: &str,
+        client: &ConfiguredOAuthClient,
+        http_client: &reqwest::Client,
+        verification_uri: &str,
+    ) -> Result<DeviceAuthorization, DeviceError> {
+        {
+            let mut devices = self.devices.lock().unwrap();
+            devices.retain(|_, device| !device.is_expired());
+            if devices.len() >= MAX_PENDING_DEVICES {
+                tracing::warn!(
+                    pending = devices.len(),
+                    "Too many pending device authorizations"
+                );
+                return Err(DeviceError::TemporarilyUnavailable);
+            }
+        }
+
+        let keycloak: StandardDeviceAuthorizationResponse = client
+            .exchange_device_code()
+            .request_async(&TracedHttpClient(http_client))
+            .await
+            .map_err(|e| {
+                tracing::error!(provider = %provider, error = %e, "Device authorization request failed");
+                DeviceError::Upstream(e.to_string())
+            })?;
+
+        let device_code = random_code();
+        let user_code = keycloak.user_code().secret().clone();
+        let authorization = DeviceAuthorization {
+            device_code: device_code.clone(),
+            verification_uri_complete: format!(
+                "{}?user_code={}",
+                verification_uri,
+                urlencoding::encode(&user_code)
+            ),
+            verification_uri: verification_uri.to_string(),
+            user_code,
+            expires_in: keycloak.expires_in().as_secs(),
+            interval: keycloak.interval().as_secs(),
+        };
+
+        tracing::info!(
+            event = "device_authorization_started",
+            provider = %provider,
+            user_code = %authorization.user_code,
+            expires_in = authorization.expires_in,
+            "Device authorization started"
+        );
+
+        self.devices.lock().unwrap().insert(
+            device_code,
+            PendingDevice {
+                provider: provider.to_string(),
+                expires_at: Instant::now() + keycloak.expires_in(),
+                keycloak,
+                last_poll: None,
+                state: DeviceState::AwaitingApproval,
+            },
+        );
+        Ok(authorization)
+    }
+
+    /// Approve a code and start polling Keycloak for the token
+    ///
+    /// The poll runs as a background task of `shutdown`, so it stops with the
+    /// portal instead of outliving the graceful shutdown. Returns the Keycloak page where the user confirms the code, or a
+    /// message for the user if the code cannot be approved.
+    pub fn approve(
+        self: &Arc<Self>,
+        user_code: &str,
+        approver: Approver<'_>,
+        client: ConfiguredOAuthClient,
+        http_client: reqwest::Client,
+        identity_providers: Arc<IdentityProviders>,
+        shutdown: &Shutdown,
+    ) -> Result<String, String> {
+        let mut devices = self.devices.lock().unwrap();
+        let (device_code, device) = awaiting_decision(&mut devices, user_code, &approver)?;
+
+        device.state = DeviceState::Approved;
+        let confirm_url = device
+            .keycloak
+            .verification_uri_complete()
+            .map(|uri| uri.secret().clone())
+            .unwrap_or_else(|| device.keycloak.verification_uri().to_string());
+
+        tracing::info!(
+            event = "device_authorization_approved",
+            provider = %approver.provider,
+            sub = %approver.sub,
+            user_code = %device.user_code(),
+            "Device authorization approved on the portal"
+        );
+
+        let flows = self.clone();
+        let device_code = device_code.clone();
+        let keycloak = device.keycloak.clone();
+        let timeout = device.expires_at.saturating_duration_since(Instant::now());
+        let provider = approver.provider.to_string();
+        let sub = approver.sub.to_string();
+        // Child of the approving request's span, so the poll joins its trace
+        let span = tracing::info_span!("device_token_exchange", provider = %provider);
+        shutdown.spawn(
+            async move {
+                let result = fetch_token(
+                    &keycloak,
+                    &client,
+                    &http_client,
+                    timeout,
+                    &identity_providers,
+                    &provider,
+                    &sub,
+                )
+                .await;
+                if let Some(device) = flows.devices.lock().unwrap().get_mut(&device_code) {
+                    device.state = DeviceState::Done(result);
+                }
+            }
+            .instrument(span),
+        );
+
+        Ok(confirm_url)
+    }
+
+    /// Deny a code; the CLI gets `access_denied` on its next poll
+    ///
+    /// Only a user of the code's identity provider may deny it, like for
+    /// [`DeviceFlows::approve`]. Returns a message for the user otherwise.
+    pub fn deny(&self, user_code: &str, approver: Approver<'_>) -> Result<(), String> {
+        let mut devices = self.devices.lock().unwrap();
+        let (_, device) = awaiting_decision(&mut devices, user_code, &approver)?;
+
+        tracing::info!(
+            event = "device_authorization_denied",
+            provider = %approver.provider,
+            sub = %approver.sub,
+            user_code = %device.user_code(),
+            "Device authorization denied on the portal"
+        );
+        device.state = DeviceState::Done(Err(DeviceError::AccessDenied));
+        Ok(())
+    }
+
+    /// Poll for the token (RFC 8628 section 3.4)
+    pub fn poll(&self, device_code: &str) -> Result<DeviceToken, DeviceError> {
+        let mut devices = self.devices.lock().unwrap();
+        let device = devices
+            .get_mut(device_code)
+            .ok_or(DeviceError::InvalidGrant)?;
+
+        if device.is_expired() {
+            devices.remove(device_code);
+            return Err(DeviceError::ExpiredToken);
+        }
+
+        let now = Instant::now();
+        let too_fast = device
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < device.keycloak.interval());
+        device.last_poll = Some(now);
+
+        match &device.state {
+            DeviceState::Done(_) => match devices.remove(device_code).map(|d| d.state) {
+                Some(DeviceState::Done(result)) => result,
+                _ => Err(DeviceError::InvalidGrant),
+            },
+            _ if too_fast => Err(DeviceError::SlowDown),
+            _ => Err(DeviceError::AuthorizationPending),
+        }
+    }
+}
+
+/// Find a pending code the approver may approve or deny
+fn awaiting_decision<'a>(
+    devices: &'a mut HashMap<String, PendingDevice>,
+    user_code: &str,
+    approver: &Approver<'_>,
+) -> Result<(&'a String, &'a mut PendingDevice), String> {
+    let (device_code, device) = devices
+        .iter_mut()
+        .find(|(_, device)| codes_match(device.user_code(), user_code))
+        .filter(|(_, device)| !device.is_expired())
+        .ok_or_else(|| "Unknown or expired code".to_string())?;
+
+    if !matches!(device.state, DeviceState::AwaitingApproval) {
+        return Err("This code has already been used".to_string());
+    }
+    if device.provider != approver.provider {
+        return Err(format!(
+            "This code is for identity provider '{}'; sign in with it to approve or deny",
+            device.provider
+        ));
+    }
+    Ok((device_code, device))
+}
+
+/// Poll Keycloak until the user confirmed the code there, then check the token
+async fn fetch_token(
+    keycloak: &StandardDeviceAuthorizationResponse,
+    client: &ConfiguredOAuthClient,
+    http_client: &reqwest::Client,
+    timeout: Duration,
+    identity_providers: &IdentityProviders,
+    provider: &str,
+    sub: &str,
+) -> Result<DeviceToken, DeviceError> {
+    let token = client
+        .exchange_device_access_token(keycloak)
+        .request_async(
+            &TracedHttpClient(http_client),
+            tokio::time::sleep,
+            Some(timeout),
+        )
+        .await
+        .map_err(|e| match e {
+            RequestTokenError::ServerResponse(response) => match response.error() {
+                DeviceCodeErrorResponseType::AccessDenied => DeviceError::AccessDenied,
+                DeviceCodeErrorResponseType::ExpiredToken => DeviceError::ExpiredToken,
+                other => DeviceError::Upstream(other.to_string()),
+            },
+            other => DeviceError::Upstream(other.to_string()),
+        })?;
+
+    let access_token = token.access_token().secret();
+    let (issuer, claims) = identity_providers
+        .validate_async(access_token)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Device flow token failed validation");
+            DeviceError::AccessDenied
+        })?;
+
+    // The Keycloak confirmation could have happened in another account's
+    // browser session: only the user who approved on the portal gets the token
+    if issuer != provider || claims.sub != sub {
+        tracing::warn!(
+            event = "device_authorization_user_mismatch",
+            approver = %sub,
+            token_sub = %claims.sub,
+            "Device flow token belongs to another user than the approver"
+        );
+        return Err(DeviceError::AccessDenied);
+    }
+
+    tracing::info!(
+        event = "device_authorization_completed",
+        provider = %provider,
+        sub = %sub,
+        "Device flow token issued"
+    );
+    Ok(DeviceToken {
+        access_token: access_token.clone(),
+        token_type: "Bearer",
+        expires_in: token.expires_in().map(|d| d.as_secs()).unwrap_or(300),
+    })
+}
+
+/// Compare user codes ignoring case and surrounding whitespace
+fn codes_match(expected: &str, presented: &str) -> bool {
+    expected.eq_ignore_ascii_case(presented.trim())
+}
+
+fn random_code() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes)
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceCodeForm {
+    /// Identity provider id (defaults to the default provider)
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceTokenForm {
+    pub grant_type: Option<String>,
+    pub device_code: String,
+}
+
+/// Device authorization endpoint for CLIs (unauthenticated)
+pub async fn device_code_handler(
+    State(state): State<Arc<crate::AppState>>,
+    Form(form): Form<DeviceCodeForm>,
+) -> Response {
+    let provider = match form.provider.as_deref() {
+        Some(id) => state.config.identity_provider(id),
+        None => Some(state.config.default_identity_provider()),
+    };
+    let Some(provider) = provider else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "invalid_request" })),
+        )
+            .into_response();
+    };
+
+    let client = match create_oauth_client(provider, &state.config.redirect_uri) {
+        Ok(client) => client,
+        Err(e) => return DeviceError::Upstream(e).into_response(),
+    };
+    let http_client = match create_http_client(
+        state.config.http_connect_timeout_secs,
+        state.config.http_request_timeout_secs,
+    ) {
+        Ok(client) => client,
+        Err(e) => return DeviceError::Upstream(e.to_string()).into_response(),
+    };
+    let verification_uri = format!(
+        "{}/auth/device",
+        state.config.portal_public_url.trim_end_matches('/')
+    );
+
+    match state
+        .device_flows
+        .start(&provider.id, &client, &http_client, &verification_uri)
+        .await
+    {
+        Ok(authorization) => Json(authorization).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Token endpoint polled by CLIs (unauthenticated, the device code is the secret)
+pub async fn device_token_handler(
+    State(state): State<Arc<crate::AppState>>,
+    Form(form): Form<DeviceTokenForm>,
+) -> Response {
+    if form
+        .grant_type
+        .as_deref()
+        .is_some_and(|grant_type| grant_type != DEVICE_CODE_GRANT_TYPE)
+    {
+        return DeviceError::UnsupportedGrantType.into_response();
+    }
+    match state.device_flows.poll(&form.device_code) {
+        Ok(token) => Json(token).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IdentityProviderConfig;
+    use crate::secrets::Secret;
+    use axum::{routing::get, routing::post, Router};
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const MOCK_KEY_PEM: &[u8] = include_bytes!("testdata/mock_idp_key.pem");
+    const MOCK_KEY_N: &str = "rTQ4PdAWT89Y16603-T16Uvu0nGRRHrBTso_W4cXu7OioQALKsUaIJHgUKNA1th04VHPtfV7qbTJqjnH3oGfJkb_OBvJqspH0NE33kzk2m48J3smFDw2bJfJuvj8yqb7BpUACJlQkEvA_iDqeiNqxkgn0gW3oNEx3dYRveQWCPyyKMXlNoC_MwJxXpISxqZFoQWr5qcgkRo45Xb0jEwcJ1sxQDDPy3vb7_S_0sfsoHij6PAFGktNORYG6EkM8Yiv9lRU9fKq1VEOnBftABjhTGQscP934b4k6vrIdRof4qM9yG2MaWBjYznW4p_p4LMiIoydPqeQQvgVBKFlRL7dtw";
+
+    fn access_token(sub: &str) -> String {
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 300;
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("mock".to_string());
+        encode(
+            &header,
+            &serde_json::json!({
+                "sub": sub,
+                "exp": exp,
+                "iss": "http://keycloak.localhost/realms/dev",
+                "aud": "portal",
+            }),
+            &EncodingKey::from_rsa_pem(MOCK_KEY_PEM).unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Mock Keycloak realm: the token endpoint answers `authorization_pending`
+    /// for the first `pending_polls` requests, then issues a token for `sub`;
+    /// `polls` counts the token requests
+    async fn mock_idp(sub: &'static str, pending_polls: usize, polls: Arc<AtomicUsize>) -> String {
+        let app = Router::new()
+            .route(
+                "/realms/dev/protocol/openid-connect/auth/device",
+                post(|| async {
+                    Json(serde_json::json!({
+                        "device_code": "keycloak-device-code",
+                        "user_code": "WDJB-MJHT",
+                        "verification_uri": "http://keycloak.localhost/realms/dev/device",
+                        "verification_uri_complete": "http://keycloak.localhost/realms/dev/device?user_code=WDJB-MJHT",
+                        "expires_in": 600,
+                        "interval": 1
+                    }))
+                }),
+            )
+            .route(
+                "/realms/dev/protocol/openid-connect/token",
+                post(move || {
+                    let poll = polls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if poll < pending_polls {
+                            (
+                                StatusCode::BAD_REQUEST,
+                                Json(serde_json::json!({ "error": "authorization_pending" })),
+                            )
+                                .into_response()
+                        } else {
+                            Json(serde_json::json!({
+                                "access_token": access_token(sub),
+                                "token_type": "Bearer",
+                                "expires_in": 300
+                            }))
+                            .into_response()
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/realms/dev/protocol/openid-connect/certs",
+                get(|| async {
+                    Json(serde_json::json!({
+                        "keys": [{ "kid": "mock", "kty": "RSA", "alg": "RS256", "n": MOCK_KEY_N, "e": "AQAB" }]
+                    }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    struct Setup {
+        flows: Arc<DeviceFlows>,
+        client: ConfiguredOAuthClient,
+        http_client: reqwest::Client,
+        providers: Arc<IdentityProviders>,
+        shutdown: Shutdown,
+        polls: Arc<AtomicUsize>,
+    }
+
+    async fn setup(sub: &'static str, pending_polls: usize) -> Setup {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let config = IdentityProviderConfig {
+            id: "default".to_string(),
+            display_name: "Dev".to_string(),
+            keycloak_url: mock_idp(sub, pending_polls, polls.clone()).await,
+            keycloak_callback_url: "http://keycloak.localhost".to_string(),
+            realm: "dev".to_string(),
+            client_id: "portal".to_string(),
+            client_secret: Secret::new("secret"),
+            accepted_audiences: Vec::new(),
+            brokers: Vec::new(),
+        };
+        Setup {
+            flows: Arc::new(DeviceFlows::default()),
+            client: create_oauth_client(&config, "http://portal.localhost/auth/callback").unwrap(),
+            http_client: create_http_client(2, 5).unwrap(),
+            providers: Arc::new(IdentityProviders::new(&[config], 2, 5, 3600).unwrap()),
+            shutdown: Shutdown::default(),
+            polls,
+        }
+    }
+
+    impl Setup {
+        async fn start(&self) -> DeviceAuthorization {
+            self.flows
+                .start(
+                    "default",
+                    &self.client,
+                    &self.http_client,
+                    "http://portal.localhost/auth/device",
+                )
+                .await
+                .unwrap()
+        }
+
+        fn approve(&self, user_code: &str, provider: &str, sub: &str) -> Result<String, String> {
+            self.flows.approve(
+                user_code,
+                Approver { provider, sub },
+                self.client.clone(),
+                self.http_client.clone(),
+                self.providers.clone(),
+                &self.shutdown,
+            )
+        }
+
+        /// Poll like a CLI until the flow is done
+        async fn finish(&self, device_code: &str) -> Result<DeviceToken, DeviceError> {
+            for _ in 0..50 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                match self.flows.poll(device_code) {
+                    Err(DeviceError::AuthorizationPending | DeviceError::SlowDown) => continue,
+                    result => return result,
+                }
+            }
+            panic!("device flow did not finish");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_issues_token_to_approver() {
+        let setup = setup("alice", 1).await;
+        let authorization = setup.start().await;
+        assert_eq!(authorization.user_code, "WDJB-MJHT");
+        assert_ne!(authorization.device_code, "keycloak-device-code");
+        assert_eq!(
+            authorization.verification_uri_complete,
+            "http://portal.localhost/auth/device?user_code=WDJB-MJHT"
+        );
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::AuthorizationPending)
+        );
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::SlowDown)
+        );
+
+        assert_eq!(
+            setup.approve("wdjb-mjht", "contractors", "alice"),
+            Err(
+                "This code is for identity provider 'default'; sign in with it to approve or deny"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            setup.approve(" wdjb-mjht ", "default", "alice"),
+            Ok("http://keycloak.localhost/realms/dev/device?user_code=WDJB-MJHT".to_string())
+        );
+        assert_eq!(
+            setup.approve("WDJB-MJHT", "default", "alice"),
+            Err("This code has already been used".to_string())
+        );
+
+        let token = setup.finish(&authorization.device_code).await.unwrap();
+        let (_, claims) = setup
+            .providers
+            .validate_async(&token.access_token)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "alice");
+
+        // Device codes are single-use
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::InvalidGrant)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_rejects_token_of_other_user() {
+        let setup = setup("mallory", 0).await;
+        let authorization = setup.start().await;
+        setup.approve("WDJB-MJHT", "default", "alice").unwrap();
+        assert_eq!(
+            setup.finish(&authorization.device_code).await,
+            Err(DeviceError::AccessDenied)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_token_poll() {
+        // Keycloak never issues the token
+        let setup = setup("alice", usize::MAX).await;
+        let authorization = setup.start().await;
+        setup.approve("WDJB-MJHT", "default", "alice").unwrap();
+
+        setup
+            .shutdown
+            .stop_background_tasks(Duration::from_secs(5))
+            .await;
+        let polls = setup.polls.load(Ordering::SeqCst);
+        // Keycloak asks for a 1 s interval; a poll that outlived the shutdown would show up
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(setup.polls.load(Ordering::SeqCst), polls);
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::AuthorizationPending)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_denied_device_flow() {
+        let setup = setup("alice", 0).await;
+        let authorization = setup.start().await;
+        let contractor = Approver {
+            provider: "contractors",
+            sub: "bob",
+        };
+        assert!(setup.flows.deny("WDJB-MJHT", contractor).is_err());
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::AuthorizationPending)
+        );
+
+        let alice = Approver {
+            provider: "default",
+            sub: "alice",
+        };
+        assert_eq!(setup.flows.deny("WDJB-MJHT", alice), Ok(()));
+        assert_eq!(
+            setup.flows.poll(&authorization.device_code),
+            Err(DeviceError::AccessDenied)
+        );
+        assert!(setup.approve("WDJB-MJHT", "default", "alice").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_too_many_pending_devices_is_unavailable() {
+        let setup = setup("alice", 0).await;
+        setup.start().await;
+        {
+            let mut devices = setup.flows.devices.lock().unwrap();
+            let keycloak = devices.values().next().unwrap().keycloak.clone();
+            for i in devices.len()..MAX_PENDING_DEVICES {
+                devices.insert(
+                    format!("device-{}", i),
+                    PendingDevice {
+                        provider: "default".to_string(),
+                        keycloak: keycloak.clone(),
+                        expires_at: Instant::now() + Duration::from_secs(600),
+                        last_poll: None,
+                        state: DeviceState::AwaitingApproval,
+                    },
+                );
+            }
+        }
+
+        let error = setup
+            .flows
+            .start(
+                "default",
+                &setup.client,
+                &setup.http_client,
+                "http://portal.localhost/auth/device",
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error, DeviceError::TemporarilyUnavailable);
+        assert_eq!(
+            error.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use crate::auth::helpers::extract_cookie;
-use crate::auth::jwt::{Claims, JwtValidator};
+use crate::audit::{AuditContext, AuditEvent, AuditEventType, AuditLog};
+use crate::auth::helpers::{extract_bearer_token, extract_cookie};
+use crate::auth::jwt::Claims;
+use crate::auth::providers::IdentityProviders;
+use crate::auth::tokens::{TokenStore, TOKEN_PREFIX};
+use crate::request_id;
 use axum::{
     extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -19,43 +23,60 @@ pub enum AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        match self {
+        let (status, mut body) = match self {
             AuthError::Unauthenticated(msg) => (
                 StatusCode::UNAUTHORIZED,
-                Json(json!({
+                json!({
                     "error": "Authentication required",
                     "message": msg,
                     "code": "UNAUTHENTICATED"
-                })),
-            )
-                .into_response(),
+                }),
+            ),
 
             AuthError::Forbidden(msg) => (
                 StatusCode::FORBIDDEN,
-                Json(json!({
+                json!({
                     "error": "Access denied",
                     "message": msg,
                     "code": "FORBIDDEN"
-                })),
-            )
-                .into_response(),
+                }),
+            ),
 
             AuthError::Internal(msg) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
+                json!({
                     "error": "Internal server error",
                     "message": msg
-                })),
-            )
-                .into_response(),
+                }),
+            ),
+        };
+        // Lets users quote the ID when reporting the error
+        if let Some(id) = request_id::current() {
+            body["request_id"] = id.0.into();
         }
+        (status, Json(body)).into_response()
     }
 }
 
-/// Authenticated user extractor - validates JWT from cookie
+/// How the request carried its token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// `access_token` cookie set by the login flow (browsers)
+    Cookie,
+    /// `Authorization: Bearer <jwt>` header (scripts and other services)
+    Bearer,
+    /// `Authorization: Bearer pat_...` personal access token (user scripts)
+    PersonalAccessToken,
+}
+
+/// Authenticated user extractor - validates JWT from bearer header or cookie
 ///
 /// This extractor provides both user claims and roles in a convenient structure.
 /// It will fail (return AuthError) if authentication is missing or invalid.
+/// An `Authorization` header takes precedence over the `access_token` cookie;
+/// `method` tells them apart (see `BrowserUser` for cookie-only routes).
+/// Personal access tokens resolve to the claims they were minted with and
+/// may be restricted to some services (`service_scope`).
 ///
 /// Usage:
 /// ```rust,ignore
@@ -67,6 +88,12 @@ impl IntoResponse for AuthError {
 pub struct AuthenticatedUser {
     pub claims: Claims,
     pub roles: Vec<String>,
+    /// Id of the identity provider that issued the token
+    pub provider: String,
+    /// Whether the token came from the cookie or a bearer header
+    pub method: AuthMethod,
+    /// Service ids a personal access token is restricted to (None = no restriction)
+    pub service_scope: Option<Vec<String>>,
 }
 
 impl<S> FromRequestParts<S> for AuthenticatedUser
@@ -76,21 +103,57 @@ where
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        // 1. Extract access_token from cookies using shared helper
-        let token = extract_cookie(&parts.headers, "access_token")
-            .ok_or_else(|| AuthError::Unauthenticated("Missing access_token cookie".to_string()))?;
+        // 1. Extract the bearer token, or access_token from cookies using shared helper
+        let (token, method) = if parts.headers.contains_key(AUTHORIZATION) {
+            let token = extract_bearer_token(&parts.headers).ok_or_else(|| {
+                AuthError::Unauthenticated(
+                    "Authorization header must be 'Bearer <token>'".to_string(),
+                )
+            })?;
+            (token, AuthMethod::Bearer)
+        } else {
+            let token = extract_cookie(&parts.headers, "access_token").ok_or_else(|| {
+                AuthError::Unauthenticated("Missing access_token cookie".to_string())
+            })?;
+            (token, AuthMethod::Cookie)
+        };
 
-        // 2. Get JwtValidator from extensions
-        let validator = parts
+        // 2. Get IdentityProviders from extensions
+        let providers = parts
             .extensions
-            .get::<Arc<JwtValidator>>()
-            .ok_or_else(|| AuthError::Internal("Missing JwtValidator extension".to_string()))?;
+            .get::<Arc<IdentityProviders>>()
+            .ok_or_else(|| {
+                AuthError::Internal("Missing IdentityProviders extension".to_string())
+            })?;
 
-        // 3. Validate JWT asynchronously
-        let claims = validator
-            .validate_async(&token)
-            .await
-            .map_err(|e| AuthError::Unauthenticated(format!("Invalid token: {}", e)))?;
+        // 3. Resolve personal access tokens from the store, validate JWTs
+        //    asynchronously with the provider matching their issuer
+        let authenticated = if method == AuthMethod::Bearer && token.starts_with(TOKEN_PREFIX) {
+            resolve_personal_access_token(parts, providers, &token).await
+        } else {
+            providers
+                .validate_async(&token)
+                .await
+                .map(|(provider, claims)| (method, provider.to_string(), claims, None))
+                .map_err(|e| format!("Invalid token: {}", e))
+        };
+        let (method, provider, claims, service_scope) = match authenticated {
+            Ok(authenticated) => authenticated,
+            Err(message) => {
+                if let Some(audit) = parts.extensions.get::<Arc<AuditLog>>() {
+                    audit.record(
+                        AuditEvent::new(
+                            AuditEventType::TokenRejected,
+                            &AuditContext::from_parts(parts),
+                        )
+                        .reason(&message)
+                        .token(&token)
+                        .target(parts.uri.path()),
+                    );
+                }
+                return Err(AuthError::Unauthenticated(message));
+            }
+        };
 
         // 4. Extract roles for easy access
         let roles = claims.roles();
@@ -107,12 +170,205 @@ where
         } else {
             tracing::debug!(
                 user = %claims.sub,
+                provider = %provider,
+                method = ?method,
                 roles = ?roles,
-                "User authenticated via cookie"
+                "User authenticated"
             );
         }
 
-        Ok(AuthenticatedUser { claims, roles })
+        Ok(AuthenticatedUser {
+            claims,
+            roles,
+            provider,
+            method,
+            service_scope,
+        })
+    }
+}
+
+/// Look up a `pat_` bearer token and re-check it against the current config
+async fn resolve_personal_access_token(
+    parts: &Parts,
+    providers: &IdentityProviders,
+    token: &str,
+) -> Result<(AuthMethod, String, Claims, Option<Vec<String>>), String> {
+    let store = parts
+        .extensions
+        .get::<Arc<TokenStore>>()
+        .ok_or_else(|| "Personal access tokens are not enabled".to_string())?;
+    let resolved = store
+        .resolve(token)
+        .await
+        .ok_or_else(|| "Invalid personal access token: unknown, revoked or expired".to_string())?;
+    // The issuing provider may have been removed since minting
+    if !providers.contains(&resolved.provider) {
+        return Err(format!(
+            "Invalid personal access token: identity provider '{}' is no longer configured",
+            resolved.provider
+        ));
+    }
+    Ok((
+        AuthMethod::PersonalAccessToken,
+        resolved.provider,
+        resolved.claims,
+        resolved.services,
+    ))
+}
+
+/// Authenticated user extractor for browser-only routes
+///
+/// Like `AuthenticatedUser`, but rejects bearer and personal access tokens
+/// with 403: routes that render pages or rely on `SameSite` cookies for CSRF
+/// protection must only be reached with the portal session cookie.
+pub struct BrowserUser(pub AuthenticatedUser);
+
+impl<S> FromRequestParts<S> for BrowserUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        if user.method != AuthMethod::Cookie {
+            tracing::warn!(
+                user = %user.claims.sub,
+                path = %parts.uri.path(),
+                method = ?user.method,
+                "Bearer token rejected on browser-only route"
+            );
+            if let Some(audit) = parts.extensions.get::<Arc<AuditLog>>() {
+                audit.record(
+                    AuditEvent::new(
+                        AuditEventType::AccessDenied,
+                        &AuditContext::from_parts(parts),
+                    )
+                    .user(&user.provider, &user.claims.sub)
+                    .reason("browser session required")
+                    .target(parts.uri.path()),
+                );
+            }
+            return Err(AuthError::Forbidden(
+                "This endpoint requires a browser session".to_string(),
+            ));
+        }
+        Ok(BrowserUser(user))
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IdentityProviderConfig;
+    use crate::secrets::Secret;
+    use axum::http::Request;
+
+    fn parts(headers: &[(&str, &str)]) -> Parts {
+        let mut request = Request::builder().uri("/dashboard");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        let providers = IdentityProviders::new(
+            &[IdentityProviderConfig {
+                id: "default".to_string(),
+                display_name: "Dev".to_string(),
+                keycloak_url: "http://127.0.0.1:9".to_string(),
+                keycloak_callback_url: "http://keycloak.localhost".to_string(),
+                realm: "dev".to_string(),
+                client_id: "portal".to_string(),
+                client_secret: Secret::new("secret"),
+                accepted_audiences: vec!["portal-api".to_string()],
+                brokers: Vec::new(),
+            }],
+            1,
+            1,
+            3600,
+        )
+        .unwrap();
+        parts.extensions.insert(Arc::new(providers));
+        parts
+    }
+
+    async fn rejection(headers: &[(&str, &str)]) -> String {
+        match AuthenticatedUser::from_request_parts(&mut parts(headers), &()).await {
+            Ok(_) => panic!("expected a rejection"),
+            Err(AuthError::Unauthenticated(message)) => message,
+            Err(other) => panic!("unexpected rejection: {:?}", other),
+        }
+    }
+
+    // Payload: {"iss":"http://keycloak.localhost/realms/contractors"}
+    const OTHER_ISSUER_TOKEN: &str = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.eyJpc3MiOiJodHRwOi8va2V5Y2xvYWsubG9jYWxob3N0L3JlYWxtcy9jb250cmFjdG9ycyJ9.";
+
+    #[tokio::test]
+    async fn test_missing_credentials() {
+        assert_eq!(rejection(&[]).await, "Missing access_token cookie");
+    }
+
+    #[tokio::test]
+    async fn test_authorization_header_takes_precedence_over_cookie() {
+        let bearer = format!("Bearer {}", OTHER_ISSUER_TOKEN);
+        let message = rejection(&[
+            ("authorization", &bearer),
+            ("cookie", "access_token=not-a-jwt"),
+        ])
+        .await;
+        assert!(message.contains("Token issuer not accepted"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_non_bearer_authorization_is_rejected() {
+        let message = rejection(&[
+            ("authorization", "Basic dXNlcjpwYXNz"),
+            ("cookie", &format!("access_token={}", OTHER_ISSUER_TOKEN)),
+        ])
+        .await;
+        assert_eq!(message, "Authorization header must be 'Bearer <token>'");
+    }
+
+    #[tokio::test]
+    async fn test_personal_access_token() {
+        assert_eq!(
+            rejection(&[("authorization", "Bearer pat_0123_secret")]).await,
+            "Personal access tokens are not enabled"
+        );
+
+        let path = std::env::temp_dir().join("test_extractor_tokens.json");
+        std::fs::remove_file(&path).ok();
+        let store = Arc::new(TokenStore::open(&path).unwrap());
+        let claims: Claims = serde_json::from_value(json!({
+            "sub": "alice",
+            "exp": 0,
+            "realm_access": { "roles": ["dev"] }
+        }))
+        .unwrap();
+        let (token, _) = store
+            .mint(
+                &claims,
+                "default",
+                "ci",
+                std::time::Duration::from_secs(3600),
+                Some(vec!["grafana".to_string()]),
+            )
+            .await
+            .unwrap();
+
+        let bearer = format!("Bearer {}", token);
+        let mut request = parts(&[("authorization", &bearer)]);
+        request.extensions.insert(store);
+        let user = AuthenticatedUser::from_request_parts(&mut request, &())
+            .await
+            .unwrap();
+        assert_eq!(user.method, AuthMethod::PersonalAccessToken);
+        assert_eq!(user.roles, vec!["dev"]);
+        assert_eq!(user.service_scope, Some(vec!["grafana".to_string()]));
+        assert!(matches!(
+            BrowserUser::from_request_parts(&mut request, &()).await,
+            Err(AuthError::Forbidden(_))
+        ));
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -5,6 +5,7 @@
 //! - `callback_handler`: Handles OAuth2 callback and token exchange
 //! - `logout_handler`: Cascading logout through oauth2-proxy services and Keycloak
 //! - `logout_complete_handler`: Final landing page after logout
+//! - `service_sign_out_handler`: Signs out of a single oauth2-proxy service
 
 use axum::{
     extract::{Query, State},
@@ -14,18 +15,25 @@ use axum::{
 };
 use oauth2::{
     basic::{BasicErrorResponseType, BasicTokenType},
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointSet, ExtraTokenFields,
-    RedirectUrl, Scope, StandardErrorResponse, StandardRevocableToken,
-    StandardTokenIntrospectionResponse, StandardTokenResponse, TokenResponse, TokenUrl,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, DeviceAuthorizationUrl,
+    EndpointSet, ExtraTokenFields, RedirectUrl, Scope, StandardErrorResponse,
+    StandardRevocableToken, StandardTokenIntrospectionResponse, StandardTokenResponse,
+    TokenResponse, TokenUrl,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tracing::Instrument;
 
+use super::extractors::{AuthenticatedUser, BrowserUser};
 use super::helpers::{
-    build_keycloak_logout_url, build_oauth2_proxy_sign_out_url, build_portal_logout_continue_url,
-    create_http_client, extract_cookie, find_next_reachable_service, list_oauth2_proxy_services,
-    FindReachableResult,
+    build_keycloak_logout_url, build_oauth2_proxy_sign_out_url, build_portal_dashboard_url,
+    build_portal_logout_continue_url, build_probe_client, create_http_client, extract_cookie,
+    find_next_reachable_service, is_valid_idp_hint, jwt_subject, list_oauth2_proxy_services,
+    probe_service_reachable, FindReachableResult, LoginChoice,
 };
+use crate::audit::{AuditContext, AuditEvent, AuditEventType};
+use crate::config::IdentityProviderConfig;
+use crate::telemetry::TracedHttpClient;
 
 // =============================================================================
 // Types
@@ -40,19 +48,38 @@ pub struct OidcTokenFields {
 impl ExtraTokenFields for OidcTokenFields {}
 
 /// Type alias for our configured OAuth client with OIDC support
-type ConfiguredOAuthClient = oauth2::Client<
+pub(crate) type ConfiguredOAuthClient = oauth2::Client<
     StandardErrorResponse<BasicErrorResponseType>,
     StandardTokenResponse<OidcTokenFields, BasicTokenType>,
     StandardTokenIntrospectionResponse<OidcTokenFields, BasicTokenType>,
     StandardRevocableToken,
     StandardErrorResponse<oauth2::RevocationErrorResponseType>,
     EndpointSet,            // HasAuthUrl
-    oauth2::EndpointNotSet, // HasDeviceAuthUrl
+    EndpointSet,            // HasDeviceAuthUrl
     oauth2::EndpointNotSet, // HasIntrospectionUrl
     oauth2::EndpointNotSet, // HasRevocationUrl
     EndpointSet,            // HasTokenUrl
 >;
 
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    /// Identity provider id (defaults to the default provider)
+    pub provider: Option<String>,
+    /// Keycloak-brokered IdP alias, forwarded as `kc_idp_hint`
+    pub idp: Option<String>,
+    /// Username or email, forwarded as `login_hint`
+    pub login_hint: Option<String>,
+}
+
+/// Long-lived cookie with the last login choice (pre-selected on the landing page)
+pub const LAST_LOGIN_COOKIE: &str = "last_login";
+
+/// One year, so the choice survives logout and token expiry
+const LAST_LOGIN_MAX_AGE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Upper bound for `login_hint` (usernames and emails are far shorter)
+const MAX_LOGIN_HINT_LEN: usize = 256;
+
 #[derive(Deserialize)]
 pub struct CallbackParams {
     pub code: Option<String>,
@@ -67,6 +94,12 @@ pub struct LogoutQuery {
     pub service_id: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ServiceSignOutQuery {
+    #[serde(rename = "serviceId")]
+    pub service_id: String,
+}
+
 // =============================================================================
 // Internal Helpers
 // =============================================================================
@@ -90,32 +123,35 @@ fn header_value(s: &str) -> Result<HeaderValue, Box<Response>> {
     })
 }
 
-/// Initialize OAuth2 client from environment
-fn create_oauth_client(
-    keycloak_callback_url: &str,
-    keycloak_url: &str,
-    realm: &str,
-    client_id: &str,
-    client_secret: &str,
+/// Initialize OAuth2 client for an identity provider
+pub(crate) fn create_oauth_client(
+    provider: &IdentityProviderConfig,
     redirect_uri: &str,
 ) -> Result<ConfiguredOAuthClient, String> {
-    let client_id = ClientId::new(client_id.to_string());
-    let client_secret = ClientSecret::new(client_secret.to_string());
+    let client_id = ClientId::new(provider.client_id.clone());
+    let client_secret = ClientSecret::new(provider.client_secret.expose());
 
     // Use public URL for browser redirects
     let auth_url = AuthUrl::new(format!(
         "{}/realms/{}/protocol/openid-connect/auth",
-        keycloak_callback_url, realm
+        provider.keycloak_callback_url, provider.realm
     ))
     .map_err(|e| format!("Invalid auth URL: {}", e))?;
 
     // Use internal URL for token exchange
     let token_url = TokenUrl::new(format!(
         "{}/realms/{}/protocol/openid-connect/token",
-        keycloak_url, realm
+        provider.keycloak_url, provider.realm
     ))
     .map_err(|e| format!("Invalid token URL: {}", e))?;
 
+    // Use internal URL for the device authorization grant (server-to-server)
+    let device_authorization_url = DeviceAuthorizationUrl::new(format!(
+        "{}/realms/{}/protocol/openid-connect/auth/device",
+        provider.keycloak_url, provider.realm
+    ))
+    .map_err(|e| format!("Invalid device authorization URL: {}", e))?;
+
     let redirect_url = RedirectUrl::new(redirect_uri.to_string())
         .map_err(|e| format!("Invalid redirect URL: {}", e))?;
 
@@ -124,6 +160,7 @@ fn create_oauth_client(
         .set_client_secret(client_secret)
         .set_auth_uri(auth_url)
         .set_token_uri(token_url)
+        .set_device_authorization_url(device_authorization_url)
         .set_redirect_uri(redirect_url);
 
     Ok(client)
@@ -134,19 +171,63 @@ fn create_oauth_client(
 // =============================================================================
 
 /// Login handler - initiates OAuth2 authorization code flow
+///
+/// `?provider=<id>` selects the identity provider; without it the default
+/// provider is used. The choice is kept in an `oauth_provider` cookie so the
+/// callback exchanges the code with the same provider.
+///
+/// `?idp=<alias>` and `?login_hint=<user>` are forwarded to Keycloak as
+/// `kc_idp_hint` (skip the Keycloak login page and go straight to a brokered
+/// IdP such as GitHub) and `login_hint`.
 pub async fn login_handler(
     State(state): State<Arc<crate::AppState>>,
+    Query(query): Query<LoginQuery>,
 ) -> Result<Response, Response> {
-    tracing::info!("Login requested");
-
-    let oauth_client = match create_oauth_client(
-        &state.config.keycloak_callback_url,
-        &state.config.keycloak_url,
-        &state.config.keycloak_realm,
-        &state.config.client_id,
-        &state.config.client_secret,
-        &state.config.redirect_uri,
-    ) {
+    tracing::info!(
+        provider = ?query.provider,
+        idp = ?query.idp,
+        has_login_hint = query.login_hint.is_some(),
+        "Login requested"
+    );
+
+    let bad_request = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": message
+            })),
+        )
+            .into_response()
+    };
+    if query
+        .idp
+        .as_deref()
+        .is_some_and(|idp| !is_valid_idp_hint(idp))
+    {
+        tracing::warn!(idp = ?query.idp, "Login requested with invalid idp hint");
+        return Err(bad_request("Invalid idp parameter"));
+    }
+    if query
+        .login_hint
+        .as_deref()
+        .is_some_and(|hint| hint.len() > MAX_LOGIN_HINT_LEN)
+    {
+        return Err(bad_request("Invalid login_hint parameter"));
+    }
+
+    let provider = match query.provider.as_deref() {
+        None => state.config.default_identity_provider(),
+        Some(id) => state.config.identity_provider(id).ok_or_else(|| {
+            tracing::warn!(provider = %id, "Login requested for unknown identity provider");
+            bad_request("Unknown identity provider")
+        })?,
+    };
+    let choice = LoginChoice {
+        provider: provider.id.clone(),
+        idp: query.idp.clone(),
+    };
+
+    let oauth_client = match create_oauth_client(provider, &state.config.redirect_uri) {
         Ok(client) => client,
         Err(e) => {
             tracing::error!(error = %e, "Failed to create OAuth client");
@@ -161,16 +242,24 @@ pub async fn login_handler(
     };
 
     // Generate authorization URL with CSRF protection
-    let (auth_url, csrf_token) = oauth_client
+    let mut auth_request = oauth_client
         .authorize_url(CsrfToken::new_random)
         .add_scope(Scope::new("openid".to_string()))
         .add_scope(Scope::new("profile".to_string()))
-        .add_scope(Scope::new("email".to_string()))
-        .url();
+        .add_scope(Scope::new("email".to_string()));
+    if let Some(idp) = &query.idp {
+        auth_request = auth_request.add_extra_param("kc_idp_hint", idp);
+    }
+    if let Some(login_hint) = &query.login_hint {
+        auth_request = auth_request.add_extra_param("login_hint", login_hint);
+    }
+    let (auth_url, csrf_token) = auth_request.url();
 
     tracing::info!(
-        keycloak_public_url = %state.config.keycloak_callback_url,
-        realm = %state.config.keycloak_realm,
+        provider = %provider.id,
+        idp = ?choice.idp,
+        keycloak_public_url = %provider.keycloak_callback_url,
+        realm = %provider.realm,
         "Redirecting to Keycloak for authentication"
     );
 
@@ -183,11 +272,23 @@ pub async fn login_handler(
         state.config.cookie_secure_flag()
     );
 
+    // Remember the choice for the callback (same lifetime as the CSRF cookie)
+    let provider_cookie = format!(
+        "oauth_provider={}; HttpOnly; Path=/auth; Max-Age=600; SameSite=Lax{}{}",
+        choice.to_cookie_value(),
+        state.config.cookie_domain_attr(),
+        state.config.cookie_secure_flag()
+    );
+
     let mut response = Redirect::to(auth_url.as_str()).into_response();
     response.headers_mut().insert(
         axum::http::header::SET_COOKIE,
         header_value(&csrf_cookie).map_err(|e| *e)?,
     );
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        header_value(&provider_cookie).map_err(|e| *e)?,
+    );
 
     Ok(response)
 }
@@ -197,9 +298,16 @@ pub async fn callback_handler(
     Query(params): Query<CallbackParams>,
     State(state): State<Arc<crate::AppState>>,
     headers: axum::http::HeaderMap,
+    context: AuditContext,
 ) -> Response {
     tracing::info!("OAuth callback received");
 
+    let login_failed = |reason: &str| {
+        state
+            .audit
+            .record(AuditEvent::new(AuditEventType::LoginFailed, &context).reason(reason));
+    };
+
     // Check for OAuth errors
     if let Some(error) = params.error {
         tracing::warn!(
@@ -207,6 +315,7 @@ pub async fn callback_handler(
             description = ?params.error_description,
             "OAuth authorization failed"
         );
+        login_failed(&format!("idp_error: {}", error));
         return (
             StatusCode::UNAUTHORIZED,
             Json(serde_json::json!({
@@ -222,6 +331,7 @@ pub async fn callback_handler(
         Some(ref s) => s,
         None => {
             tracing::warn!("CSRF validation failed: No state parameter in callback");
+            login_failed("missing_state");
             return (
                 StatusCode::BAD_REQUEST,
                 Json(serde_json::json!({
@@ -238,6 +348,7 @@ pub async fn callback_handler(
             has_cookie_header = headers.get("cookie").is_some(),
             "CSRF validation failed: No oauth_state cookie found"
         );
+        login_failed("missing_state_cookie");
         return (
             StatusCode::UNAUTHORIZED,
             Json(serde_json::json!({
@@ -250,6 +361,7 @@ pub async fn callback_handler(
     // Compare states
     if state_from_callback != &stored_state {
         tracing::warn!("CSRF validation failed: State mismatch (callback vs cookie)");
+        login_failed("state_mismatch");
         return (
             StatusCode::UNAUTHORIZED,
             Json(serde_json::json!({
@@ -263,6 +375,7 @@ pub async fn callback_handler(
 
     let Some(code) = params.code else {
         tracing::warn!("No authorization code received");
+        login_failed("missing_code");
         return (
             StatusCode::BAD_REQUEST,
             Json(serde_json::json!({
@@ -274,15 +387,45 @@ pub async fn callback_handler(
 
     tracing::debug!(code_length = code.len(), "Authorization code received");
 
+    // Exchange the code with the provider chosen at login. A missing cookie
+    // means a login started before multiple providers were configured.
+    let choice = match extract_cookie(&headers, "oauth_provider") {
+        None => LoginChoice {
+            provider: state.config.default_identity_provider().id.clone(),
+            idp: None,
+        },
+        Some(value) => match LoginChoice::from_cookie_value(&value) {
+            Some(choice) => choice,
+            None => {
+                tracing::warn!("Callback with malformed oauth_provider cookie");
+                login_failed("unknown_provider");
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({
+                        "error": "Unknown identity provider"
+                    })),
+                )
+                    .into_response();
+            }
+        },
+    };
+    let provider = match state.config.identity_provider(&choice.provider) {
+        Some(provider) => provider,
+        None => {
+            tracing::warn!(provider = %choice.provider, "Callback for unknown identity provider");
+            login_failed("unknown_provider");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "Unknown identity provider"
+                })),
+            )
+                .into_response();
+        }
+    };
+
     // Create OAuth client
-    let oauth_client = match create_oauth_client(
-        &state.config.keycloak_callback_url,
-        &state.config.keycloak_url,
-        &state.config.keycloak_realm,
-        &state.config.client_id,
-        &state.config.client_secret,
-        &state.config.redirect_uri,
-    ) {
+    let oauth_client = match create_oauth_client(provider, &state.config.redirect_uri) {
         Ok(client) => client,
         Err(e) => {
             tracing::error!(error = %e, "Failed to create OAuth client");
@@ -296,7 +439,7 @@ pub async fn callback_handler(
         }
     };
 
-    tracing::info!("Exchanging authorization code for tokens");
+    tracing::info!(provider = %provider.id, "Exchanging authorization code for tokens");
 
     // Exchange authorization code for access token
     let http_client = match create_http_client(
@@ -315,13 +458,18 @@ pub async fn callback_handler(
     };
     let token_result = oauth_client
         .exchange_code(AuthorizationCode::new(code))
-        .request_async(&http_client)
+        .request_async(&TracedHttpClient(&http_client))
         .await;
 
     let token_response = match token_result {
         Ok(token) => token,
         Err(e) => {
             tracing::error!(error = %e, "Failed to exchange code for tokens");
+            state.audit.record(
+                AuditEvent::new(AuditEventType::LoginFailed, &context)
+                    .provider(&provider.id)
+                    .reason("token_exchange_failed"),
+            );
             return (
                 StatusCode::UNAUTHORIZED,
                 Json(serde_json::json!({"error": "Token exchange failed"})),
@@ -402,6 +550,40 @@ pub async fn callback_handler(
         clear_header,
     );
 
+    let clear_provider_cookie = format!(
+        "oauth_provider=; HttpOnly; Path=/auth; Max-Age=0; SameSite=Lax{}{}",
+        state.config.cookie_domain_attr(),
+        state.config.cookie_secure_flag()
+    );
+    let clear_provider_header = match header_value(&clear_provider_cookie) {
+        Ok(h) => h,
+        Err(e) => return *e,
+    };
+    response
+        .headers_mut()
+        .append(axum::http::header::SET_COOKIE, clear_provider_header);
+
+    // Remember the choice for the landing page (not cleared on logout)
+    let last_login_cookie = format!(
+        "{}={}; HttpOnly; Path=/; Max-Age={}; SameSite=Lax{}{}",
+        LAST_LOGIN_COOKIE,
+        choice.to_cookie_value(),
+        LAST_LOGIN_MAX_AGE_SECS,
+        state.config.cookie_domain_attr(),
+        state.config.cookie_secure_flag()
+    );
+    let last_login_header = match header_value(&last_login_cookie) {
+        Ok(h) => h,
+        Err(e) => return *e,
+    };
+    response
+        .headers_mut()
+        .append(axum::http::header::SET_COOKIE, last_login_header);
+
+    let mut login = AuditEvent::new(AuditEventType::Login, &context).provider(&provider.id);
+    login.sub = jwt_subject(access_token);
+    state.audit.record(login);
+
     tracing::info!("Authentication successful, redirecting to dashboard");
     response
 }
@@ -417,15 +599,28 @@ pub async fn logout_handler(
     State(state): State<Arc<crate::AppState>>,
     Query(query): Query<LogoutQuery>,
     headers: axum::http::HeaderMap,
+    context: AuditContext,
 ) -> Response {
     let span = tracing::info_span!(
         "logout_flow",
         service_id = ?query.service_id,
         oauth2_proxy_services = tracing::field::Empty,
     );
-    let _guard = span.enter();
+    // Instrument rather than enter: the span must not stay entered across awaits
+    logout_flow(state, query, headers, context)
+        .instrument(span)
+        .await
+}
 
-    let oauth2_proxy_services = list_oauth2_proxy_services(&state.descriptor);
+async fn logout_flow(
+    state: Arc<crate::AppState>,
+    query: LogoutQuery,
+    headers: axum::http::HeaderMap,
+    context: AuditContext,
+) -> Response {
+
+    let snapshot = state.descriptor.current().await;
+    let oauth2_proxy_services = list_oauth2_proxy_services(&snapshot.descriptor);
     tracing::Span::current().record("oauth2_proxy_services", oauth2_proxy_services.len());
 
     match query.service_id.as_deref() {
@@ -445,6 +640,26 @@ pub async fn logout_handler(
     let id_token = extract_cookie(&headers, "id_token");
     let has_id_token = id_token.is_some();
 
+    // End the session at the provider that issued it (default if unknown)
+    let provider = id_token
+        .clone()
+        .or_else(|| extract_cookie(&headers, "access_token"))
+        .and_then(|token| {
+            state
+                .identity_providers
+                .provider_for_token(&token)
+                .and_then(|id| state.config.identity_provider(id))
+        })
+        .unwrap_or_else(|| state.config.default_identity_provider());
+
+    if query.service_id.is_none() {
+        let mut logout = AuditEvent::new(AuditEventType::Logout, &context).provider(&provider.id);
+        logout.sub = extract_cookie(&headers, "access_token")
+            .or_else(|| id_token.clone())
+            .and_then(|token| jwt_subject(&token));
+        state.audit.record(logout);
+    }
+
     // Determine the starting index for finding the next oauth2-proxy service.
     // Semantics: ?serviceId=<id> means "we just signed out from <id>; continue to the next one".
     let start_index = match query.service_id.as_deref() {
@@ -499,12 +714,17 @@ pub async fn logout_handler(
         state.config.cookie_secure_flag()
     );
 
-    // Also clear any stale oauth_state CSRF cookie (best-effort cleanup).
+    // Also clear any stale oauth_state CSRF and oauth_provider cookies (best-effort cleanup).
     let oauth_state_cookie = format!(
         "oauth_state=; HttpOnly; Path=/auth; Max-Age=0; SameSite=Lax{}{}",
         state.config.cookie_domain_attr(),
         state.config.cookie_secure_flag()
     );
+    let oauth_provider_cookie = format!(
+        "oauth_provider=; HttpOnly; Path=/auth; Max-Age=0; SameSite=Lax{}{}",
+        state.config.cookie_domain_attr(),
+        state.config.cookie_secure_flag()
+    );
 
     let (redirect_target, should_clear_id_token) = match find_result.service {
         Some(next) => {
@@ -537,10 +757,10 @@ pub async fn logout_handler(
             }
 
             let keycloak_logout_url = build_keycloak_logout_url(
-                &state.config.keycloak_callback_url,
-                &state.config.keycloak_realm,
+                &provider.keycloak_callback_url,
+                &provider.realm,
                 &state.config.portal_public_url,
-                &state.config.client_id,
+                &provider.client_id,
                 id_token.as_deref(),
             );
 
@@ -548,7 +768,8 @@ pub async fn logout_handler(
             tracing::info!(
                 event = "keycloak_logout_redirect",
                 has_id_token = has_id_token,
-                keycloak_realm = %state.config.keycloak_realm,
+                provider = %provider.id,
+                keycloak_realm = %provider.realm,
                 "Redirecting to Keycloak end-session"
             );
 
@@ -566,6 +787,11 @@ pub async fn logout_handler(
     if let Ok(h) = header_value(&oauth_state_cookie) {
         response.headers_mut().append(axum::http::header::SET_COOKIE, h);
     }
+    if let Ok(h) = header_value(&oauth_provider_cookie) {
+        response
+            .headers_mut()
+            .append(axum::http::header::SET_COOKIE, h);
+    }
 
     if should_clear_id_token {
         let id_cookie = format!(
@@ -600,3 +826,282 @@ pub async fn logout_complete_handler() -> Response {
     );
     Redirect::to("/").into_response()
 }
+
+/// Single-service sign-out handler - clears one oauth2-proxy session, keeps the portal session
+///
+/// Redirects through the service's `/oauth2/sign_out` endpoint with `rd` pointing back to the
+/// dashboard. Portal cookies are left untouched, so the user stays signed in to the portal
+/// (e.g. to switch accounts in a single third-party app).
+///
+/// The service is probed first, using the same rules as the logout cascade: if it is
+/// unreachable, the user is sent straight back to the dashboard instead of a network error page.
+/// In production without TRAEFIK_INTERNAL_URL, direct probes are not allowed (SSRF) and there is
+/// nothing to probe through, so the redirect happens without a probe (logged as
+/// `service_sign_out_missing_traefik_url`).
+///
+/// Requires an authenticated portal session (cookie, not a bearer token). Since the session
+/// cookie is `SameSite=Lax`, this also makes the POST form CSRF-safe.
+pub async fn service_sign_out_handler(
+    State(state): State<Arc<crate::AppState>>,
+    Query(query): Query<ServiceSignOutQuery>,
+    context: AuditContext,
+    BrowserUser(user): BrowserUser,
+) -> Response {
+    let span = tracing::info_span!(
+        "service_sign_out",
+        service_id = %query.service_id,
+        sub = %user.claims.sub,
+    );
+    service_sign_out(state, query, context, user)
+        .instrument(span)
+        .await
+}
+
+async fn service_sign_out(
+    state: Arc<crate::AppState>,
+    query: ServiceSignOutQuery,
+    context: AuditContext,
+    AuthenticatedUser {
+        claims, provider, ..
+    }: AuthenticatedUser,
+) -> Response {
+    // Only oauth2-proxy services from the descriptor can be signed out of - never an
+    // arbitrary URL, so this cannot be used as an open redirect.
+    let snapshot = state.descriptor.current().await;
+    let oauth2_proxy_services = list_oauth2_proxy_services(&snapshot.descriptor);
+    let Some(service) = oauth2_proxy_services
+        .iter()
+        .find(|s| s.id == query.service_id)
+    else {
+        tracing::warn!(
+            event = "service_sign_out_unknown_service_id",
+            "Unknown or non oauth2-proxy serviceId for single-service sign-out"
+        );
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "Unknown oauth2-proxy service"
+            })),
+        )
+            .into_response();
+    };
+
+    let dashboard_url = build_portal_dashboard_url(&state.config.portal_public_url);
+
+    // Security: In production, require TRAEFIK_INTERNAL_URL to prevent SSRF via direct probing.
+    // Unlike the logout cascade there is no next hop to fall back to, so the user explicitly
+    // asked for this redirect: we skip the probe and redirect anyway.
+    let skip_service_probe =
+        state.config.is_production() && state.config.traefik_internal_url.is_none();
+
+    if skip_service_probe {
+        tracing::warn!(
+            event = "service_sign_out_missing_traefik_url",
+            "TRAEFIK_INTERNAL_URL not set in production; redirecting without reachability probe"
+        );
+    } else {
+        let reachable = match build_probe_client(
+            state.config.logout_probe_connect_timeout_ms,
+            state.config.logout_probe_request_timeout_ms,
+        ) {
+            Ok(client) => probe_service_reachable(
+                &client,
+                &service.url,
+                state.config.traefik_internal_url.as_deref(),
+            )
+            .await
+            .is_reachable(),
+            Err(e) => {
+                tracing::warn!(
+                    error = %e,
+                    "Failed to build HTTP client for reachability probe"
+                );
+                false
+            }
+        };
+
+        if !reachable {
+            tracing::warn!(
+                event = "service_sign_out_unreachable",
+                service_url = %service.url,
+                "Service unreachable, returning to dashboard without sign-out"
+            );
+            return Redirect::to(&dashboard_url).into_response();
+        }
+    }
+
+    state.audit.record(
+        AuditEvent::new(AuditEventType::ServiceSignOut, &context)
+            .user(&provider, &claims.sub)
+            .target(&service.id),
+    );
+
+    let sign_out_url = build_oauth2_proxy_sign_out_url(&service.url, &dashboard_url);
+
+    tracing::info!(
+        event = "service_sign_out_redirect",
+        service_url = %service.url,
+        sign_out_url = %sign_out_url,
+        "Redirecting to oauth2-proxy sign_out for single service"
+    );
+
+    Redirect::to(&sign_out_url).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::extractors::AuthMethod;
+    use crate::auth::providers::IdentityProviders;
+    use crate::config::{Config, Settings};
+    use crate::rate_limit::RateLimiter;
+    use crate::services::{load_descriptor, DescriptorStore};
+    use crate::AppState;
+    use axum::http::{header, HeaderMap};
+    use axum::routing::any;
+    use axum::Router;
+    use std::collections::BTreeMap;
+
+    const DESCRIPTOR: &str = r#"{
+        "version": "1",
+        "deploymentId": "local",
+        "environment": "dev",
+        "baseDomain": "localhost",
+        "portal": { "publicUrl": "http://localhost" },
+        "keycloak": {
+            "publicUrl": "http://keycloak.localhost",
+            "issuerUrl": "http://keycloak.localhost/realms/dev",
+            "realm": "dev"
+        },
+        "services": [
+            {
+                "id": "demo",
+                "name": "Demo App",
+                "url": "http://demo.localhost",
+                "protected": true,
+                "authType": "oauth2-proxy",
+                "requiredRealmRoles": ["dev"]
+            },
+            {
+                "id": "down",
+                "name": "Down App",
+                "url": "http://down.localhost",
+                "protected": true,
+                "authType": "oauth2-proxy",
+                "requiredRealmRoles": ["dev"]
+            },
+            {
+                "id": "docs",
+                "name": "Docs",
+                "url": "http://docs.localhost",
+                "protected": false,
+                "authType": "none"
+            }
+        ]
+    }"#;
+
+    /// Traefik stand-in: routes only `demo.localhost`, 404 (no route) for anything else
+    async fn mock_traefik() -> String {
+        let app = Router::new().fallback(any(|headers: HeaderMap| async move {
+            match headers.get(header::HOST).and_then(|h| h.to_str().ok()) {
+                Some("demo.localhost") => StatusCode::OK,
+                _ => StatusCode::NOT_FOUND,
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn app_state(traefik_internal_url: &str) -> Arc<AppState> {
+        let env: BTreeMap<String, String> = [
+            ("KEYCLOAK_URL", "http://keycloak.localhost"),
+            ("KEYCLOAK_CALLBACK_URL", "http://keycloak.localhost"),
+            ("KEYCLOAK_REALM", "dev"),
+            ("CLIENT_ID", "portal"),
+            ("CLIENT_SECRET", "secret"),
+            ("REDIRECT_URI", "http://portal.localhost/auth/callback"),
+            ("PORTAL_DESCRIPTOR_JSON", DESCRIPTOR),
+            ("TRAEFIK_INTERNAL_URL", traefik_internal_url),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let config = Config::from_settings(&Settings::new(env, None).unwrap()).unwrap();
+        let descriptor = load_descriptor(&config.descriptor).unwrap();
+        Arc::new(AppState {
+            logos: Vec::new(),
+            identity_providers: Arc::new(
+                IdentityProviders::new(&config.identity_providers, 1, 2, 3600).unwrap(),
+            ),
+            descriptor: Arc::new(DescriptorStore::new(descriptor)),
+            federation: Arc::default(),
+            token_store: None,
+            device_flows: Arc::default(),
+            audit: Arc::default(),
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limits.clone())),
+            shutdown: Default::default(),
+            config: Arc::new(config),
+        })
+    }
+
+    fn user() -> AuthenticatedUser {
+        AuthenticatedUser {
+            claims: serde_json::from_value(serde_json::json!({ "sub": "alice", "exp": 0 }))
+                .unwrap(),
+            roles: Vec::new(),
+            provider: "default".to_string(),
+            method: AuthMethod::Cookie,
+            service_scope: None,
+        }
+    }
+
+    async fn sign_out(state: &Arc<AppState>, service_id: &str) -> Response {
+        let query = ServiceSignOutQuery {
+            service_id: service_id.to_string(),
+        };
+        service_sign_out(state.clone(), query, AuditContext::default(), user()).await
+    }
+
+    fn location(response: &Response) -> &str {
+        response.headers()[header::LOCATION].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_service_sign_out_rejects_unknown_and_non_oauth2_proxy_services() {
+        let state = app_state(&mock_traefik().await);
+        for service_id in ["missing", "docs"] {
+            let response = sign_out(&state, service_id).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", service_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_sign_out_redirects_reachable_service_to_its_sign_out() {
+        let state = app_state(&mock_traefik().await);
+        let dashboard_url = build_portal_dashboard_url(&state.config.portal_public_url);
+
+        let response = sign_out(&state, "demo").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            location(&response),
+            build_oauth2_proxy_sign_out_url("http://demo.localhost", &dashboard_url)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_service_sign_out_returns_to_dashboard_when_probe_fails() {
+        let state = app_state(&mock_traefik().await);
+        let dashboard_url = build_portal_dashboard_url(&state.config.portal_public_url);
+
+        // Traefik has no route for the service
+        let response = sign_out(&state, "down").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), dashboard_url);
+
+        // Traefik itself is unreachable (nothing listens on port 9)
+        let response = sign_out(&app_state("http://127.0.0.1:9"), "demo").await;
+        assert_eq!(location(&response), dashboard_url);
+    }
+}
@@ -10,6 +10,7 @@ use axum::http::HeaderMap;
 use std::time::Duration;
 
 use crate::services::AuthType;
+use crate::telemetry::trace_headers;
 
 // =============================================================================
 // JWT Helpers (for logout token validation)
@@ -22,22 +23,9 @@ use crate::services::AuthType;
 ///
 /// Returns true if the token is expired or malformed.
 pub fn is_jwt_expired(token: &str) -> bool {
-    // JWT format: header.payload.signature
-    let parts: Vec<&str> = token.split('.').collect();
-    if parts.len() != 3 {
-        return true; // Malformed
-    }
-
-    // Decode payload (base64url)
-    let payload = match base64_url_decode(parts[1]) {
-        Some(p) => p,
-        None => return true, // Can't decode
-    };
-
-    // Parse as JSON and extract exp claim
-    let json: serde_json::Value = match serde_json::from_slice(&payload) {
-        Ok(v) => v,
-        Err(_) => return true, // Can't parse
+    let json = match jwt_payload(token) {
+        Some(v) => v,
+        None => return true, // Malformed
     };
 
     let exp = match json.get("exp").and_then(|v| v.as_i64()) {
@@ -54,6 +42,40 @@ pub fn is_jwt_expired(token: &str) -> bool {
     exp < (now - 5)
 }
 
+/// Read the `iss` claim of a JWT (without signature verification)
+///
+/// Only used to pick which identity provider validates (or logs out) a
+/// token; the chosen provider still verifies the signature and issuer.
+pub fn jwt_issuer(token: &str) -> Option<String> {
+    jwt_payload(token)?
+        .get("iss")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+}
+
+/// Read the `sub` claim of a JWT (without signature verification)
+///
+/// Only used to attribute audit events for tokens the portal just received
+/// from (or set for) its identity provider, never for authorization.
+pub fn jwt_subject(token: &str) -> Option<String> {
+    jwt_payload(token)?
+        .get("sub")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+}
+
+/// Decode the payload of a JWT as JSON (header.payload.signature)
+fn jwt_payload(token: &str) -> Option<serde_json::Value> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    // Decode payload (base64url) and parse as JSON
+    let payload = base64_url_decode(parts[1])?;
+    serde_json::from_slice(&payload).ok()
+}
+
 /// Decode base64url string (JWT uses base64url without padding)
 fn base64_url_decode(input: &str) -> Option<Vec<u8>> {
     // Replace URL-safe characters and add padding
@@ -134,6 +156,11 @@ pub fn build_portal_logout_continue_url(portal_public_url: &str, next_service_id
     )
 }
 
+/// Build portal dashboard URL (return target after a single-service sign-out)
+pub fn build_portal_dashboard_url(portal_public_url: &str) -> String {
+    format!("{}/dashboard", portal_public_url)
+}
+
 /// Build oauth2-proxy sign out URL with redirect
 pub fn build_oauth2_proxy_sign_out_url(service_url: &str, rd_url: &str) -> String {
     let encoded_rd = urlencoding::encode(rd_url);
@@ -192,7 +219,7 @@ pub fn build_keycloak_logout_url(
 }
 
 // =============================================================================
-// Cookie Extraction
+// Cookie and Header Extraction
 // =============================================================================
 
 /// Extract a cookie value from headers
@@ -218,6 +245,71 @@ pub fn extract_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
     None
 }
 
+/// Extract the token from an `Authorization: Bearer <token>` header
+///
+/// The scheme is matched case-insensitively (RFC 7235). Returns None if the
+/// header is missing, uses another scheme or has an empty token.
+pub fn extract_bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(axum::http::header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+// =============================================================================
+// Login Choice
+// =============================================================================
+
+/// Identity provider (and optional Keycloak-brokered IdP) chosen at login
+///
+/// Stored as `provider` or `provider/alias` in the short-lived `oauth_provider`
+/// cookie for the callback, and in the long-lived `last_login` cookie so the
+/// landing page can pre-select it. Contains no user data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginChoice {
+    pub provider: String,
+    /// `kc_idp_hint` alias, if a brokered IdP was chosen
+    pub idp: Option<String>,
+}
+
+impl LoginChoice {
+    pub fn to_cookie_value(&self) -> String {
+        match &self.idp {
+            Some(idp) => format!("{}/{}", self.provider, idp),
+            None => self.provider.clone(),
+        }
+    }
+
+    /// Parse a cookie value, rejecting anything that could not have been written
+    pub fn from_cookie_value(value: &str) -> Option<Self> {
+        let (provider, idp) = match value.split_once('/') {
+            Some((provider, idp)) => (provider, Some(idp)),
+            None => (value, None),
+        };
+        if !crate::services::is_slug(provider) || !idp.is_none_or(is_valid_idp_hint) {
+            return None;
+        }
+        Some(Self {
+            provider: provider.to_string(),
+            idp: idp.map(str::to_string),
+        })
+    }
+}
+
+/// Check a Keycloak identity provider alias used as `kc_idp_hint`
+///
+/// Restricted to characters that are safe in a cookie value and a URL.
+pub fn is_valid_idp_hint(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 64
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
 // =============================================================================
 // Service URL Parsing
 // =============================================================================
@@ -329,7 +421,7 @@ pub async fn probe_service_reachable(
     };
 
     // Build the request
-    let mut request = client.head(probe_url);
+    let mut request = client.head(probe_url).headers(trace_headers());
     if let Some(host) = host_header {
         request = request.header("Host", host);
     }
@@ -381,6 +473,7 @@ pub struct FindReachableResult<'a> {
 /// the user on a network error page if a service is down.
 ///
 /// Uses a single HTTP client for all probes (performance optimization).
+#[tracing::instrument(skip_all, fields(start_index = start_index, service_count = services.len()))]
 pub async fn find_next_reachable_service<'a>(
     services: &'a [Oauth2ProxyService],
     start_index: usize,
@@ -465,6 +558,12 @@ mod tests {
         assert_eq!(url, "http://portal.localhost/auth/logout?serviceId=demo");
     }
 
+    #[test]
+    fn test_build_portal_dashboard_url() {
+        let url = build_portal_dashboard_url("http://portal.localhost");
+        assert_eq!(url, "http://portal.localhost/dashboard");
+    }
+
     #[test]
     fn test_build_oauth2_proxy_sign_out_url() {
         let rd_url = "http://portal.localhost/auth/logout?serviceId=demo";
@@ -629,4 +728,74 @@ mod tests {
 
         assert!(is_jwt_expired(&token));
     }
+
+    #[test]
+    fn test_extract_bearer_token() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("authorization", value.parse().unwrap());
+            headers
+        };
+        assert_eq!(
+            extract_bearer_token(&headers("Bearer abc.def.ghi")).as_deref(),
+            Some("abc.def.ghi")
+        );
+        assert_eq!(
+            extract_bearer_token(&headers("bearer abc")).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(extract_bearer_token(&headers("Basic dXNlcjpwYXNz")), None);
+        assert_eq!(extract_bearer_token(&headers("Bearer ")), None);
+        assert_eq!(extract_bearer_token(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_login_choice_cookie_round_trip() {
+        let choice = LoginChoice {
+            provider: "contractors".to_string(),
+            idp: Some("github".to_string()),
+        };
+        assert_eq!(choice.to_cookie_value(), "contractors/github");
+        assert_eq!(
+            LoginChoice::from_cookie_value("contractors/github"),
+            Some(choice)
+        );
+        assert_eq!(
+            LoginChoice::from_cookie_value("default"),
+            Some(LoginChoice {
+                provider: "default".to_string(),
+                idp: None,
+            })
+        );
+
+        assert_eq!(LoginChoice::from_cookie_value(""), None);
+        assert_eq!(LoginChoice::from_cookie_value("Default"), None);
+        assert_eq!(LoginChoice::from_cookie_value("default/"), None);
+        assert_eq!(LoginChoice::from_cookie_value("default/git hub"), None);
+    }
+
+    #[test]
+    fn test_is_valid_idp_hint() {
+        assert!(is_valid_idp_hint("github"));
+        assert!(is_valid_idp_hint("saml.corp-AD_2"));
+        assert!(!is_valid_idp_hint(""));
+        assert!(!is_valid_idp_hint("a/b"));
+        assert!(!is_valid_idp_hint(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn test_jwt_issuer() {
+        // Payload: {"iss":"http://keycloak.localhost/realms/contractors"}
+        let header = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0";
+        let payload = "eyJpc3MiOiJodHRwOi8va2V5Y2xvYWsubG9jYWxob3N0L3JlYWxtcy9jb250cmFjdG9ycyJ9";
+        let token = format!("{}.{}.", header, payload);
+
+        assert_eq!(
+            jwt_issuer(&token).as_deref(),
+            Some("http://keycloak.localhost/realms/contractors")
+        );
+        // Payload: {"exp":4102444800} (no iss)
+        assert_eq!(jwt_issuer(&format!("{}.eyJleHAiOjQxMDI0NDQ4MDB9.", header)), None);
+        assert_eq!(jwt_issuer("not-a-jwt"), None);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::telemetry::trace_headers;
 use anyhow::{Context, Result};
 use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
@@ -64,8 +65,8 @@ pub struct JwtValidator {
     realm: String,
     /// Expected issuer URL (Keycloak public URL + realm path)
     expected_issuer: String,
-    /// Expected audience (typically the client_id)
-    expected_audience: String,
+    /// Accepted audiences (the client_id plus any configured API audiences)
+    expected_audiences: Vec<String>,
     client: reqwest::Client,
     jwks_cache: RwLock<Option<JwksCache>>,
     cache_ttl: Duration,
@@ -78,7 +79,7 @@ impl JwtValidator {
     /// * `keycloak_internal_url` - Internal URL for JWKS fetching (container-to-container)
     /// * `keycloak_public_url` - Public URL for issuer validation (what browser sees)
     /// * `realm` - Keycloak realm name
-    /// * `expected_audience` - Expected audience claim (typically client_id)
+    /// * `expected_audiences` - Accepted audience claims (client_id plus e.g. a `portal-api` client)
     /// * `connect_timeout_secs` - HTTP connect timeout
     /// * `request_timeout_secs` - HTTP request timeout
     /// * `jwks_cache_ttl_secs` - JWKS cache TTL
@@ -86,7 +87,7 @@ impl JwtValidator {
         keycloak_internal_url: String,
         keycloak_public_url: String,
         realm: String,
-        expected_audience: String,
+        expected_audiences: Vec<String>,
         connect_timeout_secs: u64,
         request_timeout_secs: u64,
         jwks_cache_ttl_secs: u64,
@@ -102,7 +103,7 @@ impl JwtValidator {
         tracing::info!(
             keycloak_internal_url = %keycloak_internal_url,
             expected_issuer = %expected_issuer,
-            expected_audience = %expected_audience,
+            expected_audiences = ?expected_audiences,
             jwks_cache_ttl_secs = jwks_cache_ttl_secs,
             "JWT validator initialized with issuer and audience validation"
         );
@@ -118,7 +119,7 @@ impl JwtValidator {
             keycloak_internal_url,
             realm,
             expected_issuer,
-            expected_audience,
+            expected_audiences,
             client,
             jwks_cache: RwLock::new(None),
             cache_ttl: Duration::from_secs(jwks_cache_ttl_secs),
@@ -126,6 +127,7 @@ impl JwtValidator {
     }
 
     /// Validate JWT token asynchronously (fetches JWKS if not cached or expired)
+    #[tracing::instrument(name = "validate_token", skip_all, fields(issuer = %self.expected_issuer))]
     pub async fn validate_async(&self, token: &str) -> Result<Claims> {
         tracing::debug!(token_len = token.len(), "Validating JWT token (async)");
 
@@ -169,7 +171,8 @@ impl JwtValidator {
         // Security: Validate issuer to reject tokens from other Keycloak realms/servers
         validation.set_issuer(&[&self.expected_issuer]);
         // Security: Validate audience to prevent token reuse across clients
-        validation.set_audience(&[&self.expected_audience]);
+        // (a token needs at least one of the accepted audiences)
+        validation.set_audience(&self.expected_audiences);
 
         let token_data = match decode::<Claims>(token, &decoding_key, &validation) {
             Ok(data) => data,
@@ -199,6 +202,11 @@ impl JwtValidator {
         Ok(token_data.claims)
     }
 
+    /// Issuer this validator accepts (used to route tokens between providers)
+    pub fn expected_issuer(&self) -> &str {
+        &self.expected_issuer
+    }
+
     /// Check if JWKS is cached (for health checks)
     pub async fn is_jwks_cached(&self) -> bool {
         self.jwks_cache.read().await.is_some()
@@ -247,6 +255,7 @@ impl JwtValidator {
     }
 
     /// Refresh JWKS cache from Keycloak
+    #[tracing::instrument(skip_all, fields(realm = %self.realm))]
     async fn refresh_jwks(&self) -> Result<()> {
         let url = format!(
             "{}/realms/{}/protocol/openid-connect/certs",
@@ -258,6 +267,7 @@ impl JwtValidator {
         let response: JwksResponse = self
             .client
             .get(&url)
+            .headers(trace_headers())
             .send()
             .await
             .context("Failed to fetch JWKS")?
@@ -6,32 +6,47 @@
 //!
 //! - `extractors`: Axum extractors for authenticated users
 //! - `jwt`: JWT validation and caching
+//! - `providers`: Per-identity-provider validators, tokens routed by issuer
+//! - `tokens`: Personal access tokens minted from the dashboard
+//! - `device`: Device authorization grant brokered for CLIs
 //! - `helpers`: Pure helper functions (URL builders, cookie extraction, probing)
 //! - `handlers`: HTTP handlers for login, callback, and logout flows
 //!
 //! ## Authentication Flow
 //!
-//! 1. User visits `/auth/login` → redirect to Keycloak
+//! 1. User visits `/auth/login?provider=<id>` → redirect to that provider's Keycloak realm
 //! 2. Keycloak authenticates → redirect to `/auth/callback`
 //! 3. Portal exchanges code for tokens → sets cookies → redirect to `/dashboard`
 //! 4. User visits `/auth/logout` → cascading logout through oauth2-proxy services → Keycloak
+//!
+//! A single oauth2-proxy service can also be signed out of via `/auth/logout/service`
+//! without ending the portal session.
+//!
+//! Scripts and other services authenticate with `Authorization: Bearer <jwt>`
+//! instead of the cookie; browser-only routes reject those via `BrowserUser`.
+//! Bearer tokens starting with `pat_` are personal access tokens, resolved
+//! from the token store instead of being validated as JWTs.
 
+pub mod device;
 pub mod extractors;
 pub mod handlers;
 pub mod helpers;
 pub mod jwt;
+pub mod providers;
+pub mod tokens;
 
 // Re-export handlers for convenient routing
 pub use handlers::{
-    callback_handler, login_handler, logout_complete_handler, logout_handler, CallbackParams,
-    LogoutQuery,
+    callback_handler, login_handler, logout_complete_handler, logout_handler,
+    service_sign_out_handler, CallbackParams, LoginQuery, LogoutQuery, ServiceSignOutQuery,
+    LAST_LOGIN_COOKIE,
 };
 
 // Re-export helper types that may be useful for testing
 pub use helpers::{
-    build_keycloak_logout_url, build_oauth2_proxy_sign_out_url, build_portal_logout_continue_url,
-    extract_cookie, parse_service_url, FindReachableResult, Oauth2ProxyService, ParsedServiceUrl,
-    ProbeResult,
+    build_keycloak_logout_url, build_oauth2_proxy_sign_out_url, build_portal_dashboard_url,
+    build_portal_logout_continue_url, extract_bearer_token, extract_cookie, parse_service_url,
+    FindReachableResult, LoginChoice, Oauth2ProxyService, ParsedServiceUrl, ProbeResult,
 };
 
 #[cfg(test)]
@@ -54,6 +69,10 @@ mod tests {
                 icon: None,
                 description: None,
                 required_realm_roles: Some(vec!["dev".to_string()]),
+                identity_providers: None,
+                health_path: None,
+                tags: None,
+                links: None,
             },
             ServiceDescriptor {
                 id: "docs".to_string(),
@@ -65,6 +84,10 @@ mod tests {
                 icon: None,
                 description: None,
                 required_realm_roles: None,
+                identity_providers: None,
+                health_path: None,
+                tags: None,
+                links: None,
             },
             ServiceDescriptor {
                 id: "admin".to_string(),
@@ -76,6 +99,10 @@ mod tests {
                 icon: None,
                 description: None,
                 required_realm_roles: Some(vec!["admin".to_string()]),
+                identity_providers: None,
+                health_path: None,
+                tags: None,
+                links: None,
             },
         ];
 
@@ -0,0 +1,174 @@
+//! Identity providers (one Keycloak realm + client each)
+//!
+//! Every configured provider gets its own `JwtValidator` and JWKS cache.
+//! Incoming tokens are routed to a validator by their (unverified) `iss`
+//! claim; the validator then verifies signature, issuer and audience as
+//! before, so a token can never be accepted by a provider that did not mint it.
+
+use anyhow::{Context, Result};
+use std::sync::Arc;
+
+use super::helpers::jwt_issuer;
+use super::jwt::{Claims, JwtValidator};
+use crate::config::IdentityProviderConfig;
+
+/// A configured provider and its validator
+struct Provider {
+    id: String,
+    validator: Arc<JwtValidator>,
+}
+
+/// All identity providers, in configured order (the first is the default)
+pub struct IdentityProviders {
+    providers: Vec<Provider>,
+}
+
+impl IdentityProviders {
+    /// Create a validator per provider
+    ///
+    /// # Arguments
+    /// * `configs` - Providers from `Config::identity_providers`
+    /// * `connect_timeout_secs` - HTTP connect timeout for JWKS fetching
+    /// * `request_timeout_secs` - HTTP request timeout for JWKS fetching
+    /// * `jwks_cache_ttl_secs` - JWKS cache TTL
+    pub fn new(
+        configs: &[IdentityProviderConfig],
+        connect_timeout_secs: u64,
+        request_timeout_secs: u64,
+        jwks_cache_ttl_secs: u64,
+    ) -> Result<Self, String> {
+        let providers = configs
+            .iter()
+            .map(|config| {
+                let validator = JwtValidator::new(
+                    config.keycloak_url.clone(),          // Internal URL for JWKS fetching
+                    config.keycloak_callback_url.clone(), // Public URL for issuer validation
+                    config.realm.clone(),
+                    config.audiences(),
+                    connect_timeout_secs,
+                    request_timeout_secs,
+                    jwks_cache_ttl_secs,
+                )
+                .map_err(|e| format!("identity provider '{}': {}", config.id, e))?;
+                Ok(Provider {
+                    id: config.id.clone(),
+                    validator: Arc::new(validator),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { providers })
+    }
+
+    /// Validate a token with the provider matching its issuer
+    ///
+    /// Returns the provider id along with the claims.
+    pub async fn validate_async(&self, token: &str) -> Result<(&str, Claims)> {
+        let issuer = jwt_issuer(token).context("Token missing iss")?;
+        let provider = self
+            .providers
+            .iter()
+            .find(|p| p.validator.expected_issuer() == issuer)
+            .with_context(|| format!("Token issuer not accepted: {}", issuer))?;
+
+        let claims = provider.validator.validate_async(token).await?;
+        Ok((&provider.id, claims))
+    }
+
+    /// Id of the provider whose issuer matches the token (without verification)
+    ///
+    /// Used by logout, which only needs to pick the end-session endpoint.
+    pub fn provider_for_token(&self, token: &str) -> Option<&str> {
+        let issuer = jwt_issuer(token)?;
+        self.providers
+            .iter()
+            .find(|p| p.validator.expected_issuer() == issuer)
+            .map(|p| p.id.as_str())
+    }
+
+    /// Whether a provider with this id is configured
+    pub fn contains(&self, id: &str) -> bool {
+        self.providers.iter().any(|p| p.id == id)
+    }
+
+    /// Check if every provider's JWKS is cached (for health checks)
+    pub async fn is_jwks_cached(&self) -> bool {
+        for provider in &self.providers {
+            if !provider.validator.is_jwks_cached().await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Prefetch every provider's JWKS at startup
+    pub async fn prefetch_jwks(&self) -> Result<()> {
+        for provider in &self.providers {
+            provider
+                .validator
+                .prefetch_jwks()
+                .await
+                .with_context(|| format!("identity provider '{}'", provider.id))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Secret;
+
+    fn provider(id: &str, realm: &str) -> IdentityProviderConfig {
+        IdentityProviderConfig {
+            id: id.to_string(),
+            display_name: id.to_string(),
+            keycloak_url: "http://127.0.0.1:9".to_string(),
+            keycloak_callback_url: "http://keycloak.localhost/".to_string(),
+            realm: realm.to_string(),
+            client_id: "portal".to_string(),
+            client_secret: Secret::new("secret"),
+            accepted_audiences: Vec::new(),
+            brokers: Vec::new(),
+        }
+    }
+
+    fn providers() -> IdentityProviders {
+        IdentityProviders::new(
+            &[
+                provider("default", "dev"),
+                provider("contractors", "contractors"),
+            ],
+            1,
+            1,
+            3600,
+        )
+        .unwrap()
+    }
+
+    // Payload: {"iss":"http://keycloak.localhost/realms/contractors"}
+    const CONTRACTORS_TOKEN: &str = "eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.eyJpc3MiOiJodHRwOi8va2V5Y2xvYWsubG9jYWxob3N0L3JlYWxtcy9jb250cmFjdG9ycyJ9.";
+
+    #[test]
+    fn test_routes_token_by_issuer() {
+        assert_eq!(
+            providers().provider_for_token(CONTRACTORS_TOKEN),
+            Some("contractors")
+        );
+        assert_eq!(providers().provider_for_token("not-a-jwt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_issuer_without_fetching_jwks() {
+        let providers = IdentityProviders::new(&[provider("default", "dev")], 1, 1, 3600).unwrap();
+        let error = providers
+            .validate_async(CONTRACTORS_TOKEN)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Token issuer not accepted: http://keycloak.localhost/realms/contractors"
+        );
+        assert!(!providers.is_jwks_cached().await);
+    }
+}
@@ -0,0 +1,432 @@
+//! Personal access tokens (PORTAL_TOKEN_STORE_PATH)
+//!
+//! Users mint tokens from the dashboard to call the portal API from scripts
+//! without the browser OAuth flow. A token is `pat_<id>_<secret>`; only the
+//! SHA-256 of the secret is stored, in a JSON file rewritten atomically on
+//! every change.
+//!
+//! A token resolves to the claims (and thus realm roles and identity
+//! provider) of the session it was minted from. Expiry, revocation and the
+//! provider are checked on every use, and service access is re-evaluated
+//! against the current descriptor like for any other request. Role changes
+//! in Keycloak only apply to tokens minted afterwards.
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+use super::jwt::Claims;
+
+/// Prefix that tells personal access tokens apart from JWTs
+pub const TOKEN_PREFIX: &str = "pat_";
+
+/// Upper bound on live tokens per user
+pub const MAX_TOKENS_PER_USER: usize = 25;
+
+/// A stored token (the secret itself is never stored)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredToken {
+    id: String,
+    name: String,
+    /// Hex SHA-256 of the secret part
+    hash: String,
+    provider: String,
+    /// Claims of the session the token was minted from
+    claims: Claims,
+    created_at: u64,
+    expires_at: u64,
+    /// Service ids the token is restricted to (None = every accessible service)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    services: Option<Vec<String>>,
+}
+
+impl StoredToken {
+    fn belongs_to(&self, provider: &str, sub: &str) -> bool {
+        self.provider == provider && self.claims.sub == sub
+    }
+
+    fn info(&self) -> TokenInfo {
+        TokenInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            services: self.services.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    tokens: Vec<StoredToken>,
+}
+
+/// Token metadata shown to its owner
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Unix seconds
+    pub expires_at: u64,
+    pub services: Option<Vec<String>>,
+}
+
+/// What a presented token stands for
+#[derive(Debug, Clone)]
+pub struct ResolvedToken {
+    pub claims: Claims,
+    pub provider: String,
+    pub services: Option<Vec<String>>,
+}
+
+/// File-backed personal access token store
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: RwLock<Vec<StoredToken>>,
+}
+
+impl TokenStore {
+    /// Open the store, starting empty if the file does not exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<StoreFile>(&content)
+                .with_context(|| format!("Invalid token store {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StoreFile::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        tracing::info!(
+            path = %path.display(),
+            tokens = file.tokens.len(),
+            "Personal access token store opened"
+        );
+        Ok(Self {
+            path,
+            tokens: RwLock::new(file.tokens),
+        })
+    }
+
+    /// Mint a token for the session's claims; returns the token (shown once)
+    pub async fn mint(
+        &self,
+        claims: &Claims,
+        provider: &str,
+        name: &str,
+        ttl: Duration,
+        services: Option<Vec<String>>,
+    ) -> Result<(String, TokenInfo)> {
+        let mut tokens = self.tokens.write().await;
+        let now = unix_now();
+        tokens.retain(|t| t.expires_at > now);
+
+        let owned = tokens
+            .iter()
+            .filter(|t| t.belongs_to(provider, &claims.sub))
+            .count();
+        if owned >= MAX_TOKENS_PER_USER {
+            anyhow::bail!(
+                "Token limit reached ({} per user), revoke one first",
+                MAX_TOKENS_PER_USER
+            );
+        }
+
+        let id = hex(&random_bytes::<8>());
+        let secret = base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            random_bytes::<32>(),
+        );
+        let token = StoredToken {
+            id: id.clone(),
+            name: name.to_string(),
+            hash: hash_secret(&secret),
+            provider: provider.to_string(),
+            claims: claims.clone(),
+            created_at: now,
+            expires_at: now + ttl.as_secs(),
+            services,
+        };
+        let info = token.info();
+        tokens.push(token);
+        self.save(&tokens).await?;
+
+        tracing::info!(
+            event = "personal_access_token_minted",
+            token_id = %id,
+            sub = %claims.sub,
+            provider = %provider,
+            expires_at = info.expires_at,
+            "Personal access token minted"
+        );
+        Ok((format!("{}{}_{}", TOKEN_PREFIX, id, secret), info))
+    }
+
+    /// The user's live tokens, newest first
+    pub async fn list(&self, provider: &str, sub: &str) -> Vec<TokenInfo> {
+        let now = unix_now();
+        let tokens = self.tokens.read().await;
+        let mut owned: Vec<TokenInfo> = tokens
+            .iter()
+            .filter(|t| t.belongs_to(provider, sub) && t.expires_at > now)
+            .map(StoredToken::info)
+            .collect();
+        owned.sort_by_key(|t| std::cmp::Reverse(t.created_at));
+        owned
+    }
+
+    /// Revoke one of the user's tokens; returns false if they own no such token
+    pub async fn revoke(&self, provider: &str, sub: &str, id: &str) -> Result<bool> {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|t| !(t.id == id && t.belongs_to(provider, sub)));
+        if tokens.len() == before {
+            return Ok(false);
+        }
+        self.save(&tokens).await?;
+
+        tracing::info!(
+            event = "personal_access_token_revoked",
+            token_id = %id,
+            sub = %sub,
+            provider = %provider,
+            "Personal access token revoked"
+        );
+        Ok(true)
+    }
+
+    /// Resolve a presented token, if it exists and has not expired
+    pub async fn resolve(&self, token: &str) -> Option<ResolvedToken> {
+        let (id, secret) = token.strip_prefix(TOKEN_PREFIX)?.split_once('_')?;
+        let hash = hash_secret(secret);
+
+        let tokens = self.tokens.read().await;
+        // Looked up by id; the secret is high-entropy, so comparing its
+        // SHA-256 with == leaks nothing useful about the stored hash
+        let stored = tokens.iter().find(|t| t.id == id && t.hash == hash)?;
+        if stored.expires_at <= unix_now() {
+            tracing::debug!(token_id = %id, "Personal access token expired");
+            return None;
+        }
+
+        let mut claims = stored.claims.clone();
+        claims.exp = stored.expires_at as usize;
+        Some(ResolvedToken {
+            claims,
+            provider: stored.provider.clone(),
+            services: stored.services.clone(),
+        })
+    }
+
+    /// Write the file atomically (temp file + rename), dropping expired tokens
+    ///
+    /// Callers hold the write lock, so writes reach the file in order; the
+    /// file I/O itself runs on the blocking pool.
+    async fn save(&self, tokens: &[StoredToken]) -> Result<()> {
+        let now = unix_now();
+        let file = StoreFile {
+            tokens: tokens
+                .iter()
+                .filter(|t| t.expires_at > now)
+                .cloned()
+                .collect(),
+        };
+        let content = serde_json::to_string_pretty(&file)?;
+
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || write_atomically(&path, content.as_bytes()))
+            .await
+            .context("Token store write task failed")?
+    }
+}
+
+/// Replace `path` with `content` via a temp file only the portal user can read
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let tmp = temp_path(path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // A leftover temp file keeps its old mode; start from a fresh one
+    let _ = std::fs::remove_file(&tmp);
+    options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(content))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex(&Sha256::digest(secret.as_bytes()))
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format unix seconds as a UTC date (`YYYY-MM-DD`) for display
+pub fn format_unix_date(secs: u64) -> String {
+    // Civil-from-days (Howard Hinnant), valid for all dates after 1970
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(sub: &str) -> Claims {
+        serde_json::from_value(serde_json::json!({
+            "sub": sub,
+            "exp": 0,
+            "preferred_username": sub,
+            "realm_access": { "roles": ["dev"] }
+        }))
+        .unwrap()
+    }
+
+    fn store(name: &str) -> (TokenStore, PathBuf) {
+        let path = std::env::temp_dir().join(format!("test_tokens_{}.json", name));
+        std::fs::remove_file(&path).ok();
+        (TokenStore::open(&path).unwrap(), path)
+    }
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    #[tokio::test]
+    async fn test_mint_resolve_and_revoke() {
+        let (store, path) = store("mint");
+        let (token, info) = store
+            .mint(
+                &claims("alice"),
+                "default",
+                "ci",
+                DAY,
+                Some(vec!["grafana".to_string()]),
+            )
+            .await
+            .unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(info.name, "ci");
+
+        let resolved = store.resolve(&token).await.unwrap();
+        assert_eq!(resolved.claims.sub, "alice");
+        assert_eq!(resolved.claims.roles(), vec!["dev"]);
+        assert_eq!(resolved.claims.exp as u64, info.expires_at);
+        assert_eq!(resolved.provider, "default");
+        assert_eq!(resolved.services, Some(vec!["grafana".to_string()]));
+
+        // Only a hash is stored, and the file survives a restart
+        let content = std::fs::read_to_string(&path).unwrap();
+        let secret = token[TOKEN_PREFIX.len()..].split_once('_').unwrap().1;
+        assert!(!content.contains(secret));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let reopened = TokenStore::open(&path).unwrap();
+        assert!(reopened.resolve(&token).await.is_some());
+
+        // Other users cannot revoke it
+        assert!(!store.revoke("default", "bob", &info.id).await.unwrap());
+        assert!(store.revoke("default", "alice", &info.id).await.unwrap());
+        assert!(store.resolve(&token).await.is_none());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_tampered_and_expired_tokens() {
+        let (store, path) = store("reject");
+        let (token, _) = store
+            .mint(&claims("alice"), "default", "ci", DAY, None)
+            .await
+            .unwrap();
+        assert!(store.resolve(&format!("{}x", token)).await.is_none());
+        assert!(store.resolve("pat_nope").await.is_none());
+        assert!(store.resolve("eyJhbGciOi.x.y").await.is_none());
+
+        let (expired, _) = store
+            .mint(&claims("alice"), "default", "old", Duration::ZERO, None)
+            .await
+            .unwrap();
+        assert!(store.resolve(&expired).await.is_none());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_list_is_per_user_and_limited() {
+        let (store, path) = store("list");
+        for i in 0..MAX_TOKENS_PER_USER {
+            store
+                .mint(&claims("alice"), "default", &format!("t{}", i), DAY, None)
+                .await
+                .unwrap();
+        }
+        let error = store
+            .mint(&claims("alice"), "default", "extra", DAY, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Token limit reached"));
+
+        assert_eq!(
+            store.list("default", "alice").await.len(),
+            MAX_TOKENS_PER_USER
+        );
+        // Same sub at another provider is another user
+        assert!(store.list("contractors", "alice").await.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_format_unix_date() {
+        assert_eq!(format_unix_date(0), "1970-01-01");
+        assert_eq!(format_unix_date(951_782_400), "2000-02-29");
+        assert_eq!(format_unix_date(4_102_444_800), "2100-01-01");
+    }
+}
@@ -0,0 +1,224 @@
+//! `portal check-config`: validate the configuration without serving
+//!
+//! Loads the configuration exactly as the server does, prints every setting
+//! in use with where it came from (secrets redacted) and all invalid values,
+//! then checks that each identity provider's Keycloak internal URL and the
+//! descriptor source can be reached, and that the TLS certificate (if any)
+//! loads. Exits with 1 if anything failed.
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use crate::config::{Config, EffectiveSetting, IdentityProviderConfig, Settings};
+use crate::services;
+use crate::telemetry::trace_headers;
+use crate::tls::Certificates;
+
+/// Outcome of one connectivity check
+#[derive(Debug)]
+pub struct Check {
+    pub target: String,
+    pub result: Result<String, String>,
+}
+
+/// One `NAME = value  (origin)` line per setting
+pub fn render_settings(settings: &[EffectiveSetting]) -> String {
+    let width = settings.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    settings
+        .iter()
+        .map(|s| {
+            format!(
+                "  {:width$} = {}  ({})\n",
+                s.name,
+                s.value,
+                s.origin,
+                width = width
+            )
+        })
+        .collect()
+}
+
+/// Fetch the provider's JWKS over the internal Keycloak URL, as the portal does
+async fn check_keycloak(
+    client: &reqwest::Client,
+    provider: &IdentityProviderConfig,
+) -> Result<String, String> {
+    let url = format!(
+        "{}/realms/{}/protocol/openid-connect/certs",
+        provider.keycloak_url.trim_end_matches('/'),
+        provider.realm
+    );
+    let response = client
+        .get(&url)
+        .headers(trace_headers())
+        .send()
+        .await
+        .map_err(|e| format!("{}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{}: HTTP {}", url, response.status()));
+    }
+    let jwks: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("{}: invalid JWKS: {}", url, e))?;
+    let keys = jwks["keys"].as_array().map_or(0, Vec::len);
+    Ok(format!("{} ({} keys)", url, keys))
+}
+
+/// Check every identity provider, the descriptor source and the TLS certificate
+pub async fn check_connectivity(config: &Config) -> Vec<Check> {
+    let client = match reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.http_connect_timeout_secs))
+        .timeout(Duration::from_secs(config.http_request_timeout_secs))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            return vec![Check {
+                target: "http client".to_string(),
+                result: Err(e.to_string()),
+            }]
+        }
+    };
+
+    let mut checks = Vec::new();
+    for provider in &config.identity_providers {
+        checks.push(Check {
+            target: format!("keycloak ({})", provider.id),
+            result: check_keycloak(&client, provider).await,
+        });
+    }
+    checks.push(Check {
+        target: "descriptor".to_string(),
+        result: services::check_descriptor_source(config)
+            .await
+            .map(|summary| summary.to_string())
+            .map_err(|e| format!("{:#}", e)),
+    });
+    if let Some(tls) = &config.tls {
+        checks.push(Check {
+            target: "tls certificate".to_string(),
+            result: Certificates::load(tls)
+                .map(|_| tls.cert_path.display().to_string())
+                .map_err(|e| format!("{:#}", e)),
+        });
+    }
+    checks
+}
+
+/// Run the subcommand and print the report on stdout
+pub async fn run() -> ExitCode {
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let config = Config::from_settings(&settings);
+
+    println!("Effective configuration:");
+    print!("{}", render_settings(&settings.effective()));
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            println!("\n{:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("\nConnectivity:");
+    let checks = check_connectivity(&config).await;
+    for check in &checks {
+        match &check.result {
+            Ok(detail) => println!("  ok      {}: {}", check.target, detail),
+            Err(error) => println!("  FAILED  {}: {}", check.target, error),
+        }
+    }
+    if checks.iter().all(|check| check.result.is_ok()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use std::collections::BTreeMap;
+
+    const DESCRIPTOR: &str = r#"{
+        "version": "1",
+        "deploymentId": "local",
+        "environment": "dev",
+        "baseDomain": "localhost",
+        "portal": { "publicUrl": "http://localhost" },
+        "keycloak": {
+            "publicUrl": "http://keycloak.localhost",
+            "issuerUrl": "http://keycloak.localhost/realms/dev",
+            "realm": "dev"
+        },
+        "services": []
+    }"#;
+
+    fn config(keycloak_url: &str, descriptor: &str) -> Config {
+        let env: BTreeMap<String, String> = [
+            ("KEYCLOAK_URL", keycloak_url),
+            ("KEYCLOAK_CALLBACK_URL", "http://keycloak.localhost"),
+            ("KEYCLOAK_REALM", "dev"),
+            ("CLIENT_ID", "portal"),
+            ("CLIENT_SECRET", "secret"),
+            ("REDIRECT_URI", "http://portal.localhost/auth/callback"),
+            ("PORTAL_DESCRIPTOR_JSON", descriptor),
+            ("HTTP_CONNECT_TIMEOUT_SECS", "1"),
+            ("HTTP_REQUEST_TIMEOUT_SECS", "2"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        Config::from_settings(&Settings::new(env, None).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connectivity() {
+        // Keycloak stand-in serving the realm's JWKS
+        let app = Router::new().route(
+            "/realms/dev/protocol/openid-connect/certs",
+            get(|| async { Json(serde_json::json!({ "keys": [{ "kid": "a" }] })) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let checks = check_connectivity(&config(&url, DESCRIPTOR)).await;
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].target, "keycloak (default)");
+        assert!(checks[0].result.as_ref().unwrap().ends_with("(1 keys)"));
+        assert!(checks[1].result.is_ok(), "{:?}", checks[1]);
+
+        // Nothing listens on port 9; the descriptor is invalid
+        let checks = check_connectivity(&config("http://127.0.0.1:9", "{}")).await;
+        assert!(checks.iter().all(|check| check.result.is_err()));
+    }
+
+    #[test]
+    fn test_render_settings() {
+        let settings = [
+            EffectiveSetting {
+                name: "CLIENT_SECRET".to_string(),
+                value: "<redacted>".to_string(),
+                origin: crate::config::Origin::Env,
+            },
+            EffectiveSetting {
+                name: "SERVER_PORT".to_string(),
+                value: "3000".to_string(),
+                origin: crate::config::Origin::Default,
+            },
+        ];
+        assert_eq!(
+            render_settings(&settings),
+            "  CLIENT_SECRET = <redacted>  (env)\n  SERVER_PORT   = 3000  (default)\n"
+        );
+    }
+}
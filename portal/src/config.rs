@@ -1,4 +1,17 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::auth::helpers::is_valid_idp_hint;
+use crate::rate_limit::{EndpointClass, IpRange, RateLimitConfig};
+use crate::secrets::{self, EncryptedStore, Secret, SecretSource};
+use crate::services::is_slug;
+use crate::telemetry::TelemetryConfig;
+use crate::tls::TlsConfig;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Environment {
@@ -13,12 +26,85 @@ pub enum DescriptorSource {
     Json(String),
     /// Descriptor loaded from file path via PORTAL_DESCRIPTOR_PATH env var
     File(String),
+    /// Descriptor fetched over HTTP(S) via PORTAL_DESCRIPTOR_URL env var (polled)
+    Url(String),
 }
 
 /// Descriptor configuration
 #[derive(Debug, Clone)]
 pub struct DescriptorConfig {
     pub source: DescriptorSource,
+    /// Poll interval for the URL source (in seconds)
+    pub poll_interval_secs: u64,
+    /// Base64 Ed25519 public key; when set, descriptors must be signed with the matching key
+    pub public_key: Option<String>,
+}
+
+/// Default Content-Security-Policy (`{nonce}` is replaced per request)
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self' 'nonce-{nonce}'; img-src 'self' data:; object-src 'none'; base-uri 'none'";
+pub const DEFAULT_HSTS: &str = "max-age=31536000; includeSubDomains";
+pub const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+pub const DEFAULT_PERMISSIONS_POLICY: &str =
+    "camera=(), microphone=(), geolocation=(), payment=(), usb=()";
+pub const DEFAULT_FRAME_ANCESTORS: &str = "'none'";
+
+/// Security response headers (None = header not sent)
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersConfig {
+    /// `Strict-Transport-Security`, only sent in production
+    pub hsts: Option<String>,
+    /// `Content-Security-Policy`; `{nonce}` is replaced by the request's nonce
+    pub content_security_policy: Option<String>,
+    /// `frame-ancestors` directive appended to the CSP
+    pub frame_ancestors: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+}
+
+/// An OIDC identity provider (Keycloak realm + client) users can sign in with
+#[derive(Debug, Clone)]
+pub struct IdentityProviderConfig {
+    /// Stable slug used in `/auth/login?provider=` and descriptor `identityProviders`
+    pub id: String,
+    /// Label on the landing page provider chooser
+    pub display_name: String,
+    pub keycloak_url: String, // Internal URL for server-to-server (http://keycloak:8080)
+    pub keycloak_callback_url: String, // Public URL for browser redirects (http://keycloak.localhost)
+    pub realm: String,
+    pub client_id: String,
+    /// Re-read periodically when it comes from a file (see [`crate::secrets`])
+    pub client_secret: Secret,
+    /// Audiences accepted besides `client_id` (e.g. a `portal-api` client for bearer tokens)
+    pub accepted_audiences: Vec<String>,
+    /// Keycloak-brokered identity providers offered as landing page buttons
+    pub brokers: Vec<BrokeredIdp>,
+}
+
+/// A Keycloak-brokered identity provider (e.g. GitHub), passed as `kc_idp_hint`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokeredIdp {
+    /// Identity provider alias in Keycloak
+    pub alias: String,
+    /// Button label (defaults to the alias)
+    pub display_name: String,
+}
+
+impl IdentityProviderConfig {
+    /// Audiences a token must carry one of: `client_id` plus `accepted_audiences`
+    pub fn audiences(&self) -> Vec<String> {
+        let mut audiences = vec![self.client_id.clone()];
+        audiences.extend(self.accepted_audiences.iter().cloned());
+        audiences
+    }
+
+    /// Issuer URL of tokens minted by this provider (`{public_url}/realms/{realm}`)
+    pub fn issuer(&self) -> String {
+        format!(
+            "{}/realms/{}",
+            self.keycloak_callback_url.trim_end_matches('/'),
+            self.realm
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,19 +112,26 @@ pub struct Config {
     // Environment configuration
     pub environment: Environment,
 
+    // Log format and OpenTelemetry export
+    pub telemetry: TelemetryConfig,
+
     // Server configuration
     pub server_host: String,
     pub server_port: u16,
 
+    // Native HTTPS listener (None = plain HTTP, TLS terminated by Traefik)
+    pub tls: Option<TlsConfig>,
+
+    // Separate listener for health, readiness, metrics and debug endpoints
+    // (None = health and metrics are served by the public listener only)
+    pub admin_bind_address: Option<SocketAddr>,
+
     // Portal public URL (for logout redirects)
     pub portal_public_url: String,
 
-    // Keycloak configuration
-    pub keycloak_url: String, // Internal URL for server-to-server (http://keycloak:8080)
-    pub keycloak_callback_url: String, // Public URL for browser redirects (http://keycloak.localhost)
-    pub keycloak_realm: String,
-    pub client_id: String,
-    pub client_secret: String,
+    // Identity providers (never empty; the first one is the default provider
+    // configured by KEYCLOAK_URL, KEYCLOAK_REALM, CLIENT_ID, ...)
+    pub identity_providers: Vec<IdentityProviderConfig>,
     pub redirect_uri: String,
 
     // Cookie configuration (None = host-only cookie, Some = domain cookie)
@@ -63,44 +156,82 @@ pub struct Config {
 
     // Descriptor configuration (replaces service discovery)
     pub descriptor: DescriptorConfig,
+
+    // Read-only descriptors of other deployments shown on the dashboard
+    // (file paths or http(s) URLs, in display order)
+    pub federated_descriptors: Vec<String>,
+
+    // Personal access token store (JSON file; None = tokens disabled)
+    pub token_store_path: Option<String>,
+
+    // Security audit log (JSON lines; None = disabled), rotated by size
+    pub audit_log_path: Option<String>,
+    pub audit_log_max_bytes: Option<u64>,
+    pub audit_log_max_files: usize,
+
+    // Security response headers (CSP, HSTS, ...), set by the web middleware
+    pub security_headers: SecurityHeadersConfig,
+
+    // Per-IP rate limits of the auth endpoints
+    pub rate_limits: RateLimitConfig,
+
+    // How often file-backed secrets are re-read (in seconds; 0 = never)
+    pub secret_refresh_secs: u64,
+
+    // Graceful shutdown (in seconds): time to keep serving after /readyz
+    // turns 503, then the limit for in-flight requests to finish
+    pub shutdown_drain_secs: u64,
+    pub shutdown_timeout_secs: u64,
 }
 
 impl Config {
-    /// Load configuration from environment variables using std::env::var
-    pub fn load() -> anyhow::Result<Self> {
+    /// Build the configuration from `settings` (see [`Settings`])
+    ///
+    /// Every invalid or missing setting is reported in one error.
+    pub fn from_settings(settings: &Settings) -> anyhow::Result<Self> {
         // Parse environment type
-        let environment = match env::var("ENVIRONMENT")
-            .unwrap_or_else(|_| "development".to_string())
+        let environment = match settings
+            .string_or("ENVIRONMENT", "development")
             .to_lowercase()
             .as_str()
         {
             "production" | "prod" => Environment::Production,
-            _ => Environment::Development,
+            "development" | "dev" => Environment::Development,
+            other => {
+                settings.error(format!(
+                    "ENVIRONMENT must be 'development' or 'production', got '{}'",
+                    other
+                ));
+                Environment::Development
+            }
         };
 
-        // Required variables
-        let keycloak_url = env::var("KEYCLOAK_URL")
-            .map_err(|_| anyhow::anyhow!("KEYCLOAK_URL environment variable is required"))?;
-
-        let keycloak_callback_url = env::var("KEYCLOAK_CALLBACK_URL").map_err(|_| {
-            anyhow::anyhow!("KEYCLOAK_CALLBACK_URL environment variable is required")
-        })?;
-
-        let keycloak_realm = env::var("KEYCLOAK_REALM")
-            .map_err(|_| anyhow::anyhow!("KEYCLOAK_REALM environment variable is required"))?;
+        // Logging and trace export (also read by main before the subscriber is installed)
+        let telemetry = TelemetryConfig::from_settings(settings);
 
-        let client_id = env::var("CLIENT_ID")
-            .map_err(|_| anyhow::anyhow!("CLIENT_ID environment variable is required"))?;
+        // Required variables
+        let keycloak_url = settings.required("KEYCLOAK_URL");
+        let keycloak_callback_url = settings.required("KEYCLOAK_CALLBACK_URL");
+        let keycloak_realm = settings.required("KEYCLOAK_REALM");
+        let client_id = settings.required("CLIENT_ID");
 
-        let client_secret = env::var("CLIENT_SECRET")
-            .map_err(|_| anyhow::anyhow!("CLIENT_SECRET environment variable is required"))?;
+        // Sensitive settings can come from files or the encrypted secrets file
+        let secrets_store = settings.string("PORTAL_SECRETS_FILE").and_then(|path| {
+            let key = settings.secret_source("PORTAL_SECRETS_KEY", None);
+            if key.is_none() {
+                settings.error(
+                    "PORTAL_SECRETS_KEY or PORTAL_SECRETS_KEY_FILE is required with PORTAL_SECRETS_FILE",
+                );
+            }
+            settings.check(EncryptedStore::open(&path, key?))
+        });
+        let client_secret = settings.secret("CLIENT_SECRET", secrets_store.as_ref());
 
-        let redirect_uri = env::var("REDIRECT_URI")
-            .map_err(|_| anyhow::anyhow!("REDIRECT_URI environment variable is required"))?;
+        let redirect_uri = settings.required("REDIRECT_URI");
 
         // Portal public URL - derive from REDIRECT_URI by stripping the path
         // e.g., http://portal.localhost/auth/callback -> http://portal.localhost
-        let portal_public_url = env::var("PORTAL_PUBLIC_URL").unwrap_or_else(|_| {
+        let portal_public_url = settings.string("PORTAL_PUBLIC_URL").unwrap_or_else(|| {
             // Derive from redirect_uri by finding the third slash
             redirect_uri
                 .find("://")
@@ -113,70 +244,154 @@ impl Config {
         });
 
         // Optional variables with defaults
-        let server_host = env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-
-        let server_port = env::var("SERVER_PORT")
-            .ok()
-            .and_then(|s| s.parse::<u16>().ok())
-            .unwrap_or(3000);
+        let server_host = settings.string_or("SERVER_HOST", "0.0.0.0");
+        let server_port = settings.parse_or("SERVER_PORT", 3000u16);
+        let tls = TlsConfig::from_settings(settings);
+        let admin_bind_address = settings.parse::<SocketAddr>("ADMIN_BIND_ADDRESS");
 
         // Cookie domain: if not set or empty, use host-only cookies (no Domain attribute)
-        let cookie_domain = env::var("COOKIE_DOMAIN").ok().filter(|s| !s.is_empty());
+        let cookie_domain = settings.string("COOKIE_DOMAIN");
 
-        let http_connect_timeout_secs = env::var("HTTP_CONNECT_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(10);
-
-        let http_request_timeout_secs = env::var("HTTP_REQUEST_TIMEOUT_SECS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(30);
-
-        let jwks_cache_ttl_secs = env::var("JWKS_CACHE_TTL_SECS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(3600);
+        let http_connect_timeout_secs = settings.parse_or("HTTP_CONNECT_TIMEOUT_SECS", 10u64);
+        let http_request_timeout_secs = settings.parse_or("HTTP_REQUEST_TIMEOUT_SECS", 30u64);
+        let jwks_cache_ttl_secs = settings.parse_or("JWKS_CACHE_TTL_SECS", 3600u64);
 
         // Logout reachability probe timeouts (per plan.md 2.8.1)
         // Short timeouts to keep logout fast; defaults: 300ms connect, 750ms total
-        let logout_probe_connect_timeout_ms = env::var("LOGOUT_PROBE_CONNECT_TIMEOUT_MS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(300);
-
-        let logout_probe_request_timeout_ms = env::var("LOGOUT_PROBE_REQUEST_TIMEOUT_MS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(750);
+        let logout_probe_connect_timeout_ms =
+            settings.parse_or("LOGOUT_PROBE_CONNECT_TIMEOUT_MS", 300u64);
+        let logout_probe_request_timeout_ms =
+            settings.parse_or("LOGOUT_PROBE_REQUEST_TIMEOUT_MS", 750u64);
 
         // Internal Traefik URL for reachability probes
         // e.g., http://local-traefik:80 or http://traefik:80
-        let traefik_internal_url = env::var("TRAEFIK_INTERNAL_URL")
-            .ok()
-            .filter(|s| !s.is_empty());
+        let traefik_internal_url = settings.string("TRAEFIK_INTERNAL_URL");
 
-        // Descriptor configuration (primary: JSON env var, fallback: file path)
-        let descriptor_source = if let Ok(json) = env::var("PORTAL_DESCRIPTOR_JSON") {
+        // Descriptor configuration (primary: JSON env var, then file path, then URL)
+        let descriptor_source = if let Some(json) = settings.string("PORTAL_DESCRIPTOR_JSON") {
             DescriptorSource::Json(json)
-        } else if let Ok(path) = env::var("PORTAL_DESCRIPTOR_PATH") {
+        } else if let Some(path) = settings.string("PORTAL_DESCRIPTOR_PATH") {
             DescriptorSource::File(path)
+        } else if let Some(url) = settings.string("PORTAL_DESCRIPTOR_URL") {
+            DescriptorSource::Url(url)
         } else {
-            return Err(anyhow::anyhow!(
-                "Either PORTAL_DESCRIPTOR_JSON or PORTAL_DESCRIPTOR_PATH environment variable is required"
-            ));
+            settings.error(
+                "One of PORTAL_DESCRIPTOR_JSON, PORTAL_DESCRIPTOR_PATH or PORTAL_DESCRIPTOR_URL is required",
+            );
+            DescriptorSource::Json(String::new())
+        };
+
+        // Descriptor signing key (required in production so unsigned descriptors are refused)
+        let descriptor_public_key = settings.string("PORTAL_DESCRIPTOR_PUBLIC_KEY");
+        if environment == Environment::Production && descriptor_public_key.is_none() {
+            settings.error(
+                "PORTAL_DESCRIPTOR_PUBLIC_KEY is required in production (descriptors must be signed)",
+            );
+        }
+
+        let descriptor_poll_interval_secs =
+            settings.parse_or("PORTAL_DESCRIPTOR_POLL_INTERVAL_SECS", 60u64);
+        if descriptor_poll_interval_secs == 0 {
+            settings.error("PORTAL_DESCRIPTOR_POLL_INTERVAL_SECS must be greater than 0");
+        }
+
+        // Federated descriptors: comma-separated file paths or URLs
+        let federated_descriptors = settings.list("PORTAL_FEDERATED_DESCRIPTORS");
+
+        // Personal access tokens are only offered when a store path is set
+        let token_store_path = settings.string("PORTAL_TOKEN_STORE_PATH");
+
+        // Security audit log, separate from application logs (max bytes 0 = no rotation)
+        let audit_log_path = settings.string("PORTAL_AUDIT_LOG_PATH");
+        let audit_log_max_bytes = settings
+            .parse::<u64>("PORTAL_AUDIT_LOG_MAX_BYTES")
+            .filter(|bytes| *bytes > 0);
+        let audit_log_max_files = settings.parse_or("PORTAL_AUDIT_LOG_MAX_FILES", 5usize);
+
+        // Security headers: unset = default, empty = not sent
+        let security_headers = SecurityHeadersConfig {
+            hsts: settings.header("PORTAL_HSTS", DEFAULT_HSTS),
+            content_security_policy: settings.header(
+                "PORTAL_CONTENT_SECURITY_POLICY",
+                DEFAULT_CONTENT_SECURITY_POLICY,
+            ),
+            frame_ancestors: settings.header("PORTAL_FRAME_ANCESTORS", DEFAULT_FRAME_ANCESTORS),
+            referrer_policy: settings.header("PORTAL_REFERRER_POLICY", DEFAULT_REFERRER_POLICY),
+            permissions_policy: settings
+                .header("PORTAL_PERMISSIONS_POLICY", DEFAULT_PERMISSIONS_POLICY),
+        };
+
+        // Rate limits: requests per minute per client IP and endpoint class
+        let mut per_minute = [0; 4];
+        for (i, class) in EndpointClass::ALL.into_iter().enumerate() {
+            let name = format!("PORTAL_RATE_LIMIT_{}", class.name().to_uppercase());
+            per_minute[i] = settings.parse_or(
+                &name,
+                match class {
+                    EndpointClass::Login | EndpointClass::Callback => 30u32,
+                    EndpointClass::Logout | EndpointClass::Device => 60,
+                },
+            );
+        }
+        let trusted_proxies = settings
+            .list("PORTAL_TRUSTED_PROXIES")
+            .into_iter()
+            .filter_map(|range| {
+                let parsed = IpRange::parse(&range);
+                if parsed.is_none() {
+                    settings.error(format!(
+                        "PORTAL_TRUSTED_PROXIES: invalid IP range '{}'",
+                        range
+                    ));
+                }
+                parsed
+            })
+            .collect();
+
+        let secret_refresh_secs = settings.parse_or("PORTAL_SECRET_REFRESH_SECS", 60u64);
+
+        let shutdown_drain_secs = settings.parse_or("PORTAL_SHUTDOWN_DRAIN_SECS", 5u64);
+        let shutdown_timeout_secs = settings.parse_or("PORTAL_SHUTDOWN_TIMEOUT_SECS", 20u64);
+
+        // Default identity provider from the KEYCLOAK_* / CLIENT_* variables
+        let default_provider = IdentityProviderConfig {
+            id: settings.string_or("PORTAL_IDENTITY_PROVIDER_ID", "default"),
+            display_name: settings.string_or("PORTAL_IDENTITY_PROVIDER_NAME", &keycloak_realm),
+            keycloak_url,
+            keycloak_callback_url,
+            realm: keycloak_realm,
+            client_id,
+            client_secret,
+            accepted_audiences: settings.list("PORTAL_ACCEPTED_AUDIENCES"),
+            brokers: settings.brokers("PORTAL_IDENTITY_PROVIDER_BROKERS"),
         };
 
+        // Additional identity providers: comma-separated ids, each configured
+        // with PORTAL_IDP_<ID>_* variables
+        let mut identity_providers = vec![default_provider];
+        for id in settings.list("PORTAL_IDENTITY_PROVIDERS") {
+            let provider = additional_identity_provider(
+                settings,
+                &id,
+                &identity_providers[0],
+                secrets_store.as_ref(),
+            );
+            identity_providers.push(provider);
+        }
+
+        // Report every invalid setting at once, then the cross-setting checks
+        settings.finish()?;
+        check_identity_providers(&identity_providers)?;
+
         Ok(Config {
             environment,
+            telemetry,
             server_host,
             server_port,
+            tls,
+            admin_bind_address,
             portal_public_url,
-            keycloak_url,
-            keycloak_callback_url,
-            keycloak_realm,
-            client_id,
-            client_secret,
+            identity_providers,
             redirect_uri,
             cookie_domain,
             http_connect_timeout_secs,
@@ -187,18 +402,57 @@ impl Config {
             traefik_internal_url,
             descriptor: DescriptorConfig {
                 source: descriptor_source,
+                poll_interval_secs: descriptor_poll_interval_secs,
+                public_key: descriptor_public_key,
+            },
+            federated_descriptors,
+            token_store_path,
+            audit_log_path,
+            audit_log_max_bytes,
+            audit_log_max_files,
+            security_headers,
+            rate_limits: RateLimitConfig {
+                per_minute,
+                trusted_proxies,
             },
+            secret_refresh_secs,
+            shutdown_drain_secs,
+            shutdown_timeout_secs,
         })
     }
 
+    /// The provider configured by the KEYCLOAK_* / CLIENT_* variables
+    pub fn default_identity_provider(&self) -> &IdentityProviderConfig {
+        &self.identity_providers[0]
+    }
+
+    /// Look up an identity provider by id
+    pub fn identity_provider(&self, id: &str) -> Option<&IdentityProviderConfig> {
+        self.identity_providers.iter().find(|p| p.id == id)
+    }
+
     /// Check if running in production mode
     pub fn is_production(&self) -> bool {
         self.environment == Environment::Production
     }
 
-    /// Get cookie security flags based on environment
+    /// Whether the public listener serves /healthz, /readyz and /metrics
+    ///
+    /// In production they are only on the admin listener when one is
+    /// configured, so Traefik cannot expose them.
+    pub fn serves_health_publicly(&self) -> bool {
+        !(self.is_production() && self.admin_bind_address.is_some())
+    }
+
+    /// Whether browsers reach the portal over HTTPS: its own TLS listener,
+    /// or Traefik in production
+    pub fn is_https(&self) -> bool {
+        self.tls.is_some() || self.is_production()
+    }
+
+    /// Get cookie security flags based on the scheme the portal is served over
     pub fn cookie_secure_flag(&self) -> &str {
-        if self.is_production() {
+        if self.is_https() {
             "; Secure"
         } else {
             ""
@@ -218,3 +472,560 @@ impl Config {
         format!("{}:{}", self.server_host, self.server_port)
     }
 }
+
+// =============================================================================
+// Settings sources
+// =============================================================================
+
+/// Where an effective setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Env,
+    File,
+    Default,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Origin::Env => "env",
+            Origin::File => "file",
+            Origin::Default => "default",
+        })
+    }
+}
+
+/// A setting as used by the configuration (secrets are redacted)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveSetting {
+    pub name: String,
+    pub value: String,
+    pub origin: Origin,
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Raw settings: environment variables over the optional TOML config file
+/// (PORTAL_CONFIG_FILE)
+///
+/// Settings are looked up by environment variable name. In the file, keys
+/// are the lower-case names and tables are joined with `_`, so `[server]
+/// port = 3000` is SERVER_PORT; arrays are comma-separated lists.
+///
+/// Lookups record invalid values instead of failing, and [`Settings::finish`]
+/// reports them together with file keys nothing asked for. Every setting
+/// used is kept for `portal check-config`.
+pub struct Settings {
+    env: BTreeMap<String, String>,
+    file: BTreeMap<String, String>,
+    used: RefCell<BTreeSet<String>>,
+    effective: RefCell<BTreeMap<String, (String, Origin)>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl Settings {
+    /// The process environment and the file named by PORTAL_CONFIG_FILE, if any
+    pub fn load() -> anyhow::Result<Self> {
+        let env: BTreeMap<String, String> = env::vars().collect();
+        let file = match env
+            .get("PORTAL_CONFIG_FILE")
+            .filter(|path| !path.is_empty())
+        {
+            Some(path) => Some(std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read PORTAL_CONFIG_FILE {}: {}", path, e)
+            })?),
+            None => None,
+        };
+        Self::new(env, file.as_deref())
+    }
+
+    /// Settings from `env` over the TOML document `file`
+    pub fn new(env: BTreeMap<String, String>, file: Option<&str>) -> anyhow::Result<Self> {
+        let mut values = BTreeMap::new();
+        if let Some(file) = file {
+            let table: toml::Table = file
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid config file: {}", e))?;
+            flatten_table("", &table, &mut values)?;
+        }
+        Ok(Self {
+            env,
+            file: values,
+            used: RefCell::default(),
+            effective: RefCell::default(),
+            errors: RefCell::default(),
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Option<(String, Origin)> {
+        self.used.borrow_mut().insert(name.to_string());
+        if let Some(value) = self.env.get(name) {
+            return Some((value.clone(), Origin::Env));
+        }
+        self.file
+            .get(name)
+            .map(|value| (value.clone(), Origin::File))
+    }
+
+    fn record(&self, name: &str, value: &str, origin: Origin) {
+        self.effective
+            .borrow_mut()
+            .insert(name.to_string(), (value.to_string(), origin));
+    }
+
+    /// Non-empty value of `name`
+    pub fn string(&self, name: &str) -> Option<String> {
+        let (value, origin) = self.lookup(name).filter(|(value, _)| !value.is_empty())?;
+        self.record(name, &value, origin);
+        Some(value)
+    }
+
+    pub fn string_or(&self, name: &str, default: &str) -> String {
+        self.string(name).unwrap_or_else(|| {
+            self.record(name, default, Origin::Default);
+            default.to_string()
+        })
+    }
+
+    /// Value of a required setting (empty and reported when missing)
+    pub fn required(&self, name: &str) -> String {
+        self.string(name).unwrap_or_else(|| {
+            self.error(format!("{} is required", name));
+            String::new()
+        })
+    }
+
+    /// Parsed value of `name` (None when unset or invalid)
+    pub fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        let value = self.string(name)?;
+        let parsed = value.trim().parse().ok();
+        if parsed.is_none() {
+            self.error(format!("{}: invalid value '{}'", name, value));
+        }
+        parsed
+    }
+
+    pub fn parse_or<T: FromStr + fmt::Display>(&self, name: &str, default: T) -> T {
+        if self.string(name).is_none() {
+            self.record(name, &default.to_string(), Origin::Default);
+            return default;
+        }
+        self.parse(name).unwrap_or(default)
+    }
+
+    /// Comma-separated list, dropping empty entries
+    pub fn list(&self, name: &str) -> Vec<String> {
+        split_list(&self.string(name).unwrap_or_default())
+    }
+
+    /// Header value: the default when unset, None when set to empty
+    pub fn header(&self, name: &str, default: &str) -> Option<String> {
+        let value = match self.lookup(name) {
+            Some((value, origin)) => {
+                self.record(name, value.trim(), origin);
+                value.trim().to_string()
+            }
+            None => {
+                self.record(name, default, Origin::Default);
+                default.to_string()
+            }
+        };
+        if value.is_empty() {
+            return None;
+        }
+        // Fail at startup rather than on the first response
+        if axum::http::HeaderValue::from_str(&value).is_err() {
+            self.error(format!("{} is not a valid header value", name));
+            return None;
+        }
+        Some(value)
+    }
+
+    /// Brokered IdPs (`alias[:Label]`, comma-separated)
+    pub fn brokers(&self, name: &str) -> Vec<BrokeredIdp> {
+        let value = self.string(name).unwrap_or_default();
+        self.check(parse_brokers(name, &value)).unwrap_or_default()
+    }
+
+    /// Where the sensitive setting `name` comes from (see [`crate::secrets`])
+    pub fn secret_source(
+        &self,
+        name: &str,
+        store: Option<&Arc<EncryptedStore>>,
+    ) -> Option<SecretSource> {
+        let file_var = format!("{}_FILE", name);
+        let resolved = secrets::resolve(
+            name,
+            |var| {
+                self.lookup(var)
+                    .map(|(value, _)| value)
+                    .filter(|v| !v.is_empty())
+            },
+            store,
+        );
+        let source = self.check(resolved)??;
+        match &source {
+            SecretSource::File(path) => {
+                let origin = self.lookup(&file_var).map_or(Origin::Env, |(_, o)| o);
+                self.record(&file_var, &path.to_string_lossy(), origin);
+            }
+            SecretSource::Value(_) => {
+                let origin = self.lookup(name).map_or(Origin::Env, |(_, o)| o);
+                self.record(name, REDACTED, origin);
+            }
+            SecretSource::Encrypted { .. } => self.record(
+                name,
+                &format!("{} (PORTAL_SECRETS_FILE)", REDACTED),
+                Origin::File,
+            ),
+        }
+        Some(source)
+    }
+
+    /// A required sensitive setting, read once now
+    pub fn secret(&self, name: &str, store: Option<&Arc<EncryptedStore>>) -> Secret {
+        let errors = self.errors.borrow().len();
+        let Some(source) = self.secret_source(name, store) else {
+            // Resolving already reported why when it failed
+            if self.errors.borrow().len() == errors {
+                self.error(format!(
+                    "{} is required (or {}_FILE, or an entry in PORTAL_SECRETS_FILE)",
+                    name, name
+                ));
+            }
+            return Secret::new(String::new());
+        };
+        self.check(Secret::load(name, source))
+            .unwrap_or_else(|| Secret::new(String::new()))
+    }
+
+    /// Report an invalid setting
+    pub fn error(&self, message: impl Into<String>) {
+        self.errors.borrow_mut().push(message.into());
+    }
+
+    /// The value of `result`, reporting its error
+    pub fn check<T>(&self, result: anyhow::Result<T>) -> Option<T> {
+        result.map_err(|e| self.error(format!("{:#}", e))).ok()
+    }
+
+    /// Fail with every reported error and every unknown config file key
+    pub fn finish(&self) -> anyhow::Result<()> {
+        let used = self.used.borrow();
+        let mut errors = self.errors.borrow().clone();
+        errors.extend(
+            self.file
+                .keys()
+                .filter(|name| !used.contains(*name))
+                .map(|name| {
+                    format!(
+                        "Unknown setting '{}' in the config file",
+                        name.to_lowercase()
+                    )
+                }),
+        );
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "Invalid configuration:\n  - {}",
+            errors.join("\n  - ")
+        ))
+    }
+
+    /// Settings used so far, by name
+    pub fn effective(&self) -> Vec<EffectiveSetting> {
+        self.effective
+            .borrow()
+            .iter()
+            .map(|(name, (value, origin))| EffectiveSetting {
+                name: name.clone(),
+                value: value.clone(),
+                origin: *origin,
+            })
+            .collect()
+    }
+}
+
+/// Add the entries of a config file table to `values`, keyed by setting name
+fn flatten_table(
+    prefix: &str,
+    table: &toml::Table,
+    values: &mut BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(d) => Some(d.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    };
+    for (key, value) in table {
+        let name = format!("{}{}", prefix, key.to_uppercase().replace('-', "_"));
+        match value {
+            toml::Value::Table(table) => flatten_table(&format!("{}_", name), table, values)?,
+            toml::Value::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(scalar)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Config file: {} must be a list of plain values", name)
+                    })?;
+                values.insert(name, items.join(","));
+            }
+            value => {
+                values.insert(name, scalar(value).unwrap_or_default());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load an additional identity provider from its PORTAL_IDP_<ID>_* variables
+///
+/// The Keycloak URLs default to the default provider's, since a second realm
+/// on the same Keycloak is the common case.
+fn additional_identity_provider(
+    settings: &Settings,
+    id: &str,
+    default: &IdentityProviderConfig,
+    secrets_store: Option<&Arc<EncryptedStore>>,
+) -> IdentityProviderConfig {
+    let prefix = format!("PORTAL_IDP_{}_", id.to_uppercase().replace('-', "_"));
+    let name = |suffix: &str| format!("{}{}", prefix, suffix);
+
+    IdentityProviderConfig {
+        id: id.to_string(),
+        display_name: settings.string_or(&name("NAME"), id),
+        keycloak_url: settings.string_or(&name("KEYCLOAK_URL"), &default.keycloak_url),
+        keycloak_callback_url: settings.string_or(
+            &name("KEYCLOAK_CALLBACK_URL"),
+            &default.keycloak_callback_url,
+        ),
+        realm: settings.required(&name("REALM")),
+        client_id: settings.required(&name("CLIENT_ID")),
+        client_secret: settings.secret(&name("CLIENT_SECRET"), secrets_store),
+        accepted_audiences: settings.list(&name("ACCEPTED_AUDIENCES")),
+        brokers: settings.brokers(&name("BROKERS")),
+    }
+}
+
+/// Split a comma-separated list, dropping empty entries
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse comma-separated `alias[:Display Name]` entries
+fn parse_brokers(name: &str, value: &str) -> anyhow::Result<Vec<BrokeredIdp>> {
+    split_list(value)
+        .iter()
+        .map(|entry| {
+            let (alias, display_name) = match entry.split_once(':') {
+                Some((alias, display_name)) => (alias.trim(), display_name.trim()),
+                None => (entry.as_str(), entry.as_str()),
+            };
+            if !is_valid_idp_hint(alias) {
+                return Err(anyhow::anyhow!(
+                    "Invalid identity provider alias '{}' in {}",
+                    alias,
+                    name
+                ));
+            }
+            Ok(BrokeredIdp {
+                alias: alias.to_string(),
+                display_name: display_name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Provider ids must be slugs and unique; issuers must be unique so tokens
+/// can be routed to exactly one provider
+fn check_identity_providers(providers: &[IdentityProviderConfig]) -> anyhow::Result<()> {
+    for (i, provider) in providers.iter().enumerate() {
+        if !is_slug(&provider.id) {
+            return Err(anyhow::anyhow!(
+                "Invalid identity provider id '{}' (expected a slug like 'contractors')",
+                provider.id
+            ));
+        }
+        for other in &providers[..i] {
+            if other.id == provider.id {
+                return Err(anyhow::anyhow!(
+                    "Duplicate identity provider id '{}'",
+                    provider.id
+                ));
+            }
+            if other.issuer() == provider.issuer() {
+                return Err(anyhow::anyhow!(
+                    "Identity providers '{}' and '{}' share the issuer {}",
+                    other.id,
+                    provider.id,
+                    provider.issuer()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The required settings, as environment variables
+    fn env(extra: &[(&str, &str)]) -> BTreeMap<String, String> {
+        [
+            ("KEYCLOAK_URL", "http://keycloak:8080"),
+            ("KEYCLOAK_CALLBACK_URL", "http://keycloak.localhost"),
+            ("KEYCLOAK_REALM", "dev"),
+            ("CLIENT_ID", "portal"),
+            ("CLIENT_SECRET", "hunter2"),
+            ("REDIRECT_URI", "http://portal.localhost/auth/callback"),
+            ("PORTAL_DESCRIPTOR_PATH", "/etc/portal/descriptor.json"),
+        ]
+        .iter()
+        .chain(extra)
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+    }
+
+    fn effective(settings: &Settings, name: &str) -> (String, Origin) {
+        let setting = settings
+            .effective()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap();
+        (setting.value, setting.origin)
+    }
+
+    #[test]
+    fn test_file_merged_with_env_overrides() {
+        let file = r#"
+            environment = "production"
+            portal_descriptor_public_key = "key"
+            portal_trusted_proxies = ["10.0.0.0/8", "fd00::/8"]
+
+            [server]
+            host = "127.0.0.1"
+            port = 8080
+
+            [portal_rate_limit]
+            login = 5
+        "#;
+        let settings = Settings::new(env(&[("SERVER_PORT", "9000")]), Some(file)).unwrap();
+        let config = Config::from_settings(&settings).unwrap();
+
+        assert!(config.is_production());
+        assert_eq!(config.server_host, "127.0.0.1");
+        assert_eq!(config.server_port, 9000);
+        assert_eq!(config.rate_limits.per_minute[0], 5);
+        assert_eq!(config.rate_limits.trusted_proxies.len(), 2);
+
+        assert_eq!(
+            effective(&settings, "SERVER_HOST"),
+            ("127.0.0.1".to_string(), Origin::File)
+        );
+        assert_eq!(
+            effective(&settings, "SERVER_PORT"),
+            ("9000".to_string(), Origin::Env)
+        );
+        assert_eq!(
+            effective(&settings, "JWKS_CACHE_TTL_SECS"),
+            ("3600".to_string(), Origin::Default)
+        );
+    }
+
+    #[test]
+    fn test_every_invalid_value_reported() {
+        let mut env = env(&[
+            ("SERVER_PORT", "abc"),
+            ("ENVIRONMENT", "staging"),
+            ("PORTAL_RATE_LIMIT_LOGIN", "-1"),
+            ("PORTAL_TRUSTED_PROXIES", "10.0.0.0/33"),
+        ]);
+        env.remove("CLIENT_ID");
+        let settings = Settings::new(env, Some("sever_port = 1")).unwrap();
+
+        let error = Config::from_settings(&settings).unwrap_err().to_string();
+        for expected in [
+            "SERVER_PORT: invalid value 'abc'",
+            "ENVIRONMENT must be 'development' or 'production', got 'staging'",
+            "PORTAL_RATE_LIMIT_LOGIN: invalid value '-1'",
+            "PORTAL_TRUSTED_PROXIES: invalid IP range '10.0.0.0/33'",
+            "CLIENT_ID is required",
+            "Unknown setting 'sever_port' in the config file",
+        ] {
+            assert!(error.contains(expected), "{} not in {}", expected, error);
+        }
+
+        assert!(Settings::new(BTreeMap::new(), Some("port = ")).is_err());
+    }
+
+    #[test]
+    fn test_secrets_redacted() {
+        let settings = Settings::new(env(&[]), None).unwrap();
+        let config = Config::from_settings(&settings).unwrap();
+        assert_eq!(
+            config.default_identity_provider().client_secret.expose(),
+            "hunter2"
+        );
+        assert_eq!(
+            effective(&settings, "CLIENT_SECRET"),
+            (REDACTED.to_string(), Origin::Env)
+        );
+        assert!(!format!("{:?}", config).contains("hunter2"));
+
+        // The path of a secret file is shown, not its content
+        let path =
+            std::env::temp_dir().join(format!("portal_config_secret_{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let mut env = env(&[]);
+        env.remove("CLIENT_SECRET");
+        let file = format!("client_secret_file = {:?}", path.to_string_lossy());
+        let settings = Settings::new(env, Some(&file)).unwrap();
+        let config = Config::from_settings(&settings).unwrap();
+        assert_eq!(
+            config.default_identity_provider().client_secret.expose(),
+            "from-file"
+        );
+        assert_eq!(
+            effective(&settings, "CLIENT_SECRET_FILE"),
+            (path.to_string_lossy().to_string(), Origin::File)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_admin_listener_takes_health_routes_in_production() {
+        let config = |extra: &[(&str, &str)]| {
+            Config::from_settings(&Settings::new(env(extra), None).unwrap())
+        };
+        assert!(config(&[]).unwrap().serves_health_publicly());
+        // Development keeps them on the public listener too
+        let development = config(&[("ADMIN_BIND_ADDRESS", "127.0.0.1:9090")]).unwrap();
+        assert_eq!(
+            development.admin_bind_address,
+            Some("127.0.0.1:9090".parse().unwrap())
+        );
+        assert!(development.serves_health_publicly());
+
+        let production = config(&[
+            ("ENVIRONMENT", "production"),
+            ("PORTAL_DESCRIPTOR_PUBLIC_KEY", "key"),
+            ("ADMIN_BIND_ADDRESS", "[::1]:9090"),
+        ])
+        .unwrap();
+        assert!(!production.serves_health_publicly());
+
+        // An IP address and port, not a host name
+        assert!(config(&[("ADMIN_BIND_ADDRESS", "localhost:9090")]).is_err());
+    }
+}
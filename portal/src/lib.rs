@@ -5,22 +5,48 @@
 #![deny(dead_code)]
 
 pub mod assets;
+pub mod audit;
 pub mod auth;
+pub mod check_config;
 pub mod config;
+pub mod rate_limit;
+pub mod request_id;
+pub mod secrets;
+pub mod secrets_command;
 pub mod services;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tls;
 pub mod web;
 
-use auth::jwt::JwtValidator;
+use audit::AuditLog;
+use auth::device::DeviceFlows;
+use auth::providers::IdentityProviders;
+use auth::tokens::TokenStore;
 use config::Config;
-use services::{Descriptor, ServiceCard};
+use rate_limit::RateLimiter;
+use services::{DescriptorStore, Federation};
+use shutdown::Shutdown;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub services: Vec<ServiceCard>,
     pub logos: Vec<String>,
-    pub jwt_validator: Arc<JwtValidator>,
+    /// JWT validators of all identity providers, routed by token issuer
+    pub identity_providers: Arc<IdentityProviders>,
     pub config: Arc<Config>,
-    /// Full descriptor for logout fan-out (oauth2-proxy service URLs)
-    pub descriptor: Arc<Descriptor>,
+    /// Current descriptor and derived service cards (swapped when a remote descriptor changes)
+    pub descriptor: Arc<DescriptorStore>,
+    /// Read-only descriptors of other deployments (empty unless configured)
+    pub federation: Arc<Federation>,
+    /// Personal access token store (None unless PORTAL_TOKEN_STORE_PATH is set)
+    pub token_store: Option<Arc<TokenStore>>,
+    /// Pending device authorizations of CLIs signing in via `/auth/device`
+    pub device_flows: Arc<DeviceFlows>,
+    /// Security audit log (a no-op unless PORTAL_AUDIT_LOG_PATH is set)
+    pub audit: Arc<AuditLog>,
+    /// Token buckets of the rate-limited auth endpoints
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Set once SIGTERM/SIGINT is received (`/readyz` then returns 503)
+    pub shutdown: Shutdown,
 }
@@ -1,51 +1,127 @@
 use anyhow::Result;
-use portal::{assets, auth::jwt::JwtValidator, services, web, AppState};
+use portal::{
+    assets,
+    audit::AuditLog,
+    auth::{providers::IdentityProviders, tokens::TokenStore},
+    check_config,
+    config::{Config, Settings},
+    rate_limit::RateLimiter,
+    secrets, secrets_command, services,
+    shutdown::{self, Shutdown},
+    telemetry::{self, LogFormat},
+    tls::{self, Certificates, TlsListener},
+    web, AppState,
+};
+use std::process::ExitCode;
 use std::sync::Arc;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use std::time::Duration;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing
+async fn main() -> Result<ExitCode> {
+    // `portal check-config`: report the configuration and its connectivity, then exit
+    if std::env::args().nth(1).as_deref() == Some("check-config") {
+        return Ok(check_config::run().await);
+    }
+    // `portal secrets ...`: create keys and encrypted secrets files, then exit
+    if std::env::args().nth(1).as_deref() == Some("secrets") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        return Ok(secrets_command::run(&args));
+    }
+
+    // Load configuration from the environment and PORTAL_CONFIG_FILE
+    // (every invalid setting is reported before anything starts)
+    let (config, effective_settings) = {
+        let settings = Settings::load()?;
+        (Config::from_settings(&settings)?, settings.effective())
+    };
+
+    // Initialize tracing (spans are exported when OTEL_EXPORTER_OTLP_ENDPOINT is set)
+    let tracer_provider = telemetry::init_tracer_provider(&config.telemetry)?;
+    let fmt_layer = match config.telemetry.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        // Span fields (request_id, ...) are included with every event
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
-        .with(tracing_subscriber::fmt::layer())
+        .with(fmt_layer)
+        .with(tracer_provider.as_ref().map(telemetry::layer))
         .init();
 
     tracing::info!("Starting portal service");
-
-    // Load configuration from environment
-    let config = portal::config::Config::load()?;
     tracing::info!(
         environment = ?config.environment,
-        keycloak_realm = %config.keycloak_realm,
+        keycloak_realm = %config.default_identity_provider().realm,
+        identity_providers = config.identity_providers.len(),
         "Configuration loaded"
     );
 
-    // Initialize JWT validator with JWKS caching and issuer/audience validation
-    let jwt_validator = Arc::new(
-        JwtValidator::new(
-            config.keycloak_url.clone(),           // Internal URL for JWKS fetching
-            config.keycloak_callback_url.clone(),  // Public URL for issuer validation
-            config.keycloak_realm.clone(),
-            config.client_id.clone(),              // Expected audience
+    // Initialize a JWT validator per identity provider with JWKS caching and
+    // issuer/audience validation
+    let identity_providers = Arc::new(
+        IdentityProviders::new(
+            &config.identity_providers,
             config.http_connect_timeout_secs,
             config.http_request_timeout_secs,
             config.jwks_cache_ttl_secs,
         )
         .map_err(|e| anyhow::anyhow!("Failed to initialize JWT validator: {}", e))?,
     );
-    tracing::info!("JWT validator initialized with issuer and audience validation");
+    tracing::info!("JWT validators initialized with issuer and audience validation");
 
     // Prefetch JWKS at startup to ensure /readyz returns 200 immediately
-    jwt_validator
+    identity_providers
         .prefetch_jwks()
         .await
         .map_err(|e| anyhow::anyhow!("Failed to prefetch JWKS at startup: {}", e))?;
     tracing::info!("JWKS prefetched successfully - readiness check will pass");
 
-    // Load and validate descriptor (logs summary internally)
-    let descriptor = services::load_descriptor(&config.descriptor)?;
-    let services = services::services_from_descriptor(&descriptor);
+    // Stops the background tasks started below once the server has shut down
+    let shutdown = Shutdown::default();
+
+    // Load and validate descriptor (logs summary internally, starts polling for URL sources)
+    let descriptor_store = services::load_descriptor_store(&config, &shutdown).await?;
+
+    // Load federated descriptors of other deployments (URL sources are polled)
+    let federation = services::load_federation(
+        &config.federated_descriptors,
+        config.descriptor.public_key.as_deref(),
+        Duration::from_secs(config.descriptor.poll_interval_secs),
+        config.http_connect_timeout_secs,
+        config.http_request_timeout_secs,
+        &shutdown,
+    )
+    .await?;
+
+    // Open the personal access token store if enabled
+    let token_store = config
+        .token_store_path
+        .as_deref()
+        .map(TokenStore::open)
+        .transpose()?
+        .map(Arc::new);
+
+    // Open the security audit log if enabled
+    let audit = match config.audit_log_path.as_deref() {
+        Some(path) => AuditLog::open(path, config.audit_log_max_bytes, config.audit_log_max_files)?,
+        None => AuditLog::default(),
+    };
+
+    // Pick up rotated client secrets without a restart
+    secrets::spawn_refresh(
+        config
+            .identity_providers
+            .iter()
+            .map(|provider| provider.client_secret.clone())
+            .collect(),
+        Duration::from_secs(config.secret_refresh_secs),
+        &shutdown,
+    );
 
     // Discover logos at runtime
     let logos = assets::discover_logos().unwrap_or_default();
@@ -53,24 +129,80 @@ async fn main() -> Result<()> {
 
     // Create shared application state
     let config_arc = Arc::new(config.clone());
-    let descriptor_arc = Arc::new(descriptor);
     let state = Arc::new(AppState {
-        services,
         logos,
-        jwt_validator: jwt_validator.clone(),
+        identity_providers: identity_providers.clone(),
         config: config_arc,
-        descriptor: descriptor_arc,
+        descriptor: descriptor_store,
+        federation,
+        token_store,
+        device_flows: Arc::default(),
+        audit: Arc::new(audit),
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limits.clone())),
+        shutdown: shutdown.clone(),
     });
 
-    // Build router with JWT validator extension
-    let app = web::create_router(state, jwt_validator);
+    // Health, readiness, metrics and debug endpoints on their own listener;
+    // it keeps answering (readyz 503) until the public listener has drained
+    if let Some(admin_address) = config.admin_bind_address {
+        let listener = tokio::net::TcpListener::bind(admin_address).await?;
+        let admin = web::create_admin_router(state.clone(), effective_settings);
+        shutdown.spawn(async move {
+            if let Err(e) = axum::serve(listener, admin).await {
+                tracing::error!(error = %e, "Admin listener failed");
+            }
+        });
+        tracing::info!(
+            public_health_routes = config.serves_health_publicly(),
+            "Admin endpoints listening on {}",
+            admin_address
+        );
+    }
+
+    // Build router with identity providers extension
+    let app = web::create_router(state, identity_providers);
 
     // Bind and serve
     let bind_address = config.bind_address();
     let listener = tokio::net::TcpListener::bind(&bind_address).await?;
-    tracing::info!("Portal listening on {}", bind_address);
+    let drain = Duration::from_secs(config.shutdown_drain_secs);
+    let timeout = Duration::from_secs(config.shutdown_timeout_secs);
+
+    // Serve until SIGTERM/SIGINT, then drain (readyz 503) and let in-flight requests finish
+    match &config.tls {
+        Some(tls_config) => {
+            // HTTPS with the certificate reloaded when renewed, optionally
+            // redirecting plain HTTP
+            let certificates = Certificates::load(tls_config)?;
+            tls::spawn_reload(
+                certificates.clone(),
+                Duration::from_secs(tls_config.reload_secs),
+                &shutdown,
+            );
+            if let Some(port) = tls_config.redirect_port {
+                let redirect_address = format!("{}:{}", config.server_host, port);
+                tls::spawn_redirect_listener(&redirect_address, config.server_port, &shutdown)
+                    .await?;
+                tracing::info!("Redirecting HTTP on {} to HTTPS", redirect_address);
+            }
+            let listener = TlsListener::new(listener, certificates.server_config()?)?;
+            tracing::info!("Portal listening on {} (HTTPS)", bind_address);
+            shutdown::serve(listener, app, &shutdown, shutdown::signal(), drain, timeout).await?;
+        }
+        None => {
+            tracing::info!("Portal listening on {}", bind_address);
+            shutdown::serve(listener, app, &shutdown, shutdown::signal(), drain, timeout).await?;
+        }
+    }
+    shutdown.stop_background_tasks(Duration::from_secs(5)).await;
 
-    axum::serve(listener, app).await?;
+    // Flush spans still queued in the batch exporter
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!(error = %e, "Failed to flush OpenTelemetry spans");
+        }
+    }
 
-    Ok(())
+    tracing::info!("Portal stopped");
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,504 @@
+//! Per-IP rate limiting of the unauthenticated auth endpoints
+//!
+//! `/auth/login`, `/auth/callback`, `/auth/logout*` and the device grant
+//! endpoints can each trigger outbound calls (token exchange, JWKS refresh,
+//! reachability probes), so every client IP gets a token bucket per endpoint
+//! class. A bucket holds a minute's budget and refills continuously; an empty
+//! bucket means `429 Too Many Requests` with `Retry-After`.
+//!
+//! The client IP is the TCP peer, unless the peer is a trusted proxy
+//! (PORTAL_TRUSTED_PROXIES): then `X-Forwarded-For` is walked from the right,
+//! skipping trusted hops. IPv6 clients are limited per /64, the smallest
+//! block a single host commonly controls. The middleware stores the resolved
+//! address as a [`ClientIp`] extension, so the audit log records the same
+//! client the limiter counted.
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tracked buckets before idle (full) ones are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Minimum time between two prunes (pruning walks every bucket)
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Endpoint classes with separate budgets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    /// `/auth/login`
+    Login,
+    /// `/auth/callback` (token exchange)
+    Callback,
+    /// `/auth/logout`, `/auth/logout/complete`, `/auth/logout/service` (probes)
+    Logout,
+    /// `/auth/device/code`, `/auth/device/token` (CLI sign-in)
+    Device,
+}
+
+impl EndpointClass {
+    pub const ALL: [EndpointClass; 4] = [
+        EndpointClass::Login,
+        EndpointClass::Callback,
+        EndpointClass::Logout,
+        EndpointClass::Device,
+    ];
+
+    /// Class of a request path (None = not rate limited)
+    pub fn for_path(path: &str) -> Option<Self> {
+        match path {
+            "/auth/login" => Some(Self::Login),
+            "/auth/callback" => Some(Self::Callback),
+            "/auth/logout" | "/auth/logout/complete" | "/auth/logout/service" => Some(Self::Logout),
+            "/auth/device/code" | "/auth/device/token" => Some(Self::Device),
+            _ => None,
+        }
+    }
+
+    /// Label in metrics and logs
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Callback => "callback",
+            Self::Logout => "logout",
+            Self::Device => "device",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// An IP network in CIDR notation (`10.0.0.0/8`, `fd00::/8`, or a single address)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = address.trim().parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Client address of a request, resolved by the [`rate_limit`] middleware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Rate limit settings
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Requests per minute per client IP, indexed like [`EndpointClass::ALL`] (0 = unlimited)
+    pub per_minute: [u32; 4],
+    /// Proxies whose `X-Forwarded-For` is believed
+    pub trusted_proxies: Vec<IpRange>,
+}
+
+impl RateLimitConfig {
+    pub fn per_minute(&self, class: EndpointClass) -> u32 {
+        self.per_minute[class.index()]
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(EndpointClass, IpAddr), Bucket>,
+    last_prune: Instant,
+}
+
+/// Token buckets of all client IPs, plus allowed/rejected counters per class
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+    allowed: [AtomicU64; 4],
+    rejected: [AtomicU64; 4],
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+            allowed: Default::default(),
+            rejected: Default::default(),
+        }
+    }
+
+    /// Take a token from the client's bucket, or return how long until one is available
+    pub fn check(
+        &self,
+        class: EndpointClass,
+        client: IpAddr,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let limit = self.config.per_minute(class);
+        if limit == 0 {
+            self.allowed[class.index()].fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        let capacity = limit as f64;
+        let per_second = capacity / 60.0;
+
+        let mut state = self.buckets.lock().unwrap();
+        if state.buckets.len() >= PRUNE_THRESHOLD
+            && now.duration_since(state.last_prune) >= PRUNE_INTERVAL
+        {
+            state.last_prune = now;
+            let config = &self.config;
+            // Buckets that have refilled completely carry no state
+            state.buckets.retain(|(class, _), bucket| {
+                let full = config.per_minute(*class) as f64;
+                bucket.tokens
+                    + now.saturating_duration_since(bucket.updated).as_secs_f64() * full / 60.0
+                    < full
+            });
+        }
+
+        let bucket = state
+            .buckets
+            .entry((class, bucket_key(client)))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.allowed[class.index()].fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        } else {
+            self.rejected[class.index()].fetch_add(1, Ordering::Relaxed);
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    /// Client IP of a request (see the module docs)
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let trusted = |ip: IpAddr| self.config.trusted_proxies.iter().any(|r| r.contains(ip));
+        let peer = peer.to_canonical();
+        if !trusted(peer) {
+            return peer;
+        }
+
+        // Later headers and later entries were appended by proxies closer to us
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip.to_canonical();
+            if !trusted(client) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// Counters in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        for (name, help, counters) in [
+            (
+                "portal_rate_limit_allowed_total",
+                "Requests to rate-limited endpoints that were let through",
+                &self.allowed,
+            ),
+            (
+                "portal_rate_limit_rejected_total",
+                "Requests rejected with 429 Too Many Requests",
+                &self.rejected,
+            ),
+        ] {
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} counter\n",
+                name, help, name
+            ));
+            for class in EndpointClass::ALL {
+                out.push_str(&format!(
+                    "{}{{class=\"{}\"}} {}\n",
+                    name,
+                    class.name(),
+                    counters[class.index()].load(Ordering::Relaxed)
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// IPv6 addresses share a bucket per /64
+fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (u128::MAX << 64))),
+    }
+}
+
+/// Middleware: resolve the [`ClientIp`] and reject over-budget clients of
+/// rate-limited endpoints with 429
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Without a peer address (not served with connect info) there is nobody to limit
+    let Some(ConnectInfo(peer)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    else {
+        return next.run(request).await;
+    };
+    let client = limiter.client_ip(peer.ip(), request.headers());
+    request.extensions_mut().insert(ClientIp(client));
+
+    let Some(class) = EndpointClass::for_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    match limiter.check(class, client, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = (wait.as_secs_f64().ceil() as u64).max(1);
+            tracing::warn!(
+                event = "rate_limited",
+                class = class.name(),
+                client_ip = %client,
+                retry_after_secs = retry_after,
+                "Rate limit exceeded"
+            );
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_minute: u32, trusted: &[&str]) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            per_minute: [per_minute, per_minute, 0, per_minute],
+            trusted_proxies: trusted.iter().map(|r| IpRange::parse(r).unwrap()).collect(),
+        })
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(3, &[]);
+        let start = Instant::now();
+        let client = ip("203.0.113.7");
+
+        for _ in 0..3 {
+            assert!(limiter.check(EndpointClass::Login, client, start).is_ok());
+        }
+        // Empty: one token takes 20s to come back at 3/min
+        let wait = limiter
+            .check(EndpointClass::Login, client, start)
+            .unwrap_err();
+        assert_eq!(wait.as_secs_f64().round(), 20.0);
+
+        // Separate budgets per class and per client
+        assert!(limiter
+            .check(EndpointClass::Callback, client, start)
+            .is_ok());
+        assert!(limiter
+            .check(EndpointClass::Login, ip("203.0.113.8"), start)
+            .is_ok());
+        // 0 = unlimited
+        for _ in 0..10 {
+            assert!(limiter.check(EndpointClass::Logout, client, start).is_ok());
+        }
+
+        let later = start + Duration::from_secs(20);
+        assert!(limiter.check(EndpointClass::Login, client, later).is_ok());
+        assert!(limiter.check(EndpointClass::Login, client, later).is_err());
+
+        let metrics = limiter.render_metrics();
+        assert!(metrics.contains("portal_rate_limit_allowed_total{class=\"login\"} 5\n"));
+        assert!(metrics.contains("portal_rate_limit_rejected_total{class=\"login\"} 2\n"));
+        assert!(metrics.contains("portal_rate_limit_allowed_total{class=\"logout\"} 10\n"));
+        assert!(metrics.contains("# TYPE portal_rate_limit_rejected_total counter\n"));
+    }
+
+    #[test]
+    fn test_ipv6_clients_share_a_64() {
+        let limiter = limiter(1, &[]);
+        let now = Instant::now();
+        assert!(limiter
+            .check(EndpointClass::Login, ip("2001:db8:1:2::1"), now)
+            .is_ok());
+        assert!(limiter
+            .check(EndpointClass::Login, ip("2001:db8:1:2::ffff"), now)
+            .is_err());
+        assert!(limiter
+            .check(EndpointClass::Login, ip("2001:db8:1:3::1"), now)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ip_range() {
+        let range = IpRange::parse("10.0.0.0/8").unwrap();
+        assert!(range.contains(ip("10.1.2.3")));
+        assert!(!range.contains(ip("11.0.0.1")));
+        // IPv4-mapped peers of dual-stack sockets
+        assert!(range.contains(ip("::ffff:10.0.0.1")));
+
+        assert!(IpRange::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+        assert!(IpRange::parse("192.0.2.1")
+            .unwrap()
+            .contains(ip("192.0.2.1")));
+        assert!(!IpRange::parse("192.0.2.1")
+            .unwrap()
+            .contains(ip("192.0.2.2")));
+        assert!(IpRange::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(IpRange::parse("10.0.0.0/33").is_none());
+        assert!(IpRange::parse("not-an-ip").is_none());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let limiter = limiter(1, &["10.0.0.0/8"]);
+        let headers = |xff: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", xff.parse().unwrap());
+            headers
+        };
+
+        // Untrusted peer: X-Forwarded-For is ignored
+        assert_eq!(
+            limiter.client_ip(ip("198.51.100.1"), &headers("203.0.113.7")),
+            ip("198.51.100.1")
+        );
+        // Trusted peer: rightmost untrusted hop (the left part can be forged)
+        assert_eq!(
+            limiter.client_ip(ip("10.0.0.2"), &headers("1.2.3.4, 203.0.113.7, 10.0.0.5")),
+            ip("203.0.113.7")
+        );
+        // Trusted peer without the header
+        assert_eq!(
+            limiter.client_ip(ip("10.0.0.2"), &HeaderMap::new()),
+            ip("10.0.0.2")
+        );
+        // Garbage stops the walk at the last good hop
+        assert_eq!(
+            limiter.client_ip(ip("10.0.0.2"), &headers("203.0.113.7, garbage")),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_endpoint_class_for_path() {
+        assert_eq!(
+            EndpointClass::for_path("/auth/login"),
+            Some(EndpointClass::Login)
+        );
+        assert_eq!(
+            EndpointClass::for_path("/auth/logout/service"),
+            Some(EndpointClass::Logout)
+        );
+        assert_eq!(
+            EndpointClass::for_path("/auth/device/token"),
+            Some(EndpointClass::Device)
+        );
+        assert_eq!(EndpointClass::for_path("/auth/device"), None);
+        assert_eq!(EndpointClass::for_path("/dashboard"), None);
+    }
+
+    #[tokio::test]
+    async fn test_middleware_rejects_over_budget_clients() {
+        let app = axum::Router::new()
+            .route("/auth/login", axum::routing::get(|| async { "login" }))
+            .route("/dashboard", axum::routing::get(|| async { "dashboard" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(limiter(2, &[])),
+                rate_limit,
+            ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+
+        let client = reqwest::Client::new();
+        let get = |path: &str| client.get(format!("http://{}{}", addr, path)).send();
+        for _ in 0..2 {
+            assert_eq!(get("/auth/login").await.unwrap().status(), StatusCode::OK);
+        }
+        let response = get("/auth/login").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        // A token refills after 30 s at 2 per minute
+        let retry_after: u64 = response.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=30).contains(&retry_after), "{}", retry_after);
+
+        // Other endpoints are not limited
+        assert_eq!(get("/dashboard").await.unwrap().status(), StatusCode::OK);
+    }
+}
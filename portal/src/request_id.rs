@@ -0,0 +1,168 @@
+//! Request IDs (`X-Request-Id`)
+//!
+//! Every request gets an ID: the one sent by Traefik (or another upstream)
+//! when it is well-formed, otherwise a new random one. The ID is
+//! - set on the request's `http_request` span, so every log line of the
+//!   request carries it;
+//! - returned in the `X-Request-Id` response header (and in `AuthError`
+//!   JSON bodies);
+//! - forwarded on outbound calls made while handling the request (see
+//!   [`crate::telemetry::trace_headers`]);
+//! - recorded in the security audit log.
+
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest inbound ID that is accepted as is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// ID of the request being handled (also a request extension)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Random 128-bit ID (32 hex characters)
+    pub fn generate() -> Self {
+        Self(format!("{:032x}", rand::random::<u128>()))
+    }
+
+    /// Accept an upstream ID if it is short and made of safe characters
+    ///
+    /// The ID ends up in log lines and outbound headers, so anything that
+    /// could break either is replaced rather than escaped.
+    pub fn parse(value: &str) -> Option<Self> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'));
+        valid.then(|| Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// ID of the request handled by the current task (None outside a request)
+pub fn current() -> Option<RequestId> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+/// Middleware: accept or generate the request ID and echo it in the response
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(RequestId::parse)
+        .unwrap_or_else(RequestId::generate);
+    // Valid IDs are plain ASCII, so this cannot fail
+    let header_value = HeaderValue::from_str(id.as_str()).expect("request ID is a valid header");
+
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value.clone());
+    request.extensions_mut().insert(id.clone());
+
+    let mut response = CURRENT.scope(id, next.run(request)).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::extractors::AuthError;
+    use axum::routing::get;
+    use axum::Router;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RequestId::parse("a1b2-c3.d4_e5:f6").unwrap().as_str(),
+            "a1b2-c3.d4_e5:f6"
+        );
+        assert!(RequestId::parse("").is_none());
+        assert!(RequestId::parse("has space").is_none());
+        assert!(RequestId::parse("line\nbreak").is_none());
+        assert!(RequestId::parse(&"x".repeat(MAX_REQUEST_ID_LEN + 1)).is_none());
+
+        let generated = RequestId::generate();
+        assert_eq!(generated.as_str().len(), 32);
+        assert!(RequestId::parse(generated.as_str()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_middleware_accepts_or_generates() {
+        // The handler echoes the ID it sees as the current request ID
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { current().map(|id| id.0).unwrap_or_default() }),
+            )
+            .layer(axum::middleware::from_fn(request_id));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(&url)
+            .header("x-request-id", "traefik-123")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "traefik-123");
+        assert_eq!(response.text().await.unwrap(), "traefik-123");
+
+        let response = client
+            .get(&url)
+            .header("x-request-id", "bad id")
+            .send()
+            .await
+            .unwrap();
+        let generated = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_ne!(generated, "bad id");
+        assert_eq!(response.text().await.unwrap(), generated);
+
+        assert!(current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_auth_error_body_carries_request_id() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { AuthError::Unauthenticated("No token".to_string()) }),
+            )
+            .layer(axum::middleware::from_fn(request_id));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("x-request-id", "req-42")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["request_id"], "req-42");
+        assert_eq!(body["code"], "UNAUTHENTICATED");
+    }
+}
@@ -0,0 +1,397 @@
+//! Sensitive settings (client secrets) and where they are read from
+//!
+//! A sensitive setting `NAME` is resolved from, in order:
+//! 1. `NAME_FILE`: a file holding the value (Docker/Kubernetes secrets);
+//! 2. `NAME` itself (environment variable or config file);
+//! 3. the encrypted secrets file (PORTAL_SECRETS_FILE), if it has a `NAME` entry.
+//!
+//! Setting both `NAME` and `NAME_FILE` is an error. File-backed values are
+//! re-read every PORTAL_SECRET_REFRESH_SECS, so a rotated secret is used
+//! without a restart; [`Secret`] handles share the current value.
+//!
+//! The encrypted secrets file is a JSON document (`version`, `nonce`,
+//! `ciphertext`) holding a JSON object of `NAME` → value, sealed with
+//! AES-256-GCM under the key from PORTAL_SECRETS_KEY(_FILE). `portal secrets`
+//! creates keys and encrypts files (see [`crate::secrets_command`]).
+
+use anyhow::{Context, Result};
+use aws_lc_rs::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::shutdown::Shutdown;
+
+/// Binds ciphertexts to this file format
+const AAD: &[u8] = b"portal-secrets-v1";
+
+const KEY_LEN: usize = 32;
+
+/// Where a secret value comes from
+#[derive(Clone)]
+pub enum SecretSource {
+    /// Environment variable (fixed for the life of the process)
+    Value(String),
+    /// File holding the value (`NAME_FILE`); trailing newlines are ignored
+    File(PathBuf),
+    /// Entry of the encrypted secrets file
+    Encrypted {
+        store: Arc<EncryptedStore>,
+        name: String,
+    },
+}
+
+impl SecretSource {
+    /// Read the current value
+    pub fn load(&self) -> Result<String> {
+        let value = match self {
+            SecretSource::Value(value) => value.clone(),
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read secret file {}", path.display()))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            SecretSource::Encrypted { store, name } => store
+                .load()?
+                .remove(name)
+                .with_context(|| format!("{} not found in the encrypted secrets file", name))?,
+        };
+        anyhow::ensure!(!value.is_empty(), "secret is empty");
+        Ok(value)
+    }
+
+    /// Whether re-reading can return a different value
+    pub fn rotates(&self) -> bool {
+        !matches!(self, SecretSource::Value(_))
+    }
+}
+
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Value(_) => f.write_str("Value(<redacted>)"),
+            SecretSource::File(path) => f.debug_tuple("File").field(path).finish(),
+            SecretSource::Encrypted { store, name } => f
+                .debug_struct("Encrypted")
+                .field("path", &store.path)
+                .field("name", name)
+                .finish(),
+        }
+    }
+}
+
+/// A sensitive setting: its source and the last value read from it
+///
+/// Clones share the value, so a refresh is seen by every holder.
+#[derive(Clone)]
+pub struct Secret {
+    name: String,
+    source: SecretSource,
+    value: Arc<RwLock<String>>,
+}
+
+impl Secret {
+    /// Read the secret once (fails if the source is unreadable)
+    pub fn load(name: impl Into<String>, source: SecretSource) -> Result<Self> {
+        let name = name.into();
+        let value = source
+            .load()
+            .with_context(|| format!("Failed to load {}", name))?;
+        Ok(Self {
+            name,
+            source,
+            value: Arc::new(RwLock::new(value)),
+        })
+    }
+
+    /// A fixed value (tests and defaults)
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        Self {
+            name: String::new(),
+            source: SecretSource::Value(value.clone()),
+            value: Arc::new(RwLock::new(value)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current value (never log it)
+    pub fn expose(&self) -> String {
+        self.value.read().unwrap().clone()
+    }
+
+    pub fn rotates(&self) -> bool {
+        self.source.rotates()
+    }
+
+    /// Re-read the source; returns whether the value changed
+    ///
+    /// On error the previous value stays in use.
+    pub fn refresh(&self) -> Result<bool> {
+        let value = self.source.load()?;
+        let mut current = self.value.write().unwrap();
+        if *current == value {
+            return Ok(false);
+        }
+        *current = value;
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secret")
+            .field("name", &self.name)
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+// =============================================================================
+// Resolution
+// =============================================================================
+
+/// Source of the sensitive setting `name` (None if it is not set anywhere)
+///
+/// `lookup` returns the non-empty value of a setting (see
+/// [`crate::config::Settings`]).
+pub fn resolve(
+    name: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    store: Option<&Arc<EncryptedStore>>,
+) -> Result<Option<SecretSource>> {
+    let file_var = format!("{}_FILE", name);
+    match (lookup(name), lookup(&file_var)) {
+        (Some(_), Some(_)) => anyhow::bail!("Set either {} or {}, not both", name, file_var),
+        (None, Some(path)) => Ok(Some(SecretSource::File(path.into()))),
+        (Some(value), None) => Ok(Some(SecretSource::Value(value))),
+        (None, None) => match store {
+            Some(store) if store.load()?.contains_key(name) => Ok(Some(SecretSource::Encrypted {
+                store: store.clone(),
+                name: name.to_string(),
+            })),
+            _ => Ok(None),
+        },
+    }
+}
+
+// =============================================================================
+// Encrypted secrets file
+// =============================================================================
+
+/// On-disk format of the encrypted secrets file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptedSecrets {
+    pub version: u32,
+    /// Base64 AES-GCM nonce
+    pub nonce: String,
+    /// Base64 ciphertext of the JSON object, with the tag appended
+    pub ciphertext: String,
+}
+
+/// The encrypted secrets file and its key (both re-read on every load)
+pub struct EncryptedStore {
+    path: PathBuf,
+    key: SecretSource,
+}
+
+impl EncryptedStore {
+    pub fn new(path: impl Into<PathBuf>, key: SecretSource) -> Self {
+        Self {
+            path: path.into(),
+            key,
+        }
+    }
+
+    /// Open PORTAL_SECRETS_FILE, failing on a wrong key or a corrupt file
+    pub fn open(path: impl Into<PathBuf>, key: SecretSource) -> Result<Arc<Self>> {
+        let store = Arc::new(Self::new(path, key));
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Decrypt all entries
+    pub fn load(&self) -> Result<BTreeMap<String, String>> {
+        let key = self.key.load().context("Failed to load the secrets key")?;
+        let json = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        let document: EncryptedSecrets = serde_json::from_str(&json)
+            .with_context(|| format!("{} is not an encrypted secrets file", self.path.display()))?;
+        decrypt_secrets(&key, &document)
+            .with_context(|| format!("Failed to decrypt {}", self.path.display()))
+    }
+}
+
+/// A new random key, base64 (the PORTAL_SECRETS_KEY format)
+pub fn generate_key() -> String {
+    let mut key = [0u8; KEY_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut key);
+    STANDARD.encode(key)
+}
+
+fn sealing_key(key: &str) -> Result<LessSafeKey> {
+    let bytes = STANDARD
+        .decode(key.trim())
+        .context("key is not valid base64")?;
+    anyhow::ensure!(bytes.len() == KEY_LEN, "key must be {} bytes", KEY_LEN);
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| anyhow::anyhow!("invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Seal `secrets` under the base64 `key`
+pub fn encrypt_secrets(key: &str, secrets: &BTreeMap<String, String>) -> Result<EncryptedSecrets> {
+    let key = sealing_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let mut in_out = serde_json::to_vec(secrets)?;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(AAD),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+
+    Ok(EncryptedSecrets {
+        version: 1,
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(in_out),
+    })
+}
+
+/// Open a document sealed by [`encrypt_secrets`]
+pub fn decrypt_secrets(key: &str, document: &EncryptedSecrets) -> Result<BTreeMap<String, String>> {
+    anyhow::ensure!(
+        document.version == 1,
+        "unsupported version {}",
+        document.version
+    );
+    let key = sealing_key(key)?;
+    let nonce: [u8; NONCE_LEN] = STANDARD
+        .decode(&document.nonce)
+        .ok()
+        .and_then(|nonce| nonce.try_into().ok())
+        .context("invalid nonce")?;
+    let mut in_out = STANDARD
+        .decode(&document.ciphertext)
+        .context("ciphertext is not valid base64")?;
+
+    let plaintext = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(AAD),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow::anyhow!("wrong key or tampered file"))?;
+    serde_json::from_slice(plaintext).context("decrypted content is not a JSON object of strings")
+}
+
+// =============================================================================
+// Rotation
+// =============================================================================
+
+/// Re-read rotating secrets every `interval` until `shutdown` stops background tasks
+pub fn spawn_refresh(secrets: Vec<Secret>, interval: Duration, shutdown: &Shutdown) {
+    let secrets: Vec<Secret> = secrets.into_iter().filter(Secret::rotates).collect();
+    if secrets.is_empty() || interval.is_zero() {
+        return;
+    }
+    shutdown.spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for secret in &secrets {
+                match secret.refresh() {
+                    Ok(true) => tracing::info!(
+                        event = "secret_rotated",
+                        name = %secret.name(),
+                        "Secret changed, using the new value"
+                    ),
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!(
+                        name = %secret.name(),
+                        error = %e,
+                        "Failed to re-read secret, keeping the previous value"
+                    ),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("portal_secrets_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_file_secret_rotates() {
+        let path = temp_path("file");
+        std::fs::write(&path, "first\n").unwrap();
+
+        let secret = Secret::load("CLIENT_SECRET", SecretSource::File(path.clone())).unwrap();
+        let shared = secret.clone();
+        assert_eq!(secret.expose(), "first");
+        assert!(!secret.refresh().unwrap());
+
+        std::fs::write(&path, "second").unwrap();
+        assert!(secret.refresh().unwrap());
+        assert_eq!(shared.expose(), "second");
+
+        // Unreadable: keep the previous value
+        std::fs::remove_file(&path).unwrap();
+        assert!(secret.refresh().is_err());
+        assert_eq!(shared.expose(), "second");
+    }
+
+    #[test]
+    fn test_encrypted_store() {
+        let key = generate_key();
+        let secrets = BTreeMap::from([("CLIENT_SECRET".to_string(), "s3cret".to_string())]);
+        let document = encrypt_secrets(&key, &secrets).unwrap();
+        assert!(!document.ciphertext.contains("s3cret"));
+
+        let path = temp_path("store.json");
+        std::fs::write(&path, serde_json::to_string(&document).unwrap()).unwrap();
+        let store = Arc::new(EncryptedStore::new(&path, SecretSource::Value(key)));
+        let secret = Secret::load(
+            "CLIENT_SECRET",
+            SecretSource::Encrypted {
+                store: store.clone(),
+                name: "CLIENT_SECRET".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(secret.expose(), "s3cret");
+
+        let missing = SecretSource::Encrypted {
+            store,
+            name: "OTHER".to_string(),
+        };
+        assert!(missing.load().is_err());
+
+        // Wrong key
+        let other = EncryptedStore::new(&path, SecretSource::Value(generate_key()));
+        assert!(other.load().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_debug_redacts_values() {
+        let secret = Secret::new("hunter2");
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+        assert!(format!("{:?}", secret).contains("redacted"));
+    }
+}
@@ -0,0 +1,96 @@
+//! `portal secrets`: create keys and encrypted secrets files
+//!
+//! ```text
+//! portal secrets generate-key
+//! portal secrets encrypt <key-file> <secrets.json> <out.json>
+//! ```
+//!
+//! `generate-key` prints a new key for PORTAL_SECRETS_KEY(_FILE) on stdout.
+//! `encrypt` seals `secrets.json`, a JSON object of setting name → value
+//! (e.g. `{"CLIENT_SECRET": "..."}`), into a PORTAL_SECRETS_FILE and prints
+//! the names it holds; values are never printed. Exits with 1 on any error.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::ExitCode;
+
+use anyhow::{Context, Result};
+
+use crate::secrets::{encrypt_secrets, generate_key};
+
+const USAGE: &str = "usage: portal secrets generate-key \
+                     | portal secrets encrypt <key-file> <secrets.json> <out.json>";
+
+/// Encrypt `secrets_path` into `out_path`; returns the setting names
+fn encrypt_file(key_path: &str, secrets_path: &str, out_path: &str) -> Result<Vec<String>> {
+    let key =
+        fs::read_to_string(key_path).with_context(|| format!("Failed to read {}", key_path))?;
+    let secrets: BTreeMap<String, String> = fs::read_to_string(secrets_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| serde_json::from_str(&json).map_err(anyhow::Error::from))
+        .with_context(|| format!("Failed to load {}", secrets_path))?;
+    let document = encrypt_secrets(&key, &secrets)?;
+    fs::write(out_path, serde_json::to_string(&document)?)
+        .with_context(|| format!("Failed to write {}", out_path))?;
+    Ok(secrets.into_keys().collect())
+}
+
+/// Run `portal secrets <args>`
+pub fn run(args: &[String]) -> ExitCode {
+    let result = match args {
+        [command] if command == "generate-key" => {
+            println!("{}", generate_key());
+            Ok(())
+        }
+        [command, key, secrets, out] if command == "encrypt" => encrypt_file(key, secrets, out)
+            .map(|names| {
+                println!("Wrote {} with {}", out, names.join(", "));
+            }),
+        _ => Err(anyhow::anyhow!(USAGE)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::decrypt_secrets;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("portal_secrets_command_{}", name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_encrypt_file_round_trip() {
+        let key = generate_key();
+        let key_path = temp_path("key");
+        fs::write(&key_path, format!("{}\n", key)).unwrap();
+        let secrets_path = temp_path("plain.json");
+        fs::write(&secrets_path, r#"{"CLIENT_SECRET": "s3cret"}"#).unwrap();
+        let out = temp_path("encrypted.json");
+
+        let names = encrypt_file(&key_path, &secrets_path, &out).unwrap();
+        assert_eq!(names, vec!["CLIENT_SECRET"]);
+        let encrypted = fs::read_to_string(&out).unwrap();
+        assert!(!encrypted.contains("s3cret"));
+        let document = serde_json::from_str(&encrypted).unwrap();
+        let secrets = decrypt_secrets(&key, &document).unwrap();
+        assert_eq!(secrets["CLIENT_SECRET"], "s3cret");
+
+        // The secrets file is not a key
+        assert!(encrypt_file(&secrets_path, &secrets_path, &out).is_err());
+
+        for path in [key_path, secrets_path, out] {
+            fs::remove_file(path).ok();
+        }
+    }
+}
@@ -1,21 +1,65 @@
 //! GENERATED FILE - DO NOT EDIT
 //!
-//! Generated from: schema/portal-descriptor.schema.json
+//! Generated from: schema/portal-descriptor.v2.schema.json
 //!
 //! To regenerate, run: cargo run --bin generate-types
+//! To check for drift, run: cargo run --bin generate-types -- --check
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
 
-/// Authentication type for a service
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case")]
-pub enum AuthType {
-    /// Service has no authentication
-    None,
-    /// Service has oauth2-proxy authentication
-    Oauth2Proxy,
-    /// Service has portal authentication
-    Portal,
+use super::descriptor::DescriptorViolation;
+
+/// Deployment metadata for tracking what/when was deployed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DeploymentInfo {
+    /// Git commit SHA that was deployed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_sha: Option<String>,
+    /// When the commit was made (git committer date)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_at: Option<String>,
+    /// When the deployment happened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployed_at: Option<String>,
+}
+
+impl DeploymentInfo {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        if let Some(value) = &self.commit_sha {
+            check_pattern(
+                value,
+                is_git_sha,
+                "gitSha",
+                GIT_SHA_PATTERN,
+                &format!("{}.commitSha", path),
+                violations,
+            );
+        }
+        if let Some(value) = &self.commit_at {
+            check_pattern(
+                value,
+                is_iso_date_time,
+                "isoDateTime",
+                ISO_DATE_TIME_PATTERN,
+                &format!("{}.commitAt", path),
+                violations,
+            );
+        }
+        if let Some(value) = &self.deployed_at {
+            check_pattern(
+                value,
+                is_iso_date_time,
+                "isoDateTime",
+                ISO_DATE_TIME_PATTERN,
+                &format!("{}.deployedAt", path),
+                violations,
+            );
+        }
+    }
 }
 
 /// Portal configuration within the descriptor
@@ -26,6 +70,20 @@ pub struct PortalConfig {
     pub public_url: String,
 }
 
+impl PortalConfig {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_pattern(
+            &self.public_url,
+            is_http_url,
+            "httpUrl",
+            HTTP_URL_PATTERN,
+            &format!("{}.publicUrl", path),
+            violations,
+        );
+    }
+}
+
 /// Keycloak configuration within the descriptor
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
@@ -38,28 +96,105 @@ pub struct KeycloakConfig {
     pub realm: String,
 }
 
-/// Deployment metadata for tracking what/when was deployed
+impl KeycloakConfig {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_pattern(
+            &self.public_url,
+            is_http_url,
+            "httpUrl",
+            HTTP_URL_PATTERN,
+            &format!("{}.publicUrl", path),
+            violations,
+        );
+        check_pattern(
+            &self.issuer_url,
+            is_http_url,
+            "httpUrl",
+            HTTP_URL_PATTERN,
+            &format!("{}.issuerUrl", path),
+            violations,
+        );
+        check_min_length(&self.realm, 1, &format!("{}.realm", path), violations);
+    }
+}
+
+/// Authentication type for a service
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthType {
+    /// `"none"`
+    None,
+    /// `"oauth2-proxy"`
+    Oauth2Proxy,
+    /// `"portal"`
+    Portal,
+}
+
+/// Display metadata for a service group
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct DeploymentInfo {
-    /// Git commit SHA that was deployed (40-character hex)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub commit_sha: Option<String>,
-    /// When the commit was made (git committer date, ISO 8601 UTC)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub commit_at: Option<String>,
-    /// When the deployment happened (ISO 8601 UTC)
+pub struct Group {
+    /// Group identifier referenced by a service's 'group'
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// Optional description
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub deployed_at: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Group {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_min_length(&self.id, 1, &format!("{}.id", path), violations);
+        check_pattern(
+            &self.id,
+            is_slug,
+            "slug",
+            SLUG_PATTERN,
+            &format!("{}.id", path),
+            violations,
+        );
+        check_min_length(&self.name, 1, &format!("{}.name", path), violations);
+        if let Some(value) = &self.description {
+            check_min_length(value, 1, &format!("{}.description", path), violations);
+        }
+    }
+}
+
+/// An additional link shown on a service card
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Link {
+    /// Link text
+    pub label: String,
+    /// Fully-qualified, browser-visible URL
+    pub url: String,
+}
+
+impl Link {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_min_length(&self.label, 1, &format!("{}.label", path), violations);
+        check_pattern(
+            &self.url,
+            is_http_url,
+            "httpUrl",
+            HTTP_URL_PATTERN,
+            &format!("{}.url", path),
+            violations,
+        );
+    }
 }
 
 /// A service entry in the descriptor
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Service {
-    /// Stable identifier / slug (e.g., "demo", "api", "docs")
+    /// Stable identifier / slug (e.g., 'demo', 'api', 'docs')
     pub id: String,
-    /// Display name (e.g., "Demo App", "API Documentation")
+    /// Display name (e.g., 'Demo App', 'API Documentation')
     pub name: String,
     /// Fully-qualified, browser-visible URL
     pub url: String,
@@ -77,27 +212,138 @@ pub struct Service {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// Required realm roles to access this service (for UI filtering)
-    ///
-    /// Rules (enforced by schema):
-    /// - Required for authType: Oauth2Proxy and Portal services
-    /// - Forbidden for authType: None services
     #[serde(skip_serializing_if = "Option::is_none")]
     pub required_realm_roles: Option<Vec<String>>,
+    /// Identity provider ids whose users may see this service (default: all providers)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_providers: Option<Vec<String>>,
+    /// Path probed for service health, relative to 'url'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_path: Option<String>,
+    /// Tags for filtering and search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Additional links (docs, runbooks, dashboards)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<Link>>,
+}
+
+impl Service {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_min_length(&self.id, 1, &format!("{}.id", path), violations);
+        check_pattern(
+            &self.id,
+            is_slug,
+            "slug",
+            SLUG_PATTERN,
+            &format!("{}.id", path),
+            violations,
+        );
+        check_min_length(&self.name, 1, &format!("{}.name", path), violations);
+        check_pattern(
+            &self.url,
+            is_http_url,
+            "httpUrl",
+            HTTP_URL_PATTERN,
+            &format!("{}.url", path),
+            violations,
+        );
+        if let Some(value) = &self.group {
+            check_min_length(value, 1, &format!("{}.group", path), violations);
+        }
+        if let Some(value) = &self.icon {
+            check_min_length(value, 1, &format!("{}.icon", path), violations);
+        }
+        if let Some(value) = &self.description {
+            check_min_length(value, 1, &format!("{}.description", path), violations);
+        }
+        if let Some(value) = &self.required_realm_roles {
+            check_min_items(
+                value.len(),
+                1,
+                &format!("{}.requiredRealmRoles", path),
+                violations,
+            );
+            for (i, item) in value.iter().enumerate() {
+                check_min_length(
+                    item,
+                    1,
+                    &format!("{}.requiredRealmRoles[{}]", path, i),
+                    violations,
+                );
+            }
+        }
+        if let Some(value) = &self.identity_providers {
+            check_min_items(
+                value.len(),
+                1,
+                &format!("{}.identityProviders", path),
+                violations,
+            );
+            for (i, item) in value.iter().enumerate() {
+                check_min_length(
+                    item,
+                    1,
+                    &format!("{}.identityProviders[{}]", path, i),
+                    violations,
+                );
+                check_pattern(
+                    item,
+                    is_slug,
+                    "slug",
+                    SLUG_PATTERN,
+                    &format!("{}.identityProviders[{}]", path, i),
+                    violations,
+                );
+            }
+        }
+        if let Some(value) = &self.health_path {
+            check_pattern(
+                value,
+                is_url_path,
+                "urlPath",
+                URL_PATH_PATTERN,
+                &format!("{}.healthPath", path),
+                violations,
+            );
+        }
+        if let Some(value) = &self.tags {
+            check_min_items(value.len(), 1, &format!("{}.tags", path), violations);
+            for (i, item) in value.iter().enumerate() {
+                check_min_length(item, 1, &format!("{}.tags[{}]", path, i), violations);
+                check_pattern(
+                    item,
+                    is_slug,
+                    "slug",
+                    SLUG_PATTERN,
+                    &format!("{}.tags[{}]", path, i),
+                    violations,
+                );
+            }
+        }
+        if let Some(value) = &self.links {
+            check_min_items(value.len(), 1, &format!("{}.links", path), violations);
+            for (i, item) in value.iter().enumerate() {
+                item.schema_violations(&format!("{}.links[{}]", path, i), violations);
+            }
+        }
+    }
 }
 
-/// Portal Descriptor v1 - Complete deployment descriptor
+/// Portal Descriptor v2 - Static deployment descriptor for service discovery
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Descriptor {
-    /// Schema version (currently "1")
+    /// Schema version ('2'; v1 descriptors are upgraded on load)
     pub version: String,
-    /// Deployment identifier (e.g., "prod", "staging", "local")
+    /// Deployment identifier (e.g., 'prod', 'staging', 'local')
     pub deployment_id: String,
-    /// Environment type (e.g., "prod", "dev")
+    /// Environment type (e.g., 'prod', 'dev')
     pub environment: String,
-    /// Base domain (e.g., "localhost", "example.com")
+    /// Base domain (e.g., 'localhost', 'example.com')
     pub base_domain: String,
-    /// Deployment metadata (commit, timestamps) - optional
+    /// Deployment metadata (commit, timestamps)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deployment: Option<DeploymentInfo>,
     /// Portal configuration
@@ -106,5 +352,182 @@ pub struct Descriptor {
     pub keycloak: KeycloakConfig,
     /// Services to display (order is display order)
     pub services: Vec<Service>,
+    /// Group display metadata (order is display order)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<Group>>,
+}
+
+impl Descriptor {
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    pub(crate) fn schema_violations(&self, path: &str, violations: &mut Vec<DescriptorViolation>) {
+        check_min_length(
+            &self.deployment_id,
+            1,
+            &format!("{}.deploymentId", path),
+            violations,
+        );
+        check_pattern(
+            &self.deployment_id,
+            is_slug,
+            "slug",
+            SLUG_PATTERN,
+            &format!("{}.deploymentId", path),
+            violations,
+        );
+        check_min_length(
+            &self.environment,
+            1,
+            &format!("{}.environment", path),
+            violations,
+        );
+        check_pattern(
+            &self.environment,
+            is_slug,
+            "slug",
+            SLUG_PATTERN,
+            &format!("{}.environment", path),
+            violations,
+        );
+        check_min_length(
+            &self.base_domain,
+            1,
+            &format!("{}.baseDomain", path),
+            violations,
+        );
+        check_pattern(
+            &self.base_domain,
+            is_descriptor_base_domain,
+            "pattern",
+            DESCRIPTOR_BASE_DOMAIN_PATTERN,
+            &format!("{}.baseDomain", path),
+            violations,
+        );
+        if let Some(value) = &self.deployment {
+            value.schema_violations(&format!("{}.deployment", path), violations);
+        }
+        self.portal
+            .schema_violations(&format!("{}.portal", path), violations);
+        self.keycloak
+            .schema_violations(&format!("{}.keycloak", path), violations);
+        for (i, item) in self.services.iter().enumerate() {
+            item.schema_violations(&format!("{}.services[{}]", path, i), violations);
+        }
+        if let Some(value) = &self.groups {
+            check_min_items(value.len(), 1, &format!("{}.groups", path), violations);
+            for (i, item) in value.iter().enumerate() {
+                item.schema_violations(&format!("{}.groups[{}]", path, i), violations);
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Pattern and format validators
+// ============================================================================
+
+/// Full 40-character git commit SHA
+pub const GIT_SHA_PATTERN: &str = r#"^[0-9a-f]{40}$"#;
+
+/// Check a value against `GIT_SHA_PATTERN`
+pub fn is_git_sha(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(GIT_SHA_PATTERN));
+    PATTERN.is_match(value)
 }
 
+/// ISO 8601 UTC datetime (e.g., '2025-02-02T15:30:00Z')
+pub const ISO_DATE_TIME_PATTERN: &str = r#"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z$"#;
+
+/// Check a value against `ISO_DATE_TIME_PATTERN`
+pub fn is_iso_date_time(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(ISO_DATE_TIME_PATTERN));
+    PATTERN.is_match(value)
+}
+
+/// Absolute URL with http:// or https:// scheme
+pub const HTTP_URL_PATTERN: &str = r#"^https?://[^\s]+$"#;
+
+/// Check a value against `HTTP_URL_PATTERN`
+pub fn is_http_url(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(HTTP_URL_PATTERN));
+    PATTERN.is_match(value)
+}
+
+/// Lowercase alphanumeric with hyphens, cannot start or end with hyphen
+pub const SLUG_PATTERN: &str = r#"^[a-z][a-z0-9-]*[a-z0-9]$|^[a-z]$"#;
+
+/// Check a value against `SLUG_PATTERN`
+pub fn is_slug(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(SLUG_PATTERN));
+    PATTERN.is_match(value)
+}
+
+/// Absolute URL path starting with '/' (e.g., '/healthz')
+pub const URL_PATH_PATTERN: &str = r#"^/[^\s]*$"#;
+
+/// Check a value against `URL_PATH_PATTERN`
+pub fn is_url_path(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(URL_PATH_PATTERN));
+    PATTERN.is_match(value)
+}
+
+/// Inline schema pattern (descriptor_base_domain)
+pub const DESCRIPTOR_BASE_DOMAIN_PATTERN: &str = r#"^[^\s]+$"#;
+
+/// Check a value against `DESCRIPTOR_BASE_DOMAIN_PATTERN`
+pub fn is_descriptor_base_domain(value: &str) -> bool {
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile(DESCRIPTOR_BASE_DOMAIN_PATTERN));
+    PATTERN.is_match(value)
+}
+
+fn compile(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("schema pattern compiles")
+}
+
+fn check_pattern(
+    value: &str,
+    is_valid: fn(&str) -> bool,
+    name: &str,
+    pattern: &str,
+    path: &str,
+    violations: &mut Vec<DescriptorViolation>,
+) {
+    if !is_valid(value) {
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message: format!("must match {} `{}`: '{}'", name, pattern, value),
+        });
+    }
+}
+
+fn check_min_length(
+    value: &str,
+    min: usize,
+    path: &str,
+    violations: &mut Vec<DescriptorViolation>,
+) {
+    if value.chars().count() < min {
+        let message = if min == 1 {
+            "must not be empty".to_string()
+        } else {
+            format!("must be at least {} characters", min)
+        };
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message,
+        });
+    }
+}
+
+fn check_min_items(len: usize, min: usize, path: &str, violations: &mut Vec<DescriptorViolation>) {
+    if len < min {
+        let message = if min == 1 {
+            "must contain at least one item".to_string()
+        } else {
+            format!("must contain at least {} items", min)
+        };
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message,
+        });
+    }
+}
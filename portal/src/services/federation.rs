@@ -0,0 +1,320 @@
+//! Federated environments (PORTAL_FEDERATED_DESCRIPTORS)
+//!
+//! A portal can show the services of other deployments (e.g. `dev` and
+//! `staging` on the `prod` portal) next to its own. Federated descriptors are
+//! read-only: they only contribute service cards, labelled with their
+//! `deploymentId`. Login, logout fan-out and single-service sign-out keep
+//! using the local descriptor.
+//!
+//! Sources are file paths (any format, or a fragment directory; loaded once)
+//! or http(s) URLs (polled like `PORTAL_DESCRIPTOR_URL`). Each one goes
+//! through the same signature policy and validation as the local descriptor.
+//! A file that fails to load stops startup, like the local descriptor. An
+//! unreachable URL does not: its section stays hidden until a fetch succeeds.
+//!
+//! Cards are filtered with each federated descriptor's own role and identity
+//! provider requirements, matched against the realm roles and provider of the
+//! user's token for this portal. The other environment's oauth2-proxy remains the
+//! enforcement point.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use super::descriptor::{Descriptor, DescriptorError, DescriptorSource};
+use super::load_descriptor;
+use super::remote::{spawn_descriptor_poller, FetchOutcome, RemoteDescriptor};
+use super::signature::SignaturePolicy;
+use super::store::{DescriptorSnapshot, DescriptorStore};
+use crate::config::{DescriptorConfig, DescriptorSource as ConfigSource};
+use crate::shutdown::Shutdown;
+
+/// One read-only environment shown next to the local deployment
+pub struct FederatedEnvironment {
+    /// Configured file path or URL
+    pub source: String,
+    /// Set once the descriptor has loaded; URL sources keep it up to date
+    store: OnceLock<Arc<DescriptorStore>>,
+}
+
+impl FederatedEnvironment {
+    fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            store: OnceLock::new(),
+        }
+    }
+
+    /// Current snapshot, or `None` until the descriptor has loaded once
+    pub async fn current(&self) -> Option<Arc<DescriptorSnapshot>> {
+        match self.store.get() {
+            Some(store) => Some(store.current().await),
+            None => None,
+        }
+    }
+}
+
+/// Federated environments in configured order (empty when not configured)
+#[derive(Default)]
+pub struct Federation {
+    environments: Vec<Arc<FederatedEnvironment>>,
+}
+
+impl Federation {
+    pub fn is_empty(&self) -> bool {
+        self.environments.is_empty()
+    }
+
+    /// Snapshots of the environments that have loaded, in configured order
+    pub async fn snapshots(&self) -> Vec<Arc<DescriptorSnapshot>> {
+        let mut snapshots = Vec::new();
+        for environment in &self.environments {
+            if let Some(snapshot) = environment.current().await {
+                snapshots.push(snapshot);
+            }
+        }
+        snapshots
+    }
+}
+
+/// Load the federated descriptors and start polling URL sources
+///
+/// # Arguments
+/// * `sources` - File paths or http(s) URLs, in display order
+/// * `public_key` - Base64 Ed25519 key; when set, every source must be signed
+/// * `poll_interval` - Poll (and retry) interval for URL sources
+/// * `connect_timeout_secs` - HTTP connect timeout
+/// * `request_timeout_secs` - HTTP request timeout
+/// * `shutdown` - Stops the pollers and retries of URL sources
+pub async fn load_federation(
+    sources: &[String],
+    public_key: Option<&str>,
+    poll_interval: Duration,
+    connect_timeout_secs: u64,
+    request_timeout_secs: u64,
+    shutdown: &Shutdown,
+) -> anyhow::Result<Arc<Federation>> {
+    let mut environments = Vec::new();
+    for source in sources {
+        let environment = Arc::new(FederatedEnvironment::new(source));
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let policy = SignaturePolicy::from_base64_key(public_key)
+                .map_err(|e| anyhow::anyhow!("Invalid PORTAL_DESCRIPTOR_PUBLIC_KEY: {}", e))?;
+            let remote = RemoteDescriptor::new(
+                source.clone(),
+                connect_timeout_secs,
+                request_timeout_secs,
+                policy,
+            )
+            .map_err(|e| anyhow::anyhow!("Federated descriptor {}: {}", source, e))?;
+            connect(environment.clone(), remote, poll_interval, shutdown).await;
+        } else {
+            let descriptor = load_descriptor(&DescriptorConfig {
+                source: ConfigSource::File(source.clone()),
+                poll_interval_secs: poll_interval.as_secs(),
+                public_key: public_key.map(str::to_string),
+            })
+            .map_err(|e| anyhow::anyhow!("Federated descriptor {}: {}", source, e))?;
+            set_descriptor(&environment, descriptor);
+        }
+        environments.push(environment);
+    }
+
+    if !environments.is_empty() {
+        tracing::info!(
+            environments = environments.len(),
+            "Federated descriptors configured"
+        );
+    }
+    Ok(Arc::new(Federation { environments }))
+}
+
+/// Fetch a URL source, retrying in the background until the first success
+///
+/// After that the regular descriptor poller keeps it up to date.
+async fn connect(
+    environment: Arc<FederatedEnvironment>,
+    mut remote: RemoteDescriptor,
+    interval: Duration,
+    shutdown: &Shutdown,
+) {
+    match fetch_descriptor(&mut remote).await {
+        Ok(descriptor) => start_polling(&environment, descriptor, remote, interval, shutdown),
+        Err(error) => {
+            tracing::warn!(
+                federated_source = %environment.source,
+                error = %error,
+                "Federated descriptor fetch failed, hiding environment until it succeeds"
+            );
+            let shutdown_handle = shutdown.clone();
+            shutdown.spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match fetch_descriptor(&mut remote).await {
+                        Ok(descriptor) => {
+                            start_polling(
+                                &environment,
+                                descriptor,
+                                remote,
+                                interval,
+                                &shutdown_handle,
+                            );
+                            return;
+                        }
+                        Err(error) => tracing::debug!(
+                            federated_source = %environment.source,
+                            error = %error,
+                            "Federated descriptor still unavailable"
+                        ),
+                    }
+                }
+            });
+        }
+    }
+}
+
+async fn fetch_descriptor(remote: &mut RemoteDescriptor) -> Result<Descriptor, DescriptorError> {
+    match remote.fetch().await? {
+        FetchOutcome::Updated(descriptor) => Ok(*descriptor),
+        // No validators are sent before the first success, so a 304 is a server bug
+        FetchOutcome::NotModified => Err(DescriptorError {
+            source: DescriptorSource::Url,
+            message: "304 Not Modified on initial fetch".to_string(),
+            field_path: None,
+            violations: Vec::new(),
+        }),
+    }
+}
+
+fn start_polling(
+    environment: &FederatedEnvironment,
+    descriptor: Descriptor,
+    remote: RemoteDescriptor,
+    interval: Duration,
+    shutdown: &Shutdown,
+) {
+    let store = set_descriptor(environment, descriptor);
+    spawn_descriptor_poller(store, remote, interval, shutdown);
+}
+
+fn set_descriptor(
+    environment: &FederatedEnvironment,
+    descriptor: Descriptor,
+) -> Arc<DescriptorStore> {
+    tracing::info!(
+        federated_source = %environment.source,
+        deployment_id = %descriptor.deployment_id,
+        environment = %descriptor.environment,
+        services = descriptor.services.len(),
+        "Federated descriptor loaded"
+    );
+    environment
+        .store
+        .get_or_init(|| Arc::new(DescriptorStore::new(descriptor)))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+    use std::sync::Mutex;
+
+    fn descriptor_json(deployment_id: &str) -> String {
+        format!(
+            r#"{{
+            "version": "2",
+            "deploymentId": "{}",
+            "environment": "{}",
+            "baseDomain": "localhost",
+            "portal": {{ "publicUrl": "http://localhost" }},
+            "keycloak": {{
+                "publicUrl": "http://keycloak.localhost",
+                "issuerUrl": "http://keycloak.localhost/realms/dev",
+                "realm": "dev"
+            }},
+            "services": []
+        }}"#,
+            deployment_id, deployment_id
+        )
+    }
+
+    /// Serves the body once it is set, 503 before that
+    async fn serve(body: Arc<Mutex<Option<String>>>) -> String {
+        let app = Router::new().route(
+            "/descriptor.json",
+            get(move || {
+                let body = body.lock().unwrap().clone();
+                async move {
+                    match body {
+                        Some(body) => body.into_response(),
+                        None => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/descriptor.json", addr)
+    }
+
+    async fn load(sources: &[String]) -> anyhow::Result<Arc<Federation>> {
+        load_federation(
+            sources,
+            None,
+            Duration::from_millis(20),
+            2,
+            5,
+            &Shutdown::default(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_loads_file_and_url_sources_in_order() {
+        let path = std::env::temp_dir().join("test_federated_dev.json");
+        std::fs::write(&path, descriptor_json("dev")).unwrap();
+        let url = serve(Arc::new(Mutex::new(Some(descriptor_json("staging"))))).await;
+
+        let federation = load(&[url, path.to_string_lossy().to_string()])
+            .await
+            .unwrap();
+        let ids: Vec<String> = federation
+            .snapshots()
+            .await
+            .iter()
+            .map(|s| s.descriptor.deployment_id.clone())
+            .collect();
+        assert_eq!(ids, vec!["staging", "dev"]);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_source_fails_startup() {
+        let path = std::env::temp_dir().join("test_federated_invalid.json");
+        std::fs::write(&path, "{}").unwrap();
+        let error = load(&[path.to_string_lossy().to_string()])
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("Federated descriptor "));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_url_is_hidden_until_it_loads() {
+        let body = Arc::new(Mutex::new(None));
+        let url = serve(body.clone()).await;
+
+        let federation = load(&[url]).await.unwrap();
+        assert!(!federation.is_empty());
+        assert!(federation.snapshots().await.is_empty());
+
+        *body.lock().unwrap() = Some(descriptor_json("staging"));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let snapshots = federation.snapshots().await;
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].descriptor.deployment_id, "staging");
+    }
+}
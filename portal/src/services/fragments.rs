@@ -0,0 +1,425 @@
+//! Descriptor fragments merged from a directory
+//!
+//! `PORTAL_DESCRIPTOR_PATH` may point at a directory instead of a file, so
+//! teams can own their services in separate files:
+//!
+//! - `base.{json,yaml,yml,toml}`: a complete descriptor (any supported
+//!   version) holding the deployment, portal and keycloak settings and
+//!   optionally shared services
+//! - every other `.json`/`.yaml`/`.yml`/`.toml` file: a fragment with
+//!   `services` (current service fields) and an optional integer `order`
+//!
+//! Merge order is deterministic: base services first, then fragments sorted
+//! by `order` (default 0) and file name. Service ids must be unique across
+//! all files. Violations found when validating the merged descriptor are
+//! attributed to the file the offending service came from.
+//!
+//! Hidden entries (e.g. Kubernetes `..data` links), subdirectories and
+//! detached `.sig` files are skipped; when a public key is configured every
+//! file must be signed.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::descriptor::{
+    Descriptor, DescriptorError, DescriptorFormat, DescriptorSource, DescriptorViolation, Service,
+};
+use super::signature::{SignaturePolicy, DETACHED_SIGNATURE_EXTENSION};
+
+/// File stem of the base descriptor in a fragment directory
+pub const BASE_FILE_STEM: &str = "base";
+
+/// A per-team fragment file
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Fragment {
+    /// Ordering hint; lower values are merged first
+    #[serde(default)]
+    order: i64,
+    services: Vec<Service>,
+}
+
+/// Range of merged services contributed by one file
+#[derive(Debug, Clone)]
+struct FragmentSpan {
+    file: String,
+    start: usize,
+    len: usize,
+}
+
+/// Descriptor merged from a directory, with the origin of each service
+#[derive(Debug)]
+pub struct MergedDescriptor {
+    pub descriptor: Descriptor,
+    base_file: String,
+    spans: Vec<FragmentSpan>,
+}
+
+impl MergedDescriptor {
+    /// Rewrite violation paths relative to the file they came from
+    ///
+    /// `$.services[5].url` becomes `[team-b.yaml] $.services[1].url`; paths
+    /// outside `services` are attributed to the base file.
+    pub fn attribute(&self, violations: Vec<DescriptorViolation>) -> Vec<DescriptorViolation> {
+        violations
+            .into_iter()
+            .map(|violation| DescriptorViolation {
+                path: self.attribute_path(&violation.path),
+                message: violation.message,
+            })
+            .collect()
+    }
+
+    fn attribute_path(&self, path: &str) -> String {
+        let service = path.strip_prefix("$.services[").and_then(|rest| {
+            let (index, rest) = rest.split_once(']')?;
+            Some((index.parse::<usize>().ok()?, rest))
+        });
+        if let Some((index, rest)) = service {
+            if let Some(span) = self
+                .spans
+                .iter()
+                .find(|span| (span.start..span.start + span.len).contains(&index))
+            {
+                return format!("[{}] $.services[{}]{}", span.file, index - span.start, rest);
+            }
+        }
+        format!("[{}] {}", self.base_file, path)
+    }
+}
+
+/// Load and merge a fragment directory (validation is left to the caller)
+pub fn load_fragment_dir(
+    dir: &str,
+    policy: &SignaturePolicy,
+) -> Result<MergedDescriptor, DescriptorError> {
+    let mut base = None;
+    let mut fragments = Vec::new();
+    for file in fragment_files(dir)? {
+        let path = Path::new(dir).join(&file);
+        let path = path.to_string_lossy();
+        let content = policy
+            .verify_file(&path)
+            .map_err(|e| attribute_error(&file, e))?;
+        let format = DescriptorFormat::from_path(&file);
+
+        if Path::new(&file).file_stem().and_then(|s| s.to_str()) == Some(BASE_FILE_STEM) {
+            if let Some((existing, _)) = base {
+                return Err(dir_error(format!(
+                    "both {} and {} are base files; keep one",
+                    existing, file
+                )));
+            }
+            let descriptor =
+                Descriptor::from_str_with_format(&content, format, DescriptorSource::FilePath)
+                    .map_err(|e| attribute_error(&file, e))?;
+            base = Some((file, descriptor));
+        } else {
+            let fragment: Fragment = format
+                .parse(&content, DescriptorSource::FilePath)
+                .map_err(|e| attribute_error(&file, e))?;
+            fragments.push((file, fragment));
+        }
+    }
+
+    let Some((base_file, mut descriptor)) = base else {
+        return Err(dir_error(format!(
+            "no {}.json, {}.yaml, {}.yml or {}.toml in {}",
+            BASE_FILE_STEM, BASE_FILE_STEM, BASE_FILE_STEM, BASE_FILE_STEM, dir
+        )));
+    };
+
+    // Files arrive sorted by name; the stable sort keeps that order within equal hints
+    fragments.sort_by_key(|(_, fragment)| fragment.order);
+
+    let mut spans = vec![FragmentSpan {
+        file: base_file.clone(),
+        start: 0,
+        len: descriptor.services.len(),
+    }];
+    for (file, fragment) in fragments {
+        spans.push(FragmentSpan {
+            file,
+            start: descriptor.services.len(),
+            len: fragment.services.len(),
+        });
+        descriptor.services.extend(fragment.services);
+    }
+
+    let merged = MergedDescriptor {
+        descriptor,
+        base_file,
+        spans,
+    };
+    let duplicates = duplicate_ids(&merged);
+    if !duplicates.is_empty() {
+        return Err(DescriptorError::validation(
+            DescriptorSource::FilePath,
+            duplicates,
+        ));
+    }
+
+    tracing::info!(
+        source = %DescriptorSource::FilePath,
+        base = %merged.base_file,
+        fragments = merged.spans.len() - 1,
+        services = merged.descriptor.services.len(),
+        "Merged descriptor fragments"
+    );
+    Ok(merged)
+}
+
+/// Descriptor files in `dir`, sorted by name
+fn fragment_files(dir: &str) -> Result<Vec<String>, DescriptorError> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| dir_error(format!("failed to read directory '{}': {}", dir, e)))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| dir_error(format!("failed to read directory '{}': {}", dir, e)))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Follows symlinks, so mounted ConfigMap keys count as files
+        if name.starts_with('.') || entry.path().is_dir() {
+            continue;
+        }
+        let extension = Path::new(&name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") | Some("yaml") | Some("yml") | Some("toml") => files.push(name),
+            Some(DETACHED_SIGNATURE_EXTENSION) => {}
+            _ => {
+                return Err(dir_error(format!(
+                    "unsupported file '{}' in descriptor directory (expected .json, .yaml, .yml or .toml)",
+                    name
+                )))
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Service ids defined in more than one place
+fn duplicate_ids(merged: &MergedDescriptor) -> Vec<DescriptorViolation> {
+    let mut first_index: HashMap<&str, usize> = HashMap::new();
+    let mut violations = Vec::new();
+    for (index, service) in merged.descriptor.services.iter().enumerate() {
+        match first_index.entry(&service.id) {
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+            }
+            Entry::Occupied(entry) => violations.push(DescriptorViolation {
+                path: merged.attribute_path(&format!("$.services[{}].id", index)),
+                message: format!(
+                    "duplicate service id '{}' (already defined at {})",
+                    service.id,
+                    merged.attribute_path(&format!("$.services[{}]", entry.get()))
+                ),
+            }),
+        }
+    }
+    violations
+}
+
+/// Name the file a parse or signature error came from
+fn attribute_error(file: &str, mut error: DescriptorError) -> DescriptorError {
+    error.field_path = Some(match error.field_path {
+        Some(location) => format!("{} {}", file, location),
+        None => file.to_string(),
+    });
+    error
+}
+
+fn dir_error(message: String) -> DescriptorError {
+    DescriptorError {
+        source: DescriptorSource::FilePath,
+        message,
+        field_path: None,
+        violations: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"{
+        "version": "2",
+        "deploymentId": "local",
+        "environment": "dev",
+        "baseDomain": "localhost",
+        "portal": { "publicUrl": "http://localhost" },
+        "keycloak": {
+            "publicUrl": "http://keycloak.localhost",
+            "issuerUrl": "http://keycloak.localhost/realms/dev",
+            "realm": "dev"
+        },
+        "services": [
+            {
+                "id": "docs",
+                "name": "Docs",
+                "url": "http://docs.localhost",
+                "protected": false,
+                "authType": "none"
+            }
+        ]
+    }"#;
+
+    const TEAM_A: &str = r#"
+order = 10
+
+[[services]]
+id = "grafana"
+name = "Grafana"
+url = "http://grafana.localhost"
+protected = true
+authType = "oauth2-proxy"
+requiredRealmRoles = ["ops"]
+"#;
+
+    const TEAM_B: &str = r#"
+services:
+  - id: demo
+    name: Demo
+    url: http://demo.localhost
+    protected: false
+    authType: none
+"#;
+
+    /// Fresh directory containing the given files
+    fn fragment_dir(name: &str, files: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("portal_fragments_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        dir.to_string_lossy().to_string()
+    }
+
+    fn load(dir: &str) -> Result<MergedDescriptor, DescriptorError> {
+        load_fragment_dir(dir, &SignaturePolicy::default())
+    }
+
+    #[test]
+    fn test_merge_order() {
+        let dir = fragment_dir(
+            "order",
+            &[
+                ("base.json", BASE),
+                ("team-a.toml", TEAM_A),
+                ("team-b.yaml", TEAM_B),
+                ("base.json.sig", "ignored without a public key"),
+                (".hidden.json", "not a descriptor"),
+            ],
+        );
+        let merged = load(&dir).unwrap();
+        let ids: Vec<&str> = merged
+            .descriptor
+            .services
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect();
+        // Base first, then team-b (order 0) before team-a (order 10)
+        assert_eq!(ids, vec!["docs", "demo", "grafana"]);
+        assert!(merged.descriptor.validate().is_ok());
+    }
+
+    #[test]
+    fn test_equal_order_falls_back_to_file_name() {
+        let dir = fragment_dir(
+            "names",
+            &[
+                ("base.json", BASE),
+                ("b.yaml", TEAM_B),
+                ("a.toml", &TEAM_A.replace("order = 10", "")),
+            ],
+        );
+        let ids: Vec<String> = load(&dir)
+            .unwrap()
+            .descriptor
+            .services
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids, vec!["docs", "grafana", "demo"]);
+    }
+
+    #[test]
+    fn test_rejects_duplicate_ids_across_fragments() {
+        let dir = fragment_dir(
+            "duplicates",
+            &[
+                ("base.json", BASE),
+                ("team-b.yaml", TEAM_B),
+                ("team-c.yaml", &TEAM_B.replace("Demo", "Demo again")),
+            ],
+        );
+        let err = load(&dir).unwrap_err();
+        assert_eq!(err.violations.len(), 1);
+        assert_eq!(err.violations[0].path, "[team-c.yaml] $.services[0].id");
+        assert!(err.violations[0]
+            .message
+            .contains("already defined at [team-b.yaml] $.services[0]"));
+    }
+
+    #[test]
+    fn test_attributes_errors_to_fragments() {
+        let dir = fragment_dir(
+            "attribution",
+            &[
+                ("base.json", BASE),
+                ("team-a.toml", TEAM_A),
+                (
+                    "team-b.yaml",
+                    &TEAM_B.replace("http://demo.localhost", "demo.localhost"),
+                ),
+            ],
+        );
+        let merged = load(&dir).unwrap();
+        let violations = merged.attribute(merged.descriptor.validate().unwrap_err());
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "[team-b.yaml] $.services[0].url");
+
+        let dir = fragment_dir(
+            "parse_error",
+            &[
+                ("base.json", BASE),
+                ("team-b.yaml", "services: []\nextra: 1\n"),
+            ],
+        );
+        let err = load(&dir).unwrap_err();
+        assert_eq!(
+            err.field_path.as_deref(),
+            Some("team-b.yaml line 2 column 1")
+        );
+        assert!(err.message.contains("unknown field `extra`"));
+    }
+
+    #[test]
+    fn test_requires_single_base_file() {
+        let dir = fragment_dir("no_base", &[("team-b.yaml", TEAM_B)]);
+        assert!(load(&dir).unwrap_err().message.contains("no base.json"));
+
+        let dir = fragment_dir("two_bases", &[("base.json", BASE), ("base.yaml", BASE)]);
+        assert!(load(&dir)
+            .unwrap_err()
+            .message
+            .contains("both base.json and base.yaml"));
+
+        let dir = fragment_dir(
+            "unsupported",
+            &[("base.json", BASE), ("README.md", "# Teams")],
+        );
+        assert!(load(&dir)
+            .unwrap_err()
+            .message
+            .contains("unsupported file 'README.md'"));
+    }
+}
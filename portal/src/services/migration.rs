@@ -0,0 +1,129 @@
+//! Descriptor version migrations
+//!
+//! Each supported version is parsed strictly into its own generated types and
+//! then upgraded step by step to the current in-memory model (v2), so the rest
+//! of the portal only deals with `Descriptor`.
+//!
+//! v1 -> v2 is purely additive: every v1 field carries over unchanged, and the
+//! v2-only fields (`groups`, service `identityProviders`, `healthPath`, `tags`,
+//! `links`) are absent.
+
+use super::descriptor_gen::{
+    AuthType, DeploymentInfo, Descriptor, KeycloakConfig, PortalConfig, Service,
+};
+use super::descriptor_v1_gen as v1;
+
+/// Version of the in-memory descriptor model
+pub const CURRENT_VERSION: &str = "2";
+
+/// Upgrade a v1 descriptor to the current model
+pub(crate) fn upgrade_v1(descriptor: v1::Descriptor) -> Descriptor {
+    Descriptor {
+        version: CURRENT_VERSION.to_string(),
+        deployment_id: descriptor.deployment_id,
+        environment: descriptor.environment,
+        base_domain: descriptor.base_domain,
+        deployment: descriptor.deployment.map(|d| DeploymentInfo {
+            commit_sha: d.commit_sha,
+            commit_at: d.commit_at,
+            deployed_at: d.deployed_at,
+        }),
+        portal: PortalConfig {
+            public_url: descriptor.portal.public_url,
+        },
+        keycloak: KeycloakConfig {
+            public_url: descriptor.keycloak.public_url,
+            issuer_url: descriptor.keycloak.issuer_url,
+            realm: descriptor.keycloak.realm,
+        },
+        services: descriptor
+            .services
+            .into_iter()
+            .map(upgrade_v1_service)
+            .collect(),
+        groups: None,
+    }
+}
+
+fn upgrade_v1_service(service: v1::Service) -> Service {
+    Service {
+        id: service.id,
+        name: service.name,
+        url: service.url,
+        protected: service.protected,
+        auth_type: match service.auth_type {
+            v1::AuthType::None => AuthType::None,
+            v1::AuthType::Oauth2Proxy => AuthType::Oauth2Proxy,
+            v1::AuthType::Portal => AuthType::Portal,
+        },
+        group: service.group,
+        icon: service.icon,
+        description: service.description,
+        required_realm_roles: service.required_realm_roles,
+        identity_providers: None,
+        health_path: None,
+        tags: None,
+        links: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_v1_keeps_every_field() {
+        let v1: v1::Descriptor = serde_json::from_str(
+            r#"{
+            "version": "1",
+            "deploymentId": "prod",
+            "environment": "prod",
+            "baseDomain": "example.com",
+            "deployment": { "commitSha": "0123456789abcdef0123456789abcdef01234567" },
+            "portal": { "publicUrl": "https://portal.example.com" },
+            "keycloak": {
+                "publicUrl": "https://auth.example.com",
+                "issuerUrl": "https://auth.example.com/realms/prod",
+                "realm": "prod"
+            },
+            "services": [
+                {
+                    "id": "grafana",
+                    "name": "Grafana",
+                    "url": "https://grafana.example.com",
+                    "protected": true,
+                    "authType": "oauth2-proxy",
+                    "group": "observability",
+                    "icon": "chart",
+                    "description": "Dashboards",
+                    "requiredRealmRoles": ["ops"]
+                }
+            ]
+        }"#,
+        )
+        .unwrap();
+
+        let descriptor = upgrade_v1(v1);
+
+        assert_eq!(descriptor.version, CURRENT_VERSION);
+        assert_eq!(descriptor.deployment_id, "prod");
+        assert_eq!(descriptor.base_domain, "example.com");
+        assert_eq!(
+            descriptor.deployment.unwrap().commit_sha.as_deref(),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+        assert_eq!(descriptor.keycloak.realm, "prod");
+        assert!(descriptor.groups.is_none());
+
+        let service = &descriptor.services[0];
+        assert_eq!(service.id, "grafana");
+        assert_eq!(service.auth_type, AuthType::Oauth2Proxy);
+        assert_eq!(service.group.as_deref(), Some("observability"));
+        assert_eq!(service.icon.as_deref(), Some("chart"));
+        assert_eq!(service.description.as_deref(), Some("Dashboards"));
+        assert_eq!(service.required_realm_roles, Some(vec!["ops".to_string()]));
+        assert!(service.health_path.is_none());
+        assert!(service.tags.is_none());
+        assert!(service.links.is_none());
+    }
+}
@@ -1,57 +1,201 @@
 pub mod authz;
 pub mod descriptor;
 mod descriptor_gen;
+mod descriptor_v1_gen;
+pub mod federation;
+pub mod fragments;
+pub mod migration;
 pub mod models;
+pub mod remote;
+pub mod signature;
+pub mod store;
+mod validation;
 
 pub use authz::{build_role_set, can_access_service, ADMIN_ROLE};
 pub use descriptor::{
-    AuthType, Descriptor, DescriptorError, DescriptorSource, DescriptorSummary, KeycloakDescriptor,
-    PortalDescriptor, ServiceDescriptor,
+    AuthType, Descriptor, DescriptorError, DescriptorFormat, DescriptorSource, DescriptorSummary,
+    DescriptorViolation, KeycloakDescriptor, PortalDescriptor, ServiceDescriptor,
 };
 // Re-export generated types for direct access
 pub use descriptor_gen::{KeycloakConfig, PortalConfig, Service};
+pub(crate) use descriptor_gen::is_slug;
+pub use federation::{load_federation, FederatedEnvironment, Federation};
+pub use fragments::{load_fragment_dir, MergedDescriptor};
 pub use models::ServiceCard;
+pub use remote::{spawn_descriptor_poller, FetchOutcome, RemoteDescriptor};
+pub use signature::{SignaturePolicy, SignedEnvelope};
+pub use store::{DescriptorSnapshot, DescriptorStore};
 
-use crate::config::{DescriptorConfig, DescriptorSource as ConfigSource};
+use std::sync::Arc;
+use std::time::Duration;
 
-/// Load and validate the portal descriptor from the configured source
+use crate::config::{Config, DescriptorConfig, DescriptorSource as ConfigSource};
+use crate::shutdown::Shutdown;
+
+/// Load and validate the portal descriptor from a static source
 ///
 /// This function:
-/// 1. Loads the descriptor from the configured source (env var or file)
-/// 2. Validates the descriptor against strict rules
-/// 3. Returns the descriptor or a detailed error
+/// 1. Loads the descriptor from the configured source (env var, file or a
+///    directory of fragments; files may be JSON, YAML or TOML by extension)
+/// 2. Verifies its signature if a public key is configured
+/// 3. Validates the descriptor against strict rules
+/// 4. Returns the descriptor or a detailed error
+///
+/// The URL source needs an async fetch; use `load_descriptor_store` for it.
 ///
 /// Note: This function never logs the raw descriptor JSON to avoid
 /// accidental leakage if someone violates the "non-secret" rule.
 pub fn load_descriptor(config: &DescriptorConfig) -> anyhow::Result<Descriptor> {
+    let policy = signature_policy(config)?;
+
+    // Set for a fragment directory, to attribute violations to their file
+    let mut merged_from = None;
     let (descriptor, source) = match &config.source {
         ConfigSource::Json(json) => {
-            let desc = Descriptor::from_json_with_source(json, DescriptorSource::EnvJson)
+            let desc = policy
+                .verify(json, None, DescriptorSource::EnvJson)
+                .and_then(|payload| {
+                    Descriptor::from_json_with_source(&payload, DescriptorSource::EnvJson)
+                })
                 .map_err(|e| anyhow::anyhow!("{}", e))?;
             (desc, DescriptorSource::EnvJson)
         }
+        ConfigSource::File(path) if std::path::Path::new(path).is_dir() => {
+            let merged =
+                load_fragment_dir(path, &policy).map_err(|e| anyhow::anyhow!("{}", e))?;
+            let desc = merged.descriptor.clone();
+            merged_from = Some(merged);
+            (desc, DescriptorSource::FilePath)
+        }
         ConfigSource::File(path) => {
-            let desc = Descriptor::from_file(path).map_err(|e| anyhow::anyhow!("{}", e))?;
+            let desc = policy
+                .verify_file(path)
+                .and_then(|payload| {
+                    Descriptor::from_str_with_format(
+                        &payload,
+                        DescriptorFormat::from_path(path),
+                        DescriptorSource::FilePath,
+                    )
+                })
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
             (desc, DescriptorSource::FilePath)
         }
+        ConfigSource::Url(_) => {
+            return Err(anyhow::anyhow!(
+                "{} is fetched asynchronously; use load_descriptor_store",
+                DescriptorSource::Url
+            ));
+        }
     };
 
     // Validate the descriptor
-    if let Err(error) = descriptor.validate() {
+    if let Err(violations) = descriptor.validate() {
+        let violations = match &merged_from {
+            Some(merged) => merged.attribute(violations),
+            None => violations,
+        };
+        let error = DescriptorError::validation(source, violations);
         // Log which source was used, but never log the raw JSON
         tracing::error!(
             source = %source,
+            violations = error.violations.len(),
             error = %error,
             "Descriptor validation failed"
         );
-        return Err(anyhow::anyhow!(
-            "Descriptor validation failed ({}): {}",
-            source,
-            error
-        ));
+        return Err(anyhow::anyhow!("{}", error));
     }
 
-    // Log summary (non-sensitive) for debugging
+    log_descriptor_summary(&descriptor, source);
+
+    Ok(descriptor)
+}
+
+/// Load the descriptor from any configured source into a shared store
+///
+/// For `PORTAL_DESCRIPTOR_URL`, the initial fetch must succeed (fail fast at
+/// startup) and a background poller keeps the store up to date afterwards,
+/// until `shutdown` stops background tasks.
+pub async fn load_descriptor_store(
+    config: &Config,
+    shutdown: &Shutdown,
+) -> anyhow::Result<Arc<DescriptorStore>> {
+    let ConfigSource::Url(url) = &config.descriptor.source else {
+        let descriptor = load_descriptor(&config.descriptor)?;
+        return Ok(Arc::new(DescriptorStore::new(descriptor)));
+    };
+
+    let (descriptor, remote) = fetch_remote_descriptor(config, url).await?;
+
+    log_descriptor_summary(&descriptor, DescriptorSource::Url);
+
+    let store = Arc::new(DescriptorStore::new(descriptor));
+    spawn_descriptor_poller(
+        store.clone(),
+        remote,
+        Duration::from_secs(config.descriptor.poll_interval_secs),
+        shutdown,
+    );
+    tracing::info!(
+        poll_interval_secs = config.descriptor.poll_interval_secs,
+        "Remote descriptor polling started"
+    );
+
+    Ok(store)
+}
+
+/// Load the descriptor once from any configured source, without polling
+///
+/// Used by `portal check-config` to verify the source is reachable and valid.
+pub async fn check_descriptor_source(config: &Config) -> anyhow::Result<DescriptorSummary> {
+    let descriptor = match &config.descriptor.source {
+        ConfigSource::Url(url) => fetch_remote_descriptor(config, url).await?.0,
+        _ => load_descriptor(&config.descriptor)?,
+    };
+    Ok(descriptor.summary())
+}
+
+/// Initial fetch of `PORTAL_DESCRIPTOR_URL` (must succeed)
+async fn fetch_remote_descriptor(
+    config: &Config,
+    url: &str,
+) -> anyhow::Result<(Descriptor, RemoteDescriptor)> {
+    let mut remote = RemoteDescriptor::new(
+        url.to_string(),
+        config.http_connect_timeout_secs,
+        config.http_request_timeout_secs,
+        signature_policy(&config.descriptor)?,
+    )
+    .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    let descriptor = match remote.fetch().await {
+        Ok(FetchOutcome::Updated(descriptor)) => *descriptor,
+        Ok(FetchOutcome::NotModified) => {
+            // No validators are sent on the first fetch, so a 304 is a server bug
+            return Err(anyhow::anyhow!(
+                "{} returned 304 Not Modified on initial fetch",
+                DescriptorSource::Url
+            ));
+        }
+        Err(error) => {
+            tracing::error!(
+                source = %DescriptorSource::Url,
+                error = %error,
+                "Descriptor fetch failed"
+            );
+            return Err(anyhow::anyhow!("{}", error));
+        }
+    };
+    Ok((descriptor, remote))
+}
+
+/// Build the signature policy from the configured public key
+fn signature_policy(config: &DescriptorConfig) -> anyhow::Result<SignaturePolicy> {
+    SignaturePolicy::from_base64_key(config.public_key.as_deref())
+        .map_err(|e| anyhow::anyhow!("Invalid PORTAL_DESCRIPTOR_PUBLIC_KEY: {}", e))
+}
+
+/// Log a non-sensitive descriptor summary for debugging
+fn log_descriptor_summary(descriptor: &Descriptor, source: DescriptorSource) {
     let summary = descriptor.summary();
     tracing::info!(
         source = %source,
@@ -65,8 +209,6 @@ pub fn load_descriptor(config: &DescriptorConfig) -> anyhow::Result<Descriptor>
         public_services = summary.public_services,
         "Descriptor loaded successfully"
     );
-
-    Ok(descriptor)
 }
 
 /// Convert descriptor services to ServiceCard for UI rendering
@@ -83,6 +225,7 @@ pub fn services_from_descriptor(descriptor: &Descriptor) -> Vec<ServiceCard> {
             protected: s.protected,
             auth_type: s.auth_type.clone(),
             required_realm_roles: s.required_realm_roles.clone(),
+            identity_providers: s.identity_providers.clone(),
         })
         .collect()
 }
@@ -91,16 +234,21 @@ pub fn services_from_descriptor(descriptor: &Descriptor) -> Vec<ServiceCard> {
 ///
 /// Per plan.md 2.7: Portal should only show service cards the user can access.
 /// This is UI-only filtering; oauth2-proxy remains the enforcement point.
+/// Services restricted to other identity providers are hidden as well.
 ///
 /// Uses a precomputed HashSet for efficient role lookups across all services.
 pub fn filter_services_for_user(
     services: &[ServiceCard],
     user_roles: &[String],
+    provider: &str,
 ) -> Vec<ServiceCard> {
     let role_set = build_role_set(user_roles);
     services
         .iter()
-        .filter(|service| service.is_accessible_by_role_set(&role_set))
+        .filter(|service| {
+            service.is_available_to_provider(provider)
+                && service.is_accessible_by_role_set(&role_set)
+        })
         .cloned()
         .collect()
 }
@@ -138,6 +286,8 @@ mod tests {
     fn test_load_descriptor_from_json_config() {
         let config = DescriptorConfig {
             source: ConfigSource::Json(sample_descriptor_json().to_string()),
+            poll_interval_secs: 60,
+            public_key: None,
         };
         let result = load_descriptor(&config);
         assert!(result.is_ok());
@@ -154,6 +304,8 @@ mod tests {
 
         let config = DescriptorConfig {
             source: ConfigSource::File(temp_file.to_string_lossy().to_string()),
+            poll_interval_secs: 60,
+            public_key: None,
         };
         let result = load_descriptor(&config);
         assert!(result.is_ok());
@@ -164,6 +316,61 @@ mod tests {
         std::fs::remove_file(&temp_file).ok();
     }
 
+    #[test]
+    fn test_load_descriptor_from_fragment_dir() {
+        let dir = std::env::temp_dir().join("test_descriptor_fragments");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("base.json"), sample_descriptor_json()).unwrap();
+        std::fs::write(
+            dir.join("team.yaml"),
+            "services:\n  - id: docs\n    name: Docs\n    url: docs.localhost\n    protected: false\n    authType: none\n",
+        )
+        .unwrap();
+
+        let config = DescriptorConfig {
+            source: ConfigSource::File(dir.to_string_lossy().to_string()),
+            poll_interval_secs: 60,
+            public_key: None,
+        };
+        // Violations name the fragment and the path inside it
+        let error = load_descriptor(&config).unwrap_err().to_string();
+        assert!(error.contains("[team.yaml] $.services[0].url"), "{}", error);
+
+        std::fs::write(
+            dir.join("team.yaml"),
+            "services:\n  - id: docs\n    name: Docs\n    url: http://docs.localhost\n    protected: false\n    authType: none\n",
+        )
+        .unwrap();
+        let descriptor = load_descriptor(&config).unwrap();
+        assert_eq!(descriptor.services.len(), 2);
+        assert_eq!(descriptor.services[1].id, "docs");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_descriptor_requires_signature_when_key_configured() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[9; 32]);
+        let public_key = signature::encode_base64(key.verifying_key().as_bytes());
+
+        let unsigned = DescriptorConfig {
+            source: ConfigSource::Json(sample_descriptor_json().to_string()),
+            poll_interval_secs: 60,
+            public_key: Some(public_key.clone()),
+        };
+        let err = load_descriptor(&unsigned).unwrap_err();
+        assert!(err.to_string().contains("not signed"));
+
+        let envelope = signature::sign_envelope(sample_descriptor_json(), &key);
+        let signed = DescriptorConfig {
+            source: ConfigSource::Json(serde_json::to_string(&envelope).unwrap()),
+            poll_interval_secs: 60,
+            public_key: Some(public_key),
+        };
+        assert_eq!(load_descriptor(&signed).unwrap().deployment_id, "local");
+    }
+
     #[test]
     fn test_services_from_descriptor() {
         let descriptor =
@@ -198,6 +405,7 @@ mod tests {
                 protected: true,
                 auth_type: AuthType::Oauth2Proxy,
                 required_realm_roles: Some(vec!["admin".to_string(), "dev".to_string()]),
+                identity_providers: None,
             },
             ServiceCard {
                 id: "dozzle".to_string(),
@@ -208,6 +416,7 @@ mod tests {
                 protected: true,
                 auth_type: AuthType::Oauth2Proxy,
                 required_realm_roles: Some(vec!["admin".to_string()]),
+                identity_providers: None,
             },
             ServiceCard {
                 id: "docs".to_string(),
@@ -218,6 +427,7 @@ mod tests {
                 protected: false,
                 auth_type: AuthType::None,
                 required_realm_roles: None,
+                identity_providers: None,
             },
             ServiceCard {
                 id: "admin-panel".to_string(),
@@ -228,25 +438,60 @@ mod tests {
                 protected: true,
                 auth_type: AuthType::Portal,
                 required_realm_roles: Some(vec!["admin".to_string()]),
+                identity_providers: None,
             },
         ];
 
         // Test: dev user sees demo + docs only (not dozzle, not admin-panel)
         let dev_roles = vec!["dev".to_string()];
-        let dev_services = filter_services_for_user(&services, &dev_roles);
+        let dev_services = filter_services_for_user(&services, &dev_roles, "default");
         let dev_ids: Vec<&str> = dev_services.iter().map(|s| s.id.as_str()).collect();
         assert_eq!(dev_ids, vec!["demo", "docs"]);
 
         // Test: admin user sees everything
         let admin_roles = vec!["admin".to_string()];
-        let admin_services = filter_services_for_user(&services, &admin_roles);
+        let admin_services = filter_services_for_user(&services, &admin_roles, "default");
         let admin_ids: Vec<&str> = admin_services.iter().map(|s| s.id.as_str()).collect();
         assert_eq!(admin_ids, vec!["demo", "dozzle", "docs", "admin-panel"]);
 
         // Test: user with no roles sees only public services
         let no_roles: Vec<String> = vec![];
-        let no_role_services = filter_services_for_user(&services, &no_roles);
+        let no_role_services = filter_services_for_user(&services, &no_roles, "default");
         let no_role_ids: Vec<&str> = no_role_services.iter().map(|s| s.id.as_str()).collect();
         assert_eq!(no_role_ids, vec!["docs"]);
     }
+
+    #[test]
+    fn test_filter_services_for_user_by_identity_provider() {
+        let card = |id: &str, providers: Option<Vec<String>>| ServiceCard {
+            id: id.to_string(),
+            name: id.to_string(),
+            url: format!("http://{}.localhost", id),
+            icon: "box".to_string(),
+            description: None,
+            protected: false,
+            auth_type: AuthType::None,
+            required_realm_roles: None,
+            identity_providers: providers,
+        };
+        let services = vec![
+            card("wiki", None),
+            card("internal", Some(vec!["default".to_string()])),
+            card("timesheets", Some(vec!["contractors".to_string()])),
+        ];
+
+        let ids = |provider: &str, roles: &[String]| -> Vec<String> {
+            filter_services_for_user(&services, roles, provider)
+                .into_iter()
+                .map(|s| s.id)
+                .collect()
+        };
+        assert_eq!(ids("default", &[]), vec!["wiki", "internal"]);
+        assert_eq!(ids("contractors", &[]), vec!["wiki", "timesheets"]);
+        // Admins of one provider do not see services restricted to another
+        assert_eq!(
+            ids("contractors", &["admin".to_string()]),
+            vec!["wiki", "timesheets"]
+        );
+    }
 }
@@ -23,6 +23,8 @@ pub struct ServiceCard {
     pub auth_type: AuthType,
     /// Required realm roles to access this service (for UI filtering)
     pub required_realm_roles: Option<Vec<String>>,
+    /// Identity providers whose users may see this service (None = all)
+    pub identity_providers: Option<Vec<String>>,
 }
 
 impl ServiceCard {
@@ -51,4 +53,23 @@ impl ServiceCard {
             self.required_realm_roles.as_deref(),
         )
     }
+
+    /// Check if users signed in with the given identity provider may see this service
+    ///
+    /// Applies before the role check, so it also hides the service from admins
+    /// of other providers.
+    pub fn is_available_to_provider(&self, provider: &str) -> bool {
+        match &self.identity_providers {
+            Some(providers) => providers.iter().any(|p| p == provider),
+            None => true,
+        }
+    }
+
+    /// Whether the dashboard offers a "sign out of this service" action
+    ///
+    /// Only oauth2-proxy services hold their own session cookie that the portal
+    /// can clear via `/oauth2/sign_out`.
+    pub fn supports_sign_out(&self) -> bool {
+        self.auth_type == AuthType::Oauth2Proxy
+    }
 }
@@ -0,0 +1,469 @@
+//! Remote descriptor source (PORTAL_DESCRIPTOR_URL)
+//!
+//! Fetches the descriptor over HTTP(S) at startup, then polls it with
+//! conditional requests (`If-None-Match` / `If-Modified-Since`) so unchanged
+//! descriptors cost a 304 round-trip.
+//!
+//! Every fetched descriptor goes through the same pipeline as static sources:
+//! size guard, signature verification, strict parsing (`deny_unknown_fields`)
+//! and `validate()`. Signed remote descriptors use the envelope format.
+//! If a poll fails (network error, bad status, invalid descriptor), the last
+//! good copy stays in place.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use tokio::task::AbortHandle;
+
+use super::descriptor::{
+    check_descriptor_size, Descriptor, DescriptorError, DescriptorSource, MAX_ENV_DESCRIPTOR_SIZE,
+};
+use super::signature::SignaturePolicy;
+use super::store::DescriptorStore;
+use crate::shutdown::Shutdown;
+use crate::telemetry::trace_headers;
+
+/// Result of a (conditional) descriptor fetch
+#[derive(Debug)]
+pub enum FetchOutcome {
+    /// A new, validated descriptor was fetched
+    Updated(Box<Descriptor>),
+    /// Server answered 304 Not Modified
+    NotModified,
+}
+
+/// HTTP(S) descriptor fetcher that remembers validators for conditional requests
+pub struct RemoteDescriptor {
+    url: String,
+    client: reqwest::Client,
+    policy: SignaturePolicy,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl RemoteDescriptor {
+    /// Create a fetcher for the given URL
+    ///
+    /// # Arguments
+    /// * `url` - Absolute http(s) URL of the descriptor
+    /// * `connect_timeout_secs` - HTTP connect timeout
+    /// * `request_timeout_secs` - HTTP request timeout
+    /// * `policy` - Signature policy applied to every fetched descriptor
+    pub fn new(
+        url: String,
+        connect_timeout_secs: u64,
+        request_timeout_secs: u64,
+        policy: SignaturePolicy,
+    ) -> Result<Self, DescriptorError> {
+        let parsed = url::Url::parse(&url).map_err(|e| DescriptorError {
+            source: DescriptorSource::Url,
+            message: format!("invalid descriptor URL: {}", e),
+            field_path: None,
+            violations: Vec::new(),
+        })?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(DescriptorError {
+                source: DescriptorSource::Url,
+                message: format!(
+                    "unsupported descriptor URL scheme '{}' (expected http or https)",
+                    parsed.scheme()
+                ),
+                field_path: None,
+                violations: Vec::new(),
+            });
+        }
+
+        // Security: no redirects, so the descriptor cannot be served from an unexpected host
+        let client = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(Duration::from_secs(connect_timeout_secs))
+            .timeout(Duration::from_secs(request_timeout_secs))
+            .build()
+            .map_err(|e| DescriptorError {
+                source: DescriptorSource::Url,
+                message: format!("failed to build HTTP client: {}", e),
+                field_path: None,
+                violations: Vec::new(),
+            })?;
+
+        Ok(Self {
+            url,
+            client,
+            policy,
+            etag: None,
+            last_modified: None,
+        })
+    }
+
+    /// Fetch the descriptor, sending validators from the last successful fetch
+    ///
+    /// Validators are only updated after the descriptor has been parsed and
+    /// validated, so a bad descriptor is fetched again on the next poll.
+    pub async fn fetch(&mut self) -> Result<FetchOutcome, DescriptorError> {
+        let mut request = self.client.get(&self.url).headers(trace_headers());
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .map_err(|e| fetch_error(e.to_string()))?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(FetchOutcome::NotModified);
+        }
+        if !status.is_success() {
+            return Err(fetch_error(format!("unexpected HTTP status {}", status)));
+        }
+
+        // Reject early on Content-Length, then cap the streamed body (the header may be absent)
+        if let Some(len) = response.content_length() {
+            check_descriptor_size(len as usize, DescriptorSource::Url)?;
+        }
+
+        let etag = header_string(&response, ETAG);
+        let last_modified = header_string(&response, LAST_MODIFIED);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| fetch_error(e.to_string()))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() > MAX_ENV_DESCRIPTOR_SIZE {
+                check_descriptor_size(body.len(), DescriptorSource::Url)?;
+            }
+        }
+
+        let json = String::from_utf8(body).map_err(|_| DescriptorError {
+            source: DescriptorSource::Url,
+            message: "descriptor body is not valid UTF-8".to_string(),
+            field_path: None,
+            violations: Vec::new(),
+        })?;
+
+        let payload = self.policy.verify(&json, None, DescriptorSource::Url)?;
+        let descriptor = Descriptor::from_json_with_source(&payload, DescriptorSource::Url)?;
+        descriptor
+            .validate()
+            .map_err(|violations| DescriptorError::validation(DescriptorSource::Url, violations))?;
+
+        self.etag = etag;
+        self.last_modified = last_modified;
+
+        Ok(FetchOutcome::Updated(Box::new(descriptor)))
+    }
+}
+
+fn fetch_error(message: String) -> DescriptorError {
+    DescriptorError {
+        source: DescriptorSource::Url,
+        message: format!("failed to fetch descriptor: {}", message),
+        field_path: None,
+        violations: Vec::new(),
+    }
+}
+
+fn header_string(
+    response: &reqwest::Response,
+    name: reqwest::header::HeaderName,
+) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Poll the remote descriptor and swap it into the store when it changes
+///
+/// Errors are logged and the last good descriptor is kept. The first poll
+/// happens one interval after startup (the initial fetch is done by the caller).
+/// Polling ends when `shutdown` stops background tasks.
+pub fn spawn_descriptor_poller(
+    store: Arc<DescriptorStore>,
+    mut remote: RemoteDescriptor,
+    interval: Duration,
+    shutdown: &Shutdown,
+) -> AbortHandle {
+    shutdown.spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // First tick completes immediately
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            match remote.fetch().await {
+                Ok(FetchOutcome::Updated(descriptor)) => {
+                    let summary = descriptor.summary();
+                    store.replace(*descriptor).await;
+                    tracing::info!(
+                        source = %DescriptorSource::Url,
+                        deployment_id = %summary.deployment_id,
+                        total_services = summary.total_services,
+                        "Remote descriptor changed, reloaded"
+                    );
+                }
+                Ok(FetchOutcome::NotModified) => {
+                    tracing::debug!("Remote descriptor not modified");
+                }
+                Err(error) => {
+                    // Never log the body; the error only carries parse/validation context
+                    tracing::warn!(
+                        error = %error,
+                        "Remote descriptor poll failed, keeping last good descriptor"
+                    );
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        http::{header, HeaderMap, StatusCode as AxumStatus},
+        response::IntoResponse,
+        routing::get,
+        Router,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    const ETAG_VALUE: &str = "\"v1\"";
+
+    fn sample_descriptor_json(deployment_id: &str) -> String {
+        format!(
+            r#"{{
+            "version": "1",
+            "deploymentId": "{}",
+            "environment": "dev",
+            "baseDomain": "localhost",
+            "portal": {{ "publicUrl": "http://localhost" }},
+            "keycloak": {{
+                "publicUrl": "http://keycloak.localhost",
+                "issuerUrl": "http://keycloak.localhost/realms/dev",
+                "realm": "dev"
+            }},
+            "services": []
+        }}"#,
+            deployment_id
+        )
+    }
+
+    /// Local HTTP stand-in for the config server
+    struct StandIn {
+        body: Mutex<String>,
+        requests: AtomicUsize,
+        conditional_requests: AtomicUsize,
+    }
+
+    async fn serve(stand_in: Arc<StandIn>) -> String {
+        let app = Router::new().route(
+            "/descriptor.json",
+            get(move |headers: HeaderMap| {
+                let stand_in = stand_in.clone();
+                async move {
+                    stand_in.requests.fetch_add(1, Ordering::SeqCst);
+                    if headers.get(header::IF_NONE_MATCH).map(|v| v.as_bytes())
+                        == Some(ETAG_VALUE.as_bytes())
+                    {
+                        stand_in.conditional_requests.fetch_add(1, Ordering::SeqCst);
+                        return AxumStatus::NOT_MODIFIED.into_response();
+                    }
+                    let body = stand_in.body.lock().unwrap().clone();
+                    ([(header::ETAG, ETAG_VALUE)], body).into_response()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/descriptor.json", addr)
+    }
+
+    fn stand_in(body: String) -> Arc<StandIn> {
+        Arc::new(StandIn {
+            body: Mutex::new(body),
+            requests: AtomicUsize::new(0),
+            conditional_requests: AtomicUsize::new(0),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_fetch_then_not_modified_with_etag() {
+        let server = stand_in(sample_descriptor_json("remote"));
+        let url = serve(server.clone()).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        match remote.fetch().await.unwrap() {
+            FetchOutcome::Updated(d) => assert_eq!(d.deployment_id, "remote"),
+            FetchOutcome::NotModified => panic!("first fetch must return the descriptor"),
+        }
+
+        // Second fetch sends If-None-Match and gets 304
+        assert!(matches!(
+            remote.fetch().await.unwrap(),
+            FetchOutcome::NotModified
+        ));
+        assert_eq!(server.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(server.conditional_requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_unknown_fields() {
+        let body = sample_descriptor_json("remote").replacen('{', r#"{"extra": 1,"#, 1);
+        let url = serve(stand_in(body)).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        let err = remote.fetch().await.unwrap_err();
+        assert!(err.message.contains("unknown field"));
+        assert!(format!("{}", err).contains("PORTAL_DESCRIPTOR_URL"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_applies_validation() {
+        let body = sample_descriptor_json("Remote");
+        let url = serve(stand_in(body)).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        let err = remote.fetch().await.unwrap_err();
+        assert_eq!(err.violations.len(), 1);
+        assert_eq!(err.violations[0].path, "$.deploymentId");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_applies_size_guard() {
+        let body = format!(
+            "{}{}",
+            sample_descriptor_json("remote"),
+            " ".repeat(MAX_ENV_DESCRIPTOR_SIZE)
+        );
+        let url = serve(stand_in(body)).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        let err = remote.fetch().await.unwrap_err();
+        assert!(err.message.contains("exceeds maximum"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_verifies_signed_envelope() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let policy = SignaturePolicy::with_public_key(key.verifying_key());
+
+        let envelope =
+            crate::services::signature::sign_envelope(&sample_descriptor_json("signed"), &key);
+        let url = serve(stand_in(serde_json::to_string(&envelope).unwrap())).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, policy.clone()).unwrap();
+        match remote.fetch().await.unwrap() {
+            FetchOutcome::Updated(d) => assert_eq!(d.deployment_id, "signed"),
+            FetchOutcome::NotModified => panic!("first fetch must return the descriptor"),
+        }
+
+        // Unsigned descriptors are refused once a key is configured
+        let url = serve(stand_in(sample_descriptor_json("unsigned"))).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, policy).unwrap();
+        let err = remote.fetch().await.unwrap_err();
+        assert!(err.message.contains("not signed"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_network_error() {
+        // Bind then drop to get a port with nothing listening
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut remote = RemoteDescriptor::new(
+            format!("http://{}/descriptor.json", addr),
+            2,
+            5,
+            SignaturePolicy::default(),
+        )
+        .unwrap();
+        let err = remote.fetch().await.unwrap_err();
+        assert!(err.message.contains("failed to fetch descriptor"));
+    }
+
+    #[test]
+    fn test_new_rejects_non_http_scheme() {
+        assert!(RemoteDescriptor::new(
+            "file:///etc/descriptor.json".to_string(),
+            2,
+            5,
+            SignaturePolicy::default()
+        )
+        .is_err());
+        assert!(
+            RemoteDescriptor::new("not a url".to_string(), 2, 5, SignaturePolicy::default())
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poller_swaps_in_changed_descriptor() {
+        let server = stand_in(sample_descriptor_json("first"));
+        let url = serve(server.clone()).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        let FetchOutcome::Updated(initial) = remote.fetch().await.unwrap() else {
+            panic!("first fetch must return the descriptor");
+        };
+        let store = Arc::new(DescriptorStore::new(*initial));
+
+        // Publish a new descriptor; drop validators so the stand-in serves the new body
+        *server.body.lock().unwrap() = sample_descriptor_json("second");
+        remote.etag = None;
+
+        let handle = spawn_descriptor_poller(
+            store.clone(),
+            remote,
+            Duration::from_millis(20),
+            &Shutdown::default(),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.abort();
+
+        assert_eq!(store.current().await.descriptor.deployment_id, "second");
+        // Later polls are conditional again
+        assert!(server.conditional_requests.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_poller_keeps_last_good_descriptor() {
+        let server = stand_in(sample_descriptor_json("first"));
+        let url = serve(server.clone()).await;
+        let mut remote = RemoteDescriptor::new(url, 2, 5, SignaturePolicy::default()).unwrap();
+
+        let FetchOutcome::Updated(initial) = remote.fetch().await.unwrap() else {
+            panic!("first fetch must return the descriptor");
+        };
+        let store = Arc::new(DescriptorStore::new(*initial));
+
+        // Publish a broken descriptor; drop validators to force a full fetch
+        *server.body.lock().unwrap() = "{ not json".to_string();
+        remote.etag = None;
+
+        let handle = spawn_descriptor_poller(
+            store.clone(),
+            remote,
+            Duration::from_millis(20),
+            &Shutdown::default(),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.abort();
+
+        assert!(server.requests.load(Ordering::SeqCst) > 1);
+        assert_eq!(store.current().await.descriptor.deployment_id, "first");
+    }
+}
@@ -0,0 +1,327 @@
+//! Ed25519 descriptor signatures
+//!
+//! The descriptor decides where users are sent and which roles gate each
+//! service, so the portal can verify that it was produced by a trusted signer
+//! (the deploy pipeline) before the descriptor is parsed and validated.
+//!
+//! Two formats are accepted:
+//! - Detached: `<descriptor>.sig` next to the file, containing the base64
+//!   signature of the exact file bytes (file source only)
+//! - Envelope: `{"payload": "<descriptor JSON text>", "signature": "<base64>"}`,
+//!   where the signature covers the UTF-8 bytes of `payload` (all sources)
+//!
+//! Policy:
+//! - When a public key is configured, every descriptor must carry a valid signature
+//! - Production requires a public key (enforced in `Config::from_settings`)
+//! - Without a public key, signatures are ignored (development only)
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::descriptor::{check_descriptor_size, DescriptorError, DescriptorSource};
+
+/// File extension appended to the descriptor path for detached signatures
+pub const DETACHED_SIGNATURE_EXTENSION: &str = "sig";
+
+/// Signature envelope wrapping the descriptor JSON text
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignedEnvelope {
+    /// Descriptor JSON exactly as signed
+    pub payload: String,
+    /// Base64-encoded Ed25519 signature over `payload`
+    pub signature: String,
+}
+
+/// Signature verification policy for descriptors
+#[derive(Debug, Clone, Default)]
+pub struct SignaturePolicy {
+    public_key: Option<VerifyingKey>,
+}
+
+impl SignaturePolicy {
+    /// Policy that enforces signatures made with the given key
+    pub fn with_public_key(public_key: VerifyingKey) -> Self {
+        Self {
+            public_key: Some(public_key),
+        }
+    }
+
+    /// Build the policy from an optional base64-encoded public key
+    pub fn from_base64_key(public_key: Option<&str>) -> Result<Self, String> {
+        match public_key {
+            Some(key) => Ok(Self::with_public_key(decode_public_key(key)?)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Whether descriptors must be signed
+    pub fn requires_signature(&self) -> bool {
+        self.public_key.is_some()
+    }
+
+    /// Verify a raw descriptor and return the descriptor JSON to parse
+    ///
+    /// `raw` is the source content (plain descriptor or envelope), `detached`
+    /// the content of a detached signature file if one exists.
+    pub fn verify(
+        &self,
+        raw: &str,
+        detached: Option<&str>,
+        source: DescriptorSource,
+    ) -> Result<String, DescriptorError> {
+        check_descriptor_size(raw.len(), source)?;
+
+        let (payload, signature) = match serde_json::from_str::<SignedEnvelope>(raw) {
+            Ok(envelope) => (envelope.payload, Some(envelope.signature)),
+            Err(_) => (raw.to_string(), detached.map(|s| s.to_string())),
+        };
+
+        let Some(public_key) = self.public_key.as_ref() else {
+            if signature.is_some() {
+                tracing::warn!(
+                    source = %source,
+                    "Descriptor is signed but no public key is configured; signature not verified"
+                );
+            }
+            return Ok(payload);
+        };
+
+        let Some(signature) = signature else {
+            return Err(signature_error(
+                source,
+                "descriptor is not signed (a public key is configured, so signatures are required)",
+            ));
+        };
+
+        let signature = decode_signature(&signature).map_err(|e| signature_error(source, &e))?;
+        public_key
+            .verify_strict(payload.as_bytes(), &signature)
+            .map_err(|_| signature_error(source, "descriptor signature verification failed"))?;
+
+        tracing::info!(source = %source, "Descriptor signature verified");
+        Ok(payload)
+    }
+
+    /// Read a descriptor file and its optional detached signature, then verify it
+    pub fn verify_file(&self, path: &str) -> Result<String, DescriptorError> {
+        let raw = std::fs::read_to_string(path).map_err(|e| DescriptorError {
+            source: DescriptorSource::FilePath,
+            message: format!("failed to read file '{}': {}", path, e),
+            field_path: None,
+            violations: Vec::new(),
+        })?;
+
+        let signature_path = detached_signature_path(path);
+        let detached = match std::fs::read_to_string(&signature_path) {
+            Ok(sig) => Some(sig),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(DescriptorError {
+                    source: DescriptorSource::FilePath,
+                    message: format!("failed to read signature '{}': {}", signature_path, e),
+                    field_path: None,
+                    violations: Vec::new(),
+                })
+            }
+        };
+
+        self.verify(&raw, detached.as_deref(), DescriptorSource::FilePath)
+    }
+}
+
+/// Path of the detached signature for a descriptor file
+pub fn detached_signature_path(descriptor_path: &str) -> String {
+    format!("{}.{}", descriptor_path, DETACHED_SIGNATURE_EXTENSION)
+}
+
+/// Sign descriptor JSON, returning the base64-encoded signature
+pub fn sign_payload(payload: &str, signing_key: &SigningKey) -> String {
+    BASE64.encode(signing_key.sign(payload.as_bytes()).to_bytes())
+}
+
+/// Wrap descriptor JSON in a signed envelope
+pub fn sign_envelope(payload: &str, signing_key: &SigningKey) -> SignedEnvelope {
+    SignedEnvelope {
+        payload: payload.to_string(),
+        signature: sign_payload(payload, signing_key),
+    }
+}
+
+/// Decode a base64-encoded 32-byte Ed25519 public key
+pub fn decode_public_key(key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = decode_fixed(key, "public key")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("invalid Ed25519 public key: {}", e))
+}
+
+/// Decode a base64-encoded 32-byte Ed25519 signing key (seed)
+pub fn decode_signing_key(key: &str) -> Result<SigningKey, String> {
+    let bytes: [u8; 32] = decode_fixed(key, "signing key")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Encode a key or signature as base64 (the format used by all key files)
+pub fn encode_base64(bytes: &[u8]) -> String {
+    BASE64.encode(bytes)
+}
+
+fn decode_signature(signature: &str) -> Result<Signature, String> {
+    let bytes: [u8; 64] = decode_fixed(signature, "signature")?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+fn decode_fixed<const N: usize>(value: &str, what: &str) -> Result<[u8; N], String> {
+    let bytes = BASE64
+        .decode(value.trim())
+        .map_err(|e| format!("{} is not valid base64: {}", what, e))?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| format!("{} must be {} bytes, got {}", what, N, len))
+}
+
+fn signature_error(source: DescriptorSource, message: &str) -> DescriptorError {
+    DescriptorError {
+        source,
+        message: message.to_string(),
+        field_path: None,
+        violations: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTOR: &str = r#"{"version":"1","deploymentId":"local"}"#;
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn policy_for(key: &SigningKey) -> SignaturePolicy {
+        SignaturePolicy::with_public_key(key.verifying_key())
+    }
+
+    #[test]
+    fn test_detached_signature_verifies() {
+        let key = signing_key(1);
+        let sig = sign_payload(DESCRIPTOR, &key);
+        let payload = policy_for(&key)
+            .verify(DESCRIPTOR, Some(&sig), DescriptorSource::FilePath)
+            .unwrap();
+        assert_eq!(payload, DESCRIPTOR);
+    }
+
+    #[test]
+    fn test_detached_signature_tolerates_trailing_newline() {
+        let key = signing_key(1);
+        let sig = format!("{}\n", sign_payload(DESCRIPTOR, &key));
+        assert!(policy_for(&key)
+            .verify(DESCRIPTOR, Some(&sig), DescriptorSource::FilePath)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_envelope_verifies_and_unwraps() {
+        let key = signing_key(2);
+        let envelope = serde_json::to_string(&sign_envelope(DESCRIPTOR, &key)).unwrap();
+        let payload = policy_for(&key)
+            .verify(&envelope, None, DescriptorSource::EnvJson)
+            .unwrap();
+        assert_eq!(payload, DESCRIPTOR);
+    }
+
+    #[test]
+    fn test_tampered_payload_rejected() {
+        let key = signing_key(1);
+        let sig = sign_payload(DESCRIPTOR, &key);
+        let tampered = DESCRIPTOR.replace("local", "evil");
+        let err = policy_for(&key)
+            .verify(&tampered, Some(&sig), DescriptorSource::FilePath)
+            .unwrap_err();
+        assert!(err.message.contains("verification failed"));
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let sig = sign_payload(DESCRIPTOR, &signing_key(1));
+        let err = policy_for(&signing_key(2))
+            .verify(DESCRIPTOR, Some(&sig), DescriptorSource::FilePath)
+            .unwrap_err();
+        assert!(err.message.contains("verification failed"));
+    }
+
+    #[test]
+    fn test_unsigned_rejected_when_key_configured() {
+        let err = policy_for(&signing_key(1))
+            .verify(DESCRIPTOR, None, DescriptorSource::FilePath)
+            .unwrap_err();
+        assert!(err.message.contains("not signed"));
+    }
+
+    #[test]
+    fn test_malformed_signature_rejected() {
+        let err = policy_for(&signing_key(1))
+            .verify(DESCRIPTOR, Some("not-base64!"), DescriptorSource::FilePath)
+            .unwrap_err();
+        assert!(err.message.contains("not valid base64"));
+    }
+
+    #[test]
+    fn test_no_key_accepts_unsigned_and_unwraps_envelope() {
+        let policy = SignaturePolicy::default();
+        assert!(!policy.requires_signature());
+        assert_eq!(
+            policy
+                .verify(DESCRIPTOR, None, DescriptorSource::FilePath)
+                .unwrap(),
+            DESCRIPTOR
+        );
+
+        let envelope = serde_json::to_string(&sign_envelope(DESCRIPTOR, &signing_key(1))).unwrap();
+        assert_eq!(
+            policy
+                .verify(&envelope, None, DescriptorSource::EnvJson)
+                .unwrap(),
+            DESCRIPTOR
+        );
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let key = signing_key(3);
+        let encoded_public = encode_base64(key.verifying_key().as_bytes());
+        let encoded_secret = encode_base64(key.as_bytes());
+
+        assert_eq!(
+            decode_public_key(&encoded_public).unwrap(),
+            key.verifying_key()
+        );
+        assert_eq!(
+            decode_signing_key(&encoded_secret).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+        assert!(decode_public_key("AAAA").unwrap_err().contains("32 bytes"));
+    }
+
+    #[test]
+    fn test_verify_file_with_detached_signature() {
+        let key = signing_key(4);
+        let path = std::env::temp_dir().join("test_signed_descriptor.json");
+        let path = path.to_string_lossy().to_string();
+        std::fs::write(&path, DESCRIPTOR).unwrap();
+        std::fs::write(
+            detached_signature_path(&path),
+            sign_payload(DESCRIPTOR, &key),
+        )
+        .unwrap();
+
+        let result = policy_for(&key).verify_file(&path);
+
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(detached_signature_path(&path)).ok();
+        assert_eq!(result.unwrap(), DESCRIPTOR);
+    }
+}
@@ -0,0 +1,59 @@
+//! Swappable descriptor snapshot shared by all handlers
+//!
+//! Static sources (env var, file) load the descriptor once at startup. Remote
+//! sources are polled, so handlers read the descriptor through this store and
+//! always get a consistent snapshot (descriptor + derived service cards).
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::descriptor::Descriptor;
+use super::models::ServiceCard;
+use super::services_from_descriptor;
+
+/// A validated descriptor and the service cards derived from it
+#[derive(Debug)]
+pub struct DescriptorSnapshot {
+    /// Full descriptor (logout fan-out, deployment footer)
+    pub descriptor: Descriptor,
+    /// Service cards in display order
+    pub services: Vec<ServiceCard>,
+}
+
+impl DescriptorSnapshot {
+    pub fn new(descriptor: Descriptor) -> Self {
+        let services = services_from_descriptor(&descriptor);
+        Self {
+            descriptor,
+            services,
+        }
+    }
+}
+
+/// Holds the current descriptor snapshot
+///
+/// Readers clone the inner `Arc`, so a request keeps using the same snapshot
+/// even if the descriptor is replaced mid-request.
+pub struct DescriptorStore {
+    current: RwLock<Arc<DescriptorSnapshot>>,
+}
+
+impl DescriptorStore {
+    /// Create a store from an already validated descriptor
+    pub fn new(descriptor: Descriptor) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(DescriptorSnapshot::new(descriptor))),
+        }
+    }
+
+    /// Get the current snapshot
+    pub async fn current(&self) -> Arc<DescriptorSnapshot> {
+        self.current.read().await.clone()
+    }
+
+    /// Replace the current snapshot with a new validated descriptor
+    pub async fn replace(&self, descriptor: Descriptor) {
+        let snapshot = Arc::new(DescriptorSnapshot::new(descriptor));
+        *self.current.write().await = snapshot;
+    }
+}
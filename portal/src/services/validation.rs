@@ -0,0 +1,327 @@
+//! Semantic validation of the portal descriptor
+//!
+//! Enforces the README "Descriptor Contract Rules" on the consumer side, so a
+//! hand-edited descriptor (or one from another producer) is held to the same
+//! rules Pulumi checks with Ajv. Every rule is checked and all violations are
+//! reported at once, each with a JSON path (e.g. `$.services[2].url`).
+//!
+//! Per-field rules (`pattern`, `format`, `minLength`, `minItems`) are generated
+//! from the schema into `descriptor_gen.rs`. This module adds the rules that
+//! span several fields:
+//! - `version` is a supported version
+//! - `protected` matches `authType` (`protected = authType != "none"`)
+//! - `requiredRealmRoles` is required for protected services and forbidden
+//!   for `authType: none`
+//! - service IDs are unique
+//! - group IDs are unique, and when `groups` is declared every service
+//!   `group` references one of them (v2)
+
+use std::collections::HashMap;
+
+use super::descriptor::DescriptorViolation;
+use super::descriptor_gen::{AuthType, Descriptor};
+
+/// Check every contract rule and collect all violations
+pub(crate) fn validate_descriptor(
+    descriptor: &Descriptor,
+    supported_versions: &[&str],
+) -> Vec<DescriptorViolation> {
+    let mut violations = Vec::new();
+
+    if !supported_versions.contains(&descriptor.version.as_str()) {
+        violations.push(violation(
+            "$.version",
+            format!(
+                "unsupported descriptor version '{}' (supported: {})",
+                descriptor.version,
+                supported_versions.join(", ")
+            ),
+        ));
+    }
+
+    descriptor.schema_violations("$", &mut violations);
+
+    let mut group_ids: HashMap<&str, usize> = HashMap::new();
+    for (i, group) in descriptor.groups.iter().flatten().enumerate() {
+        if let Some(first) = group_ids.get(group.id.as_str()) {
+            violations.push(violation(
+                format!("$.groups[{}].id", i),
+                format!(
+                    "duplicate group id '{}' (first defined at $.groups[{}])",
+                    group.id, first
+                ),
+            ));
+        } else {
+            group_ids.insert(&group.id, i);
+        }
+    }
+
+    let mut first_seen: HashMap<&str, usize> = HashMap::new();
+    for (i, service) in descriptor.services.iter().enumerate() {
+        let path = format!("$.services[{}]", i);
+
+        if let Some(first) = first_seen.get(service.id.as_str()) {
+            violations.push(violation(
+                format!("{}.id", path),
+                format!(
+                    "duplicate service id '{}' (first defined at $.services[{}])",
+                    service.id, first
+                ),
+            ));
+        } else {
+            first_seen.insert(&service.id, i);
+        }
+
+        if let (Some(group), Some(_)) = (&service.group, &descriptor.groups) {
+            if !group_ids.contains_key(group.as_str()) {
+                violations.push(violation(
+                    format!("{}.group", path),
+                    format!("unknown group '{}' (not declared in $.groups)", group),
+                ));
+            }
+        }
+
+        let expects_protected = service.auth_type != AuthType::None;
+        if service.protected != expects_protected {
+            violations.push(violation(
+                format!("{}.protected", path),
+                format!(
+                    "must be {} for authType '{}'",
+                    expects_protected,
+                    auth_type_name(&service.auth_type)
+                ),
+            ));
+        }
+
+        let roles_path = format!("{}.requiredRealmRoles", path);
+        match (&service.auth_type, &service.required_realm_roles) {
+            (AuthType::None, Some(_)) => {
+                violations.push(violation(roles_path, "is not allowed for authType 'none'"));
+            }
+            (AuthType::None, None) | (_, Some(_)) => {}
+            (auth_type, None) => violations.push(violation(
+                roles_path,
+                format!("is required for authType '{}'", auth_type_name(auth_type)),
+            )),
+        }
+    }
+
+    violations
+}
+
+fn violation(path: impl Into<String>, message: impl Into<String>) -> DescriptorViolation {
+    DescriptorViolation {
+        path: path.into(),
+        message: message.into(),
+    }
+}
+
+fn auth_type_name(auth_type: &AuthType) -> &'static str {
+    match auth_type {
+        AuthType::None => "none",
+        AuthType::Oauth2Proxy => "oauth2-proxy",
+        AuthType::Portal => "portal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::descriptor::DescriptorSource;
+    use crate::services::descriptor_gen::{is_http_url, is_slug};
+
+    use crate::services::descriptor::{Group, CURRENT_VERSION};
+
+    const SUPPORTED: &[&str] = &[CURRENT_VERSION];
+
+    fn valid_descriptor() -> Descriptor {
+        Descriptor::from_json_with_source(
+            r#"{
+            "version": "1",
+            "deploymentId": "local",
+            "environment": "dev",
+            "baseDomain": "localhost",
+            "deployment": {
+                "commitSha": "0123456789abcdef0123456789abcdef01234567",
+                "commitAt": "2026-02-02T15:30:00Z"
+            },
+            "portal": { "publicUrl": "http://portal.localhost" },
+            "keycloak": {
+                "publicUrl": "http://keycloak.localhost",
+                "issuerUrl": "http://keycloak.localhost/realms/dev",
+                "realm": "dev"
+            },
+            "services": [
+                {
+                    "id": "demo",
+                    "name": "Demo App",
+                    "url": "http://demo.localhost",
+                    "protected": true,
+                    "authType": "oauth2-proxy",
+                    "requiredRealmRoles": ["dev"]
+                },
+                {
+                    "id": "docs",
+                    "name": "Docs",
+                    "url": "https://docs.localhost",
+                    "protected": false,
+                    "authType": "none"
+                }
+            ]
+        }"#,
+            DescriptorSource::EnvJson,
+        )
+        .unwrap()
+    }
+
+    fn paths(violations: &[DescriptorViolation]) -> Vec<&str> {
+        violations.iter().map(|v| v.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_descriptor_has_no_violations() {
+        assert!(validate_descriptor(&valid_descriptor(), SUPPORTED).is_empty());
+    }
+
+    #[test]
+    fn test_reports_all_violations_at_once() {
+        let mut d = valid_descriptor();
+        d.version = "9".to_string();
+        d.deployment_id = "Prod".to_string();
+        d.portal.public_url = "portal.localhost".to_string();
+        d.services[0].url = "/demo".to_string();
+        d.services[1].id = "demo".to_string();
+
+        let violations = validate_descriptor(&d, SUPPORTED);
+        assert_eq!(
+            paths(&violations),
+            vec![
+                "$.version",
+                "$.deploymentId",
+                "$.portal.publicUrl",
+                "$.services[0].url",
+                "$.services[1].id",
+            ]
+        );
+        assert!(violations[4]
+            .message
+            .contains("duplicate service id 'demo'"));
+        assert!(violations[4].message.contains("$.services[0]"));
+    }
+
+    #[test]
+    fn test_protected_must_match_auth_type() {
+        let mut d = valid_descriptor();
+        d.services[0].protected = false;
+        d.services[1].protected = true;
+
+        let violations = validate_descriptor(&d, SUPPORTED);
+        assert_eq!(
+            paths(&violations),
+            vec!["$.services[0].protected", "$.services[1].protected"]
+        );
+    }
+
+    #[test]
+    fn test_required_realm_roles_rules() {
+        let mut d = valid_descriptor();
+        d.services[0].required_realm_roles = None;
+        d.services[1].required_realm_roles = Some(vec!["dev".to_string()]);
+
+        let violations = validate_descriptor(&d, SUPPORTED);
+        assert_eq!(
+            paths(&violations),
+            vec![
+                "$.services[0].requiredRealmRoles",
+                "$.services[1].requiredRealmRoles"
+            ]
+        );
+        assert!(violations[0].message.contains("required"));
+        assert!(violations[1].message.contains("not allowed"));
+
+        let mut d = valid_descriptor();
+        d.services[0].required_realm_roles = Some(vec![]);
+        assert_eq!(
+            paths(&validate_descriptor(&d, SUPPORTED)),
+            vec!["$.services[0].requiredRealmRoles"]
+        );
+
+        let mut d = valid_descriptor();
+        d.services[0].required_realm_roles = Some(vec!["dev".to_string(), String::new()]);
+        assert_eq!(
+            paths(&validate_descriptor(&d, SUPPORTED)),
+            vec!["$.services[0].requiredRealmRoles[1]"]
+        );
+    }
+
+    #[test]
+    fn test_deployment_metadata_formats() {
+        let mut d = valid_descriptor();
+        let deployment = d.deployment.as_mut().unwrap();
+        deployment.commit_sha = Some("ABC123".to_string());
+        deployment.deployed_at = Some("2026-02-02 15:30".to_string());
+
+        assert_eq!(
+            paths(&validate_descriptor(&d, SUPPORTED)),
+            vec!["$.deployment.commitSha", "$.deployment.deployedAt"]
+        );
+    }
+
+    #[test]
+    fn test_empty_optional_strings_rejected() {
+        let mut d = valid_descriptor();
+        d.services[1].icon = Some(String::new());
+        d.keycloak.realm = String::new();
+
+        assert_eq!(
+            paths(&validate_descriptor(&d, SUPPORTED)),
+            vec!["$.keycloak.realm", "$.services[1].icon"]
+        );
+    }
+
+    #[test]
+    fn test_group_references() {
+        let group = |id: &str| Group {
+            id: id.to_string(),
+            name: "Apps".to_string(),
+            description: None,
+        };
+
+        // Without declared groups, `group` is free-form
+        let mut d = valid_descriptor();
+        d.services[0].group = Some("anything".to_string());
+        assert!(validate_descriptor(&d, SUPPORTED).is_empty());
+
+        d.groups = Some(vec![group("apps"), group("apps")]);
+        let violations = validate_descriptor(&d, SUPPORTED);
+        assert_eq!(
+            paths(&violations),
+            vec!["$.groups[1].id", "$.services[0].group"]
+        );
+        assert!(violations[1].message.contains("unknown group 'anything'"));
+    }
+
+    #[test]
+    fn test_is_slug() {
+        assert!(is_slug("a"));
+        assert!(is_slug("prod"));
+        assert!(is_slug("my-service-2"));
+        assert!(!is_slug(""));
+        assert!(!is_slug("-prod"));
+        assert!(!is_slug("prod-"));
+        assert!(!is_slug("2prod"));
+        assert!(!is_slug("Prod"));
+        assert!(!is_slug("my_service"));
+    }
+
+    #[test]
+    fn test_is_http_url() {
+        assert!(is_http_url("http://demo.localhost"));
+        assert!(is_http_url("https://demo.example.com/path?x=1"));
+        assert!(!is_http_url("demo.localhost"));
+        assert!(!is_http_url("/relative"));
+        assert!(!is_http_url("ftp://demo.localhost"));
+        assert!(!is_http_url("http://"));
+        assert!(!is_http_url("http://demo .localhost"));
+    }
+}
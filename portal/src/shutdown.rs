@@ -0,0 +1,314 @@
+//! Graceful shutdown on SIGTERM / SIGINT
+//!
+//! On a signal the portal:
+//! 1. reports not ready (`/readyz` 503), so Traefik and Kubernetes stop
+//!    routing new requests to it;
+//! 2. keeps serving for PORTAL_SHUTDOWN_DRAIN_SECS while that takes effect;
+//! 3. stops accepting connections and lets in-flight requests (e.g. a
+//!    callback in the middle of its token exchange) finish, for at most
+//!    PORTAL_SHUTDOWN_TIMEOUT_SECS;
+//! 4. stops the background tasks started with [`Shutdown::spawn`]
+//!    (descriptor pollers, secret refresh, device token polls).
+
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::serve::{Listener, ListenerExt};
+use axum::Router;
+use tokio::sync::{oneshot, watch};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Shutdown state shared by `main`, `/readyz` and background tasks
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    draining: AtomicBool,
+    stop: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                draining: AtomicBool::new(false),
+                stop: watch::channel(false).0,
+                tasks: Mutex::default(),
+            }),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Whether a shutdown has started (`/readyz` then returns 503)
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn start_draining(&self) {
+        self.inner.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Run `task` until it completes or background tasks are stopped
+    ///
+    /// The task is dropped at its next `.await` once stopped, so it must not
+    /// leave shared state half-updated across an await.
+    pub fn spawn<F>(&self, task: F) -> AbortHandle
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut stop = self.inner.stop.subscribe();
+        let stopped = async move {
+            // Every handle dropped without stopping: run until completion
+            if stop.wait_for(|stopped| *stopped).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        let handle = tokio::spawn(async move {
+            tokio::select! {
+                _ = task => {}
+                _ = stopped => {}
+            }
+        });
+        let abort = handle.abort_handle();
+        let mut tasks = self.inner.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle);
+        abort
+    }
+
+    /// Stop every task started with [`Shutdown::spawn`] and wait for them (at most `timeout`)
+    pub async fn stop_background_tasks(&self, timeout: Duration) {
+        self.inner.stop.send_replace(true);
+        let tasks = std::mem::take(&mut *self.inner.tasks.lock().unwrap());
+        let count = tasks.len();
+        let joined = tokio::time::timeout(timeout, async {
+            for task in tasks {
+                // Tasks only end by completing or being stopped
+                let _ = task.await;
+            }
+        })
+        .await;
+        match joined {
+            Ok(()) => tracing::info!(tasks = count, "Background tasks stopped"),
+            Err(_) => tracing::warn!(tasks = count, "Background tasks did not stop in time"),
+        }
+    }
+}
+
+/// Serve `app` until `signal` resolves, then drain and stop accepting
+///
+/// `listener` is a plain TCP listener or a [`crate::tls::TlsListener`].
+///
+/// Returns once in-flight requests have finished or `timeout` has passed
+/// (remaining connections are then closed). Background tasks are left to
+/// the caller.
+pub async fn serve(
+    listener: impl Listener<Addr = SocketAddr>,
+    app: Router,
+    shutdown: &Shutdown,
+    signal: impl Future<Output = &'static str>,
+    drain: Duration,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let (stop_accepting, accepting_stopped) = oneshot::channel::<()>();
+    // Peer addresses feed the rate limiter and the audit log's client_ip
+    // (axum provides them for any listener through `tap_io`)
+    let server = axum::serve(
+        listener.tap_io(|_| {}),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async {
+        let _ = accepting_stopped.await;
+    });
+    let mut server = tokio::spawn(server.into_future());
+
+    let signal = tokio::select! {
+        result = &mut server => return Ok(result??),
+        signal = signal => signal,
+    };
+
+    tracing::info!(
+        event = "shutdown_started",
+        signal,
+        drain_secs = drain.as_secs_f64(),
+        "Shutting down: not ready, draining"
+    );
+    shutdown.start_draining();
+    tokio::time::sleep(drain).await;
+
+    tracing::info!("Drain period over, no longer accepting connections");
+    let _ = stop_accepting.send(());
+    match tokio::time::timeout(timeout, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            tracing::warn!(
+                timeout_secs = timeout.as_secs_f64(),
+                "In-flight requests did not finish in time, closing their connections"
+            );
+            server.abort();
+        }
+    }
+    Ok(())
+}
+
+/// Wait for SIGTERM or SIGINT; returns the signal's name
+pub async fn signal() -> &'static str {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => "SIGINT",
+        _ = terminate => "SIGTERM",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_stop_background_tasks() {
+        let shutdown = Shutdown::default();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        shutdown.spawn(async move {
+            loop {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+        // A task that completes on its own is not waited for again
+        shutdown.spawn(async {});
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(ticks.load(Ordering::SeqCst) > 0);
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            shutdown.stop_background_tasks(Duration::from_millis(500)),
+        )
+        .await
+        .unwrap();
+        let stopped_at = ticks.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(ticks.load(Ordering::SeqCst), stopped_at);
+
+        // Tasks spawned after the stop end immediately
+        let late = shutdown.spawn(std::future::pending());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(late.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_serve_drains_then_finishes_in_flight() {
+        use axum::http::StatusCode;
+        use axum::routing::get;
+
+        let shutdown = Shutdown::default();
+        let draining = shutdown.clone();
+        let app = Router::new()
+            .route(
+                "/readyz",
+                get(move || async move {
+                    if draining.is_draining() {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::OK
+                    }
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "done"
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (send_signal, signal) = oneshot::channel::<()>();
+        let server_shutdown = shutdown.clone();
+        let server = tokio::spawn(async move {
+            serve(
+                listener,
+                app,
+                &server_shutdown,
+                async {
+                    signal.await.unwrap();
+                    "SIGTERM"
+                },
+                Duration::from_millis(200),
+                Duration::from_secs(2),
+            )
+            .await
+        });
+        let client = reqwest::Client::new();
+        assert_eq!(
+            client
+                .get(format!("{}/readyz", url))
+                .send()
+                .await
+                .unwrap()
+                .status(),
+            200
+        );
+
+        // A request in flight when the signal arrives
+        let slow = tokio::spawn(client.get(format!("{}/slow", url)).send());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        send_signal.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Draining: still serving, but not ready
+        let response = reqwest::Client::new()
+            .get(format!("{}/readyz", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+
+        let response = slow.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // No longer accepting connections
+        assert!(reqwest::Client::new()
+            .get(format!("{}/readyz", url))
+            .send()
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! OpenTelemetry trace export (OTEL_EXPORTER_OTLP_ENDPOINT)
+//!
+//! When an OTLP endpoint is configured, `tracing` spans are exported over
+//! OTLP/HTTP (protobuf) with a batch processor, and W3C trace context is
+//! propagated:
+//! - inbound: [`trace_request`] continues the `traceparent` sent by Traefik
+//!   and wraps each request in an `http_request` span;
+//! - outbound: reqwest calls add [`trace_headers`], OAuth2 requests go
+//!   through [`TracedHttpClient`].
+//!
+//! Without an endpoint no spans leave the process and no `traceparent` is
+//! sent (there is no trace context to propagate). The request ID is
+//! forwarded either way.
+
+use anyhow::{Context, Result};
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use oauth2::{AsyncHttpClient, HttpRequest};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Instrument;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::Settings;
+use crate::request_id::{self, RequestId, REQUEST_ID_HEADER};
+
+/// Log line format (LOG_FORMAT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "" | "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => anyhow::bail!("LOG_FORMAT must be 'text' or 'json', got '{}'", other),
+        }
+    }
+}
+
+/// Logging and trace export settings
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub log_format: LogFormat,
+    /// OTLP/HTTP base URL, e.g. `http://otel-collector:4318` (spans go to `/v1/traces`)
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` resource attribute
+    pub service_name: String,
+}
+
+impl TelemetryConfig {
+    /// Read LOG_FORMAT, OTEL_EXPORTER_OTLP_ENDPOINT and OTEL_SERVICE_NAME
+    ///
+    /// Part of [`crate::config::Config`], loaded before the tracing
+    /// subscriber is installed.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let log_format = settings.string_or("LOG_FORMAT", "text");
+        Self {
+            log_format: settings
+                .check(LogFormat::parse(&log_format))
+                .unwrap_or_default(),
+            otlp_endpoint: settings.string("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            service_name: settings.string_or("OTEL_SERVICE_NAME", "portal"),
+        }
+    }
+}
+
+/// Build the tracer provider exporting to the configured endpoint (None if disabled)
+///
+/// Also installs the W3C trace context propagator. Call
+/// `SdkTracerProvider::shutdown` before exiting to flush pending spans.
+pub fn init_tracer_provider(config: &TelemetryConfig) -> Result<Option<SdkTracerProvider>> {
+    let Some(endpoint) = config.otlp_endpoint.as_deref() else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .context("Failed to build OTLP span exporter")?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+                .build(),
+        )
+        .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(Some(provider))
+}
+
+/// `tracing` layer feeding spans to the tracer provider
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("portal"))
+}
+
+/// Middleware: wrap the request in an `http_request` span continuing the inbound trace
+pub async fn trace_request(request: Request, next: Next) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+
+    // Route templates keep span names low-cardinality (`/tokens/{id}/revoke`)
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "http_request",
+        otel.name = %format!("{} {}", request.method(), route),
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        http.route = %route,
+        http.response.status_code = tracing::field::Empty,
+        request_id = %request_id,
+    );
+    // Fails only when no OpenTelemetry layer is installed (export disabled)
+    let _ = span.set_parent(parent);
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
+
+/// Add the current span's trace context and the request ID to outbound request headers
+pub fn inject_trace_context(headers: &mut HeaderMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+    if let Some(id) = request_id::current() {
+        if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+            headers.insert(REQUEST_ID_HEADER, value);
+        }
+    }
+}
+
+/// Trace context headers (`traceparent`, `X-Request-Id`) for an outbound reqwest call
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    inject_trace_context(&mut headers);
+    headers
+}
+
+/// reqwest client for `oauth2` requests that carries the current trace context
+pub struct TracedHttpClient<'a>(pub &'a reqwest::Client);
+
+impl<'c> AsyncHttpClient<'c> for TracedHttpClient<'_> {
+    type Error = <reqwest::Client as AsyncHttpClient<'c>>::Error;
+    type Future = <reqwest::Client as AsyncHttpClient<'c>>::Future;
+
+    fn call(&'c self, mut request: HttpRequest) -> Self::Future {
+        inject_trace_context(request.headers_mut());
+        self.0.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::header::CONTENT_TYPE;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// OTLP collector stand-in: forwards each `/v1/traces` request (content type, body)
+    async fn start_collector() -> (String, mpsc::UnboundedReceiver<(String, Bytes)>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/v1/traces",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let content_type = headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                let _ = sender.send((content_type, body));
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", address), receiver)
+    }
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(LogFormat::parse("").unwrap(), LogFormat::Text);
+        assert_eq!(LogFormat::parse("JSON").unwrap(), LogFormat::Json);
+        assert!(LogFormat::parse("yaml").is_err());
+    }
+
+    #[test]
+    fn test_disabled_without_endpoint() {
+        let config = TelemetryConfig {
+            log_format: LogFormat::Text,
+            otlp_endpoint: None,
+            service_name: "portal".to_string(),
+        };
+        assert!(init_tracer_provider(&config).unwrap().is_none());
+        // No layer installed: nothing to propagate
+        assert!(trace_headers().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_exported_to_collector() {
+        let (endpoint, mut received) = start_collector().await;
+        let config = TelemetryConfig {
+            log_format: LogFormat::Text,
+            otlp_endpoint: Some(format!("{}/", endpoint)),
+            service_name: "portal-test".to_string(),
+        };
+        let provider = init_tracer_provider(&config).unwrap().unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let traceparent = tracing::subscriber::with_default(subscriber, || {
+            let _guard = tracing::info_span!("logout_flow").entered();
+            trace_headers()
+                .get("traceparent")
+                .map(|v| v.to_str().unwrap().to_string())
+        });
+        let traceparent = traceparent.expect("outbound calls carry traceparent");
+        assert!(traceparent.starts_with("00-"));
+
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (content_type, body) = received.recv().await.unwrap();
+        assert_eq!(content_type, "application/x-protobuf");
+        let contains = |needle: &[u8]| body.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"logout_flow"));
+        assert!(contains(b"portal-test"));
+    }
+
+    #[tokio::test]
+    async fn test_inbound_traceparent_continued() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        // Echo the traceparent an outbound call from the handler would send
+        let app = Router::new()
+            .route(
+                "/probe",
+                get(|| async {
+                    trace_headers()
+                        .get("traceparent")
+                        .map(|v| v.to_str().unwrap().to_string())
+                        .unwrap_or_default()
+                }),
+            )
+            .layer(axum::middleware::from_fn(trace_request));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let outbound = reqwest::Client::new()
+            .get(format!("http://{}/probe", address))
+            .header(
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-01", trace_id),
+            )
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        assert!(outbound.starts_with(&format!("00-{}-", trace_id)));
+        // A new span id: the request span, not Traefik's
+        assert!(!outbound.contains("00f067aa0ba902b7"));
+    }
+}
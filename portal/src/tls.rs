@@ -0,0 +1,452 @@
+//! Native HTTPS for standalone deployments without Traefik
+//!
+//! When PORTAL_TLS_CERT_FILE and PORTAL_TLS_KEY_FILE are set, the portal
+//! serves HTTPS itself with rustls:
+//! - the certificate chain and private key are PEM files, re-read every
+//!   PORTAL_TLS_RELOAD_SECS when either file changed (a renewed certificate
+//!   is used for new connections; an invalid pair keeps the previous one);
+//! - HTTP/2 is negotiated via ALPN, with HTTP/1.1 as the fallback;
+//! - PORTAL_TLS_REDIRECT_PORT optionally binds a plain HTTP listener that
+//!   redirects every request to HTTPS.
+//!
+//! Cookies get the `Secure` attribute whenever the portal serves HTTPS (see
+//! [`crate::config::Config::is_https`]).
+
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use axum::http::header::HOST;
+use axum::http::uri::Authority;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::serve::Listener;
+use axum::Router;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Settings;
+use crate::shutdown::Shutdown;
+
+/// Connections that have not completed the handshake by then are closed
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTPS listener settings
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf certificate first
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+    /// Plain HTTP port redirecting to HTTPS (None = no redirect listener)
+    pub redirect_port: Option<u16>,
+    /// How often the files are checked for changes (in seconds; 0 = never)
+    pub reload_secs: u64,
+}
+
+impl TlsConfig {
+    /// None (plain HTTP) unless both PORTAL_TLS_CERT_FILE and PORTAL_TLS_KEY_FILE are set
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        let cert_path = settings.string("PORTAL_TLS_CERT_FILE");
+        let key_path = settings.string("PORTAL_TLS_KEY_FILE");
+        let redirect_port = settings.parse::<u16>("PORTAL_TLS_REDIRECT_PORT");
+        let reload_secs = settings.parse_or("PORTAL_TLS_RELOAD_SECS", 30u64);
+        match (cert_path, key_path) {
+            (Some(cert_path), Some(key_path)) => Some(Self {
+                cert_path: cert_path.into(),
+                key_path: key_path.into(),
+                redirect_port,
+                reload_secs,
+            }),
+            (None, None) => {
+                if redirect_port.is_some() {
+                    settings.error(
+                        "PORTAL_TLS_REDIRECT_PORT requires PORTAL_TLS_CERT_FILE and PORTAL_TLS_KEY_FILE",
+                    );
+                }
+                None
+            }
+            _ => {
+                settings.error("PORTAL_TLS_CERT_FILE and PORTAL_TLS_KEY_FILE must be set together");
+                None
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Certificates
+// =============================================================================
+
+/// aws-lc-rs, the crypto backend the crate already uses for JWTs and secrets
+fn crypto_provider() -> CryptoProvider {
+    rustls::crypto::aws_lc_rs::default_provider()
+}
+
+/// The served certificate and key, reloaded when their files change
+#[derive(Debug)]
+pub struct Certificates {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the files the current pair was read from
+    modified: Mutex<Option<(SystemTime, SystemTime)>>,
+}
+
+impl Certificates {
+    /// Read the certificate chain and key (fails if they do not match)
+    pub fn load(config: &TlsConfig) -> Result<Arc<Self>> {
+        let modified = modified(&config.cert_path, &config.key_path);
+        let current = read_certified_key(&config.cert_path, &config.key_path)?;
+        Ok(Arc::new(Self {
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+            current: RwLock::new(Arc::new(current)),
+            modified: Mutex::new(modified),
+        }))
+    }
+
+    /// Re-read the files if either changed; true if a new pair is now served
+    ///
+    /// The previous pair stays in use on error, and the files are read again
+    /// on the next call (e.g. once a renewal has replaced both).
+    pub fn reload(&self) -> Result<bool> {
+        let modified = modified(&self.cert_path, &self.key_path);
+        if modified.is_some() && modified == *self.modified.lock().unwrap() {
+            return Ok(false);
+        }
+        let current = read_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(current);
+        *self.modified.lock().unwrap() = modified;
+        Ok(true)
+    }
+
+    /// rustls configuration serving these certificates, offering HTTP/2 and HTTP/1.1
+    pub fn server_config(self: &Arc<Self>) -> Result<Arc<ServerConfig>> {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(crypto_provider()))
+            .with_safe_default_protocol_versions()
+            .context("Failed to configure TLS")?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((modified(cert_path)?, modified(key_path)?))
+}
+
+fn read_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", cert_path.display(), e))?;
+    if chain.is_empty() {
+        anyhow::bail!("No certificate in {}", cert_path.display());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", key_path.display(), e))?;
+    CertifiedKey::from_der(chain, key, &crypto_provider()).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid TLS certificate {} or key {}: {}",
+            cert_path.display(),
+            key_path.display(),
+            e
+        )
+    })
+}
+
+/// Periodically reload `certificates` until background tasks are stopped
+pub fn spawn_reload(certificates: Arc<Certificates>, interval: Duration, shutdown: &Shutdown) {
+    if interval.is_zero() {
+        return;
+    }
+    shutdown.spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match certificates.reload() {
+                Ok(true) => tracing::info!(
+                    event = "tls_certificate_reloaded",
+                    cert_file = %certificates.cert_path.display(),
+                    "TLS certificate changed, using it for new connections"
+                ),
+                Ok(false) => {}
+                Err(e) => tracing::warn!(
+                    error = %e,
+                    "Failed to reload TLS certificate, keeping the previous one"
+                ),
+            }
+        }
+    });
+}
+
+// =============================================================================
+// Listeners
+// =============================================================================
+
+/// HTTPS listener for [`crate::shutdown::serve`]
+///
+/// Handshakes run in their own tasks so a slow client does not hold up
+/// accepting others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(64);
+        tokio::spawn(accept_connections(
+            listener,
+            TlsAcceptor::from(config),
+            sender,
+        ));
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept loop only ends once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Accept TCP connections and hand over those completing the handshake,
+/// until the [`TlsListener`] is dropped (the server stopped accepting)
+async fn accept_connections(
+    mut listener: TcpListener,
+    acceptor: TlsAcceptor,
+    sender: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, client) = tokio::select! {
+            _ = sender.closed() => return,
+            // Retries (with a back-off) on accept errors
+            accepted = Listener::accept(&mut listener) => accepted,
+        };
+        let acceptor = acceptor.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = sender.send((stream, client)).await;
+                }
+                Ok(Err(e)) => tracing::debug!(%client, error = %e, "TLS handshake failed"),
+                Err(_) => tracing::debug!(%client, "TLS handshake timed out"),
+            }
+        });
+    }
+}
+
+/// Plain HTTP app redirecting every request to the same URL over HTTPS on `https_port`
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, &uri, https_port)
+    })
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let Some(host) = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "Missing or invalid Host header").into_response();
+    };
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("https://{}{}{}", host.host(), port, path)).into_response()
+}
+
+/// Bind the HTTP-to-HTTPS redirect listener; it runs until background tasks are stopped
+pub async fn spawn_redirect_listener(
+    address: &str,
+    https_port: u16,
+    shutdown: &Shutdown,
+) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to bind the HTTPS redirect listener on {}", address))?;
+    let local_addr = listener.local_addr()?;
+    shutdown.spawn(async move {
+        if let Err(e) = axum::serve(listener, redirect_router(https_port)).await {
+            tracing::error!(error = %e, "HTTPS redirect listener failed");
+        }
+    });
+    Ok(local_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shutdown;
+    use axum::routing::get;
+
+    const FIRST_CERT: &[u8] = include_bytes!("testdata/tls_first_cert.pem");
+    const FIRST_KEY: &[u8] = include_bytes!("testdata/tls_first_key.pem");
+    const SECOND_CERT: &[u8] = include_bytes!("testdata/tls_second_cert.pem");
+    const SECOND_KEY: &[u8] = include_bytes!("testdata/tls_second_key.pem");
+
+    fn temp_config(name: &str, cert: &[u8], key: &[u8]) -> TlsConfig {
+        let path = |file: &str| {
+            std::env::temp_dir().join(format!(
+                "portal_tls_{}_{}_{}",
+                std::process::id(),
+                name,
+                file
+            ))
+        };
+        let config = TlsConfig {
+            cert_path: path("cert.pem"),
+            key_path: path("key.pem"),
+            redirect_port: None,
+            reload_secs: 0,
+        };
+        fs::write(&config.cert_path, cert).unwrap();
+        fs::write(&config.key_path, key).unwrap();
+        config
+    }
+
+    fn served(certificates: &Certificates) -> Vec<u8> {
+        certificates.current.read().unwrap().cert[0].to_vec()
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let config = temp_config("reload", FIRST_CERT, FIRST_KEY);
+        let certificates = Certificates::load(&config).unwrap();
+        let first = served(&certificates);
+        assert!(!certificates.reload().unwrap());
+
+        // A certificate whose key is not renewed yet: keep serving the first one
+        fs::write(&config.cert_path, SECOND_CERT).unwrap();
+        // Writes within the file system's timestamp granularity look unchanged
+        *certificates.modified.lock().unwrap() = None;
+        assert!(certificates.reload().is_err());
+        assert_eq!(served(&certificates), first);
+
+        fs::write(&config.key_path, SECOND_KEY).unwrap();
+        assert!(certificates.reload().unwrap());
+        assert_ne!(served(&certificates), first);
+
+        // Mismatched files are refused at startup
+        let config = temp_config("mismatch", FIRST_CERT, SECOND_KEY);
+        assert!(Certificates::load(&config).is_err());
+    }
+
+    #[test]
+    fn test_redirect_to_https() {
+        let redirect = |host: Option<&str>, uri: &str, port: u16| {
+            let mut headers = HeaderMap::new();
+            if let Some(host) = host {
+                headers.insert(HOST, host.parse().unwrap());
+            }
+            let response = redirect_to_https(&headers, &uri.parse().unwrap(), port);
+            (
+                response.status(),
+                response
+                    .headers()
+                    .get("location")
+                    .map(|location| location.to_str().unwrap().to_string()),
+            )
+        };
+        assert_eq!(
+            redirect(Some("portal.example.com"), "/auth/login?next=%2F", 443),
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                Some("https://portal.example.com/auth/login?next=%2F".to_string())
+            )
+        );
+        assert_eq!(
+            redirect(Some("portal.example.com:80"), "/", 8443)
+                .1
+                .unwrap(),
+            "https://portal.example.com:8443/"
+        );
+        assert_eq!(
+            redirect(Some("[::1]:80"), "/healthz", 443).1.unwrap(),
+            "https://[::1]/healthz"
+        );
+        assert_eq!(redirect(None, "/", 443).0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_serve_https_with_http2() {
+        let config = temp_config("serve", FIRST_CERT, FIRST_KEY);
+        let certificates = Certificates::load(&config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let listener = TlsListener::new(listener, certificates.server_config().unwrap()).unwrap();
+        let app = Router::new().route("/", get(|| async { "hello" }));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            shutdown::serve(
+                listener,
+                app,
+                &Shutdown::default(),
+                async {
+                    stopped.await.unwrap();
+                    "SIGTERM"
+                },
+                Duration::ZERO,
+                Duration::from_secs(1),
+            )
+            .await
+        });
+
+        let client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .add_root_certificate(reqwest::Certificate::from_pem(FIRST_CERT).unwrap())
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{}/", port))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        assert_eq!(response.text().await.unwrap(), "hello");
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! Device approval page (`/auth/device`)
+//!
+//! The `verification_uri` of the device authorization grant. The user
+//! confirms the code shown by their CLI on their portal session; approving
+//! continues to Keycloak's confirmation page. Like the token pages, the POST
+//! form relies on the `SameSite=Lax` session cookie for CSRF protection.
+
+use super::templates::DeviceTemplate;
+use crate::{
+    audit::{AuditContext, AuditEvent, AuditEventType},
+    auth::{
+        device::Approver,
+        extractors::{AuthError, AuthenticatedUser, BrowserUser},
+        handlers::create_oauth_client,
+        helpers::create_http_client,
+    },
+    AppState,
+};
+use askama::Template;
+use axum::extract::{Form, Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceQuery {
+    pub user_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceDecisionForm {
+    pub user_code: String,
+    /// `approve` or `deny`
+    pub decision: String,
+}
+
+fn render(template: DeviceTemplate) -> Response {
+    match template.render() {
+        Ok(html) => Html(html).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response(),
+    }
+}
+
+fn username(user: &AuthenticatedUser) -> String {
+    user.claims
+        .preferred_username
+        .clone()
+        .unwrap_or_else(|| user.claims.sub.clone())
+}
+
+/// Show the code confirmation form (signed-out users are sent to sign in first)
+pub async fn device_page_handler(
+    Query(query): Query<DeviceQuery>,
+    user: Result<BrowserUser, AuthError>,
+) -> Response {
+    let user = match user {
+        Ok(BrowserUser(user)) => user,
+        Err(AuthError::Unauthenticated(_)) => return Redirect::to("/").into_response(),
+        Err(e) => return e.into_response(),
+    };
+
+    render(DeviceTemplate {
+        username: username(&user),
+        user_code: query.user_code.unwrap_or_default(),
+        error: None,
+        denied: false,
+    })
+}
+
+/// Approve (continue to Keycloak) or deny a device code
+pub async fn device_decision_handler(
+    State(state): State<Arc<AppState>>,
+    context: AuditContext,
+    BrowserUser(user): BrowserUser,
+    Form(form): Form<DeviceDecisionForm>,
+) -> Response {
+    let page = |error: Option<String>, denied: bool| DeviceTemplate {
+        username: username(&user),
+        user_code: form.user_code.clone(),
+        error,
+        denied,
+    };
+
+    let approver = Approver {
+        provider: &user.provider,
+        sub: &user.claims.sub,
+    };
+    if form.decision != "approve" {
+        return match state.device_flows.deny(&form.user_code, approver) {
+            Ok(()) => {
+                state.audit.record(
+                    AuditEvent::new(AuditEventType::DeviceDenied, &context)
+                        .user(&user.provider, &user.claims.sub)
+                        .target(&form.user_code),
+                );
+                render(page(None, true))
+            }
+            Err(message) => render(page(Some(message), false)),
+        };
+    }
+
+    let Some(provider) = state.config.identity_provider(&user.provider) else {
+        return render(page(Some("Unknown identity provider".to_string()), false));
+    };
+    let client = match create_oauth_client(provider, &state.config.redirect_uri) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to create OAuth client");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "OAuth configuration error",
+            )
+                .into_response();
+        }
+    };
+    let http_client = match create_http_client(
+        state.config.http_connect_timeout_secs,
+        state.config.http_request_timeout_secs,
+    ) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to build HTTP client for device flow");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
+        }
+    };
+
+    match state.device_flows.approve(
+        &form.user_code,
+        approver,
+        client,
+        http_client,
+        state.identity_providers.clone(),
+        &state.shutdown,
+    ) {
+        Ok(confirm_url) => {
+            state.audit.record(
+                AuditEvent::new(AuditEventType::DeviceApproved, &context)
+                    .user(&user.provider, &user.claims.sub)
+                    .target(&form.user_code),
+            );
+            Redirect::to(&confirm_url).into_response()
+        }
+        Err(message) => {
+            state.audit.record(
+                AuditEvent::new(AuditEventType::AccessDenied, &context)
+                    .user(&user.provider, &user.claims.sub)
+                    .reason(&message)
+                    .target(&form.user_code),
+            );
+            render(page(Some(message), false))
+        }
+    }
+}
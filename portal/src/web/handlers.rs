@@ -1,9 +1,24 @@
-use super::templates::{DashboardTemplate, DeploymentDisplay, FormattedTime, LandingTemplate};
-use crate::{auth::extractors::AuthenticatedUser, services::filter_services_for_user, AppState};
+use super::security_headers::CspNonce;
+use super::templates::{
+    DashboardTemplate, DeploymentDisplay, EnvironmentSection, FormattedTime, LandingTemplate,
+    LoginOption,
+};
+use crate::{
+    auth::{
+        extract_cookie,
+        extractors::{AuthenticatedUser, BrowserUser},
+        LoginChoice, LAST_LOGIN_COOKIE,
+    },
+    check_config::render_settings,
+    config::{EffectiveSetting, IdentityProviderConfig},
+    services::{filter_services_for_user, DescriptorSnapshot},
+    AppState,
+};
 use askama::Template;
 use axum::extract::State;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::{Html, IntoResponse};
+use axum::{Extension, Json};
 use std::sync::Arc;
 
 /// Returns the number of days in a given month for a given year
@@ -88,21 +103,106 @@ fn format_utc_datetime(iso_datetime: &str) -> Option<FormattedTime> {
     })
 }
 
+/// Build dashboard sections for federated deployments
+///
+/// Each deployment's cards are filtered against its own role and identity
+/// provider requirements; deployments where the user can access nothing are
+/// left out.
+fn environment_sections(
+    snapshots: &[Arc<DescriptorSnapshot>],
+    user_roles: &[String],
+    provider: &str,
+) -> Vec<EnvironmentSection> {
+    snapshots
+        .iter()
+        .map(|snapshot| EnvironmentSection {
+            deployment_id: snapshot.descriptor.deployment_id.clone(),
+            environment: snapshot.descriptor.environment.clone(),
+            services: filter_services_for_user(&snapshot.services, user_roles, provider),
+        })
+        .filter(|section| !section.services.is_empty())
+        .collect()
+}
+
+/// Build the landing page sign-in buttons
+///
+/// One button per identity provider, followed by one per Keycloak-brokered IdP
+/// of that provider. Broker labels name the provider when there is more than
+/// one. The option matching the last login is marked for pre-selection.
+fn login_options(
+    providers: &[IdentityProviderConfig],
+    last_login: Option<&LoginChoice>,
+) -> Vec<LoginOption> {
+    let mut options = Vec::new();
+    for provider in providers {
+        let is_last = |idp: Option<&str>| {
+            last_login.is_some_and(|last| last.provider == provider.id && last.idp.as_deref() == idp)
+        };
+        options.push(LoginOption {
+            id: provider.id.clone(),
+            label: provider.display_name.clone(),
+            href: format!("/auth/login?provider={}", provider.id),
+            last_used: is_last(None),
+        });
+        for broker in &provider.brokers {
+            let label = if providers.len() > 1 {
+                format!("{} ({})", broker.display_name, provider.display_name)
+            } else {
+                broker.display_name.clone()
+            };
+            options.push(LoginOption {
+                id: format!("{}-{}", provider.id, broker.alias),
+                label,
+                href: format!(
+                    "/auth/login?provider={}&idp={}",
+                    provider.id,
+                    urlencoding::encode(&broker.alias)
+                ),
+                last_used: is_last(Some(&broker.alias)),
+            });
+        }
+    }
+    options
+}
+
 /// Liveness probe - always returns OK if the process is running
 pub async fn healthz_handler() -> impl IntoResponse {
     StatusCode::OK
 }
 
+/// Prometheus metrics (rate limiter counters)
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.rate_limiter.render_metrics(),
+    )
+}
+
+/// Effective configuration, as printed by `portal check-config` (admin listener only)
+pub async fn debug_config_handler(
+    Extension(settings): Extension<Arc<Vec<EffectiveSetting>>>,
+) -> impl IntoResponse {
+    render_settings(&settings)
+}
+
 /// Readiness probe - checks if the service is ready to handle requests
 ///
 /// Returns 200 OK if:
-/// - JWKS cache has been populated (Keycloak is reachable)
+/// - JWKS cache of every identity provider has been populated (Keycloak is reachable)
 ///
 /// Returns 503 Service Unavailable if:
-/// - JWKS cache is empty (Keycloak not yet contacted or unreachable)
+/// - The portal is shutting down (so the load balancer drains it first)
+/// - Any JWKS cache is empty (Keycloak not yet contacted or unreachable)
 pub async fn readyz_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if state.shutdown.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "not ready: shutting down");
+    }
+
     // Check if JWKS has been cached (indicates Keycloak connectivity)
-    let jwks_cached = state.jwt_validator.is_jwks_cached().await;
+    let jwks_cached = state.identity_providers.is_jwks_cached().await;
 
     if jwks_cached {
         (StatusCode::OK, "ready")
@@ -112,7 +212,11 @@ pub async fn readyz_handler(State(state): State<Arc<AppState>>) -> impl IntoResp
     }
 }
 
-pub async fn landing_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+pub async fn landing_handler(
+    State(state): State<Arc<AppState>>,
+    CspNonce(csp_nonce): CspNonce,
+    headers: HeaderMap,
+) -> impl IntoResponse {
     // Pick a random logo each time the landing page is loaded
     let logo_url = if !state.logos.is_empty() {
         let random_index = fastrand::usize(..state.logos.len());
@@ -121,7 +225,15 @@ pub async fn landing_handler(State(state): State<Arc<AppState>>) -> impl IntoRes
         None
     };
 
-    let template = LandingTemplate { logo_url };
+    let last_login = extract_cookie(&headers, LAST_LOGIN_COOKIE)
+        .and_then(|value| LoginChoice::from_cookie_value(&value));
+    let login_options = login_options(&state.config.identity_providers, last_login.as_ref());
+
+    let template = LandingTemplate {
+        logo_url,
+        login_options,
+        csp_nonce,
+    };
     match template.render() {
         Ok(html) => Html(html).into_response(),
         Err(_) => (
@@ -132,21 +244,70 @@ pub async fn landing_handler(State(state): State<Arc<AppState>>) -> impl IntoRes
     }
 }
 
+/// Service cards the caller can access, as JSON
+///
+/// Accepts the session cookie or a bearer token, so scripts and other
+/// services can list what a user (or service account) may reach. Federated
+/// deployments are not included. Personal access tokens restricted to some
+/// services only list those.
+pub async fn services_api_handler(
+    State(state): State<Arc<AppState>>,
+    AuthenticatedUser {
+        claims,
+        roles,
+        provider,
+        method,
+        service_scope,
+    }: AuthenticatedUser,
+) -> impl IntoResponse {
+    let snapshot = state.descriptor.current().await;
+    let mut services = filter_services_for_user(&snapshot.services, &roles, &provider);
+    if let Some(scope) = &service_scope {
+        services.retain(|card| scope.contains(&card.id));
+    }
+
+    tracing::debug!(
+        sub = %claims.sub,
+        provider = %provider,
+        method = ?method,
+        accessible_services = services.len(),
+        "Listed services via API"
+    );
+
+    Json(serde_json::json!({
+        "deploymentId": snapshot.descriptor.deployment_id,
+        "services": services
+    }))
+}
+
 pub async fn dashboard_handler(
     State(state): State<Arc<AppState>>,
-    AuthenticatedUser { claims, .. }: AuthenticatedUser,
+    CspNonce(csp_nonce): CspNonce,
+    BrowserUser(AuthenticatedUser {
+        claims, provider, ..
+    }): BrowserUser,
 ) -> impl IntoResponse {
     // Get user's realm roles from JWT claims
     let user_roles = claims.roles();
 
+    // One consistent snapshot for the whole request (remote descriptors may be swapped)
+    let snapshot = state.descriptor.current().await;
+
     // Filter services to only those the user can access (per plan.md 2.7)
-    let accessible_services = filter_services_for_user(&state.services, &user_roles);
+    let accessible_services =
+        filter_services_for_user(&snapshot.services, &user_roles, &provider);
+
+    // Federated deployments, each filtered against its own role requirements
+    let environments =
+        environment_sections(&state.federation.snapshots().await, &user_roles, &provider);
 
     tracing::debug!(
         username = ?claims.preferred_username,
+        provider = %provider,
         user_roles = ?user_roles,
-        total_services = state.services.len(),
+        total_services = snapshot.services.len(),
         accessible_services = accessible_services.len(),
+        federated_environments = environments.len(),
         "Filtered services for user"
     );
 
@@ -154,7 +315,7 @@ pub async fn dashboard_handler(
     // deployment_id is always present, metadata fields are optional
     let deployment = {
         let (short_sha, commit_time, deployed_time) =
-            if let Some(d) = state.descriptor.deployment.as_ref() {
+            if let Some(d) = snapshot.descriptor.deployment.as_ref() {
                 (
                     d.commit_sha
                         .as_ref()
@@ -169,7 +330,8 @@ pub async fn dashboard_handler(
             };
 
         DeploymentDisplay {
-            deployment_id: state.descriptor.deployment_id.clone(),
+            deployment_id: snapshot.descriptor.deployment_id.clone(),
+            environment: snapshot.descriptor.environment.clone(),
             short_sha,
             commit_time,
             deployed_time,
@@ -184,6 +346,9 @@ pub async fn dashboard_handler(
         email: claims.email.clone(),
         services: accessible_services,
         deployment,
+        environments,
+        tokens_enabled: state.token_store.is_some(),
+        csp_nonce,
     };
 
     match template.render() {
@@ -199,6 +364,118 @@ pub async fn dashboard_handler(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::secrets::Secret;
+    use crate::services::Descriptor;
+
+    fn snapshot(deployment_id: &str, required_role: &str) -> Arc<DescriptorSnapshot> {
+        let json = format!(
+            r#"{{
+            "version": "2",
+            "deploymentId": "{id}",
+            "environment": "{id}",
+            "baseDomain": "localhost",
+            "portal": {{ "publicUrl": "http://localhost" }},
+            "keycloak": {{
+                "publicUrl": "http://keycloak.localhost",
+                "issuerUrl": "http://keycloak.localhost/realms/dev",
+                "realm": "dev"
+            }},
+            "services": [
+                {{
+                    "id": "grafana",
+                    "name": "Grafana",
+                    "url": "http://grafana.{id}.localhost",
+                    "protected": true,
+                    "authType": "oauth2-proxy",
+                    "requiredRealmRoles": ["{role}"]
+                }}
+            ]
+        }}"#,
+            id = deployment_id,
+            role = required_role
+        );
+        let descriptor =
+            Descriptor::from_json_with_source(&json, crate::services::DescriptorSource::EnvJson)
+                .unwrap();
+        Arc::new(DescriptorSnapshot::new(descriptor))
+    }
+
+    #[test]
+    fn test_environment_sections_use_each_deployments_roles() {
+        let snapshots = vec![snapshot("staging", "dev"), snapshot("prod", "ops")];
+
+        let sections = environment_sections(&snapshots, &["dev".to_string()], "default");
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].deployment_id, "staging");
+        assert_eq!(
+            sections[0].services[0].url,
+            "http://grafana.staging.localhost"
+        );
+
+        let sections = environment_sections(&snapshots, &["admin".to_string()], "default");
+        let ids: Vec<&str> = sections.iter().map(|s| s.deployment_id.as_str()).collect();
+        assert_eq!(ids, vec!["staging", "prod"]);
+    }
+
+    fn provider(id: &str, brokers: &[&str]) -> IdentityProviderConfig {
+        IdentityProviderConfig {
+            id: id.to_string(),
+            display_name: id.to_uppercase(),
+            keycloak_url: "http://keycloak:8080".to_string(),
+            keycloak_callback_url: "http://keycloak.localhost".to_string(),
+            realm: id.to_string(),
+            client_id: "portal".to_string(),
+            client_secret: Secret::new("secret"),
+            accepted_audiences: Vec::new(),
+            brokers: brokers
+                .iter()
+                .map(|alias| crate::config::BrokeredIdp {
+                    alias: alias.to_string(),
+                    display_name: "GitHub".to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_login_options_per_provider_and_broker() {
+        let last = LoginChoice {
+            provider: "contractors".to_string(),
+            idp: Some("github".to_string()),
+        };
+        let options = login_options(
+            &[provider("default", &[]), provider("contractors", &["github"])],
+            Some(&last),
+        );
+
+        let summary: Vec<(&str, &str, &str, bool)> = options
+            .iter()
+            .map(|o| (o.id.as_str(), o.label.as_str(), o.href.as_str(), o.last_used))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("default", "DEFAULT", "/auth/login?provider=default", false),
+                (
+                    "contractors",
+                    "CONTRACTORS",
+                    "/auth/login?provider=contractors",
+                    false
+                ),
+                (
+                    "contractors-github",
+                    "GitHub (CONTRACTORS)",
+                    "/auth/login?provider=contractors&idp=github",
+                    true
+                ),
+            ]
+        );
+
+        // A single provider labels brokers without the provider name
+        let options = login_options(&[provider("default", &["github"])], None);
+        assert_eq!(options[1].label, "GitHub");
+        assert!(options.iter().all(|o| !o.last_used));
+    }
 
     #[test]
     fn test_days_in_month() {
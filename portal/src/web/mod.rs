@@ -1,5 +1,8 @@
+pub mod device;
 pub mod handlers;
 pub mod routes;
+pub mod security_headers;
 pub mod templates;
+pub mod tokens;
 
-pub use routes::create_router;
+pub use routes::{create_admin_router, create_router};
@@ -1,26 +1,106 @@
-use super::handlers::{dashboard_handler, healthz_handler, landing_handler, readyz_handler};
+use super::device::{device_decision_handler, device_page_handler};
+use super::handlers::{
+    dashboard_handler, debug_config_handler, healthz_handler, landing_handler, metrics_handler,
+    readyz_handler, services_api_handler,
+};
+use super::security_headers::security_headers;
+use super::tokens::{create_token_handler, revoke_token_handler, tokens_page_handler};
 use crate::{
     auth::{
-        callback_handler, jwt::JwtValidator, login_handler, logout_complete_handler, logout_handler,
+        callback_handler,
+        device::{device_code_handler, device_token_handler},
+        login_handler, logout_complete_handler, logout_handler,
+        providers::IdentityProviders,
+        service_sign_out_handler,
     },
+    config::EffectiveSetting,
+    rate_limit::rate_limit,
+    request_id::request_id,
+    telemetry::trace_request,
     AppState,
 };
-use axum::{routing::get, Extension, Router};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Extension, Router,
+};
 use std::sync::Arc;
 use tower_http::services::ServeDir;
 
-pub fn create_router(state: Arc<AppState>, jwt_validator: Arc<JwtValidator>) -> Router {
+/// Health, readiness and metrics, for the public and admin listeners
+fn health_routes() -> Router<Arc<AppState>> {
     Router::new()
-        .route("/", get(landing_handler))
         .route("/healthz", get(healthz_handler))
         .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
+}
+
+pub fn create_router(state: Arc<AppState>, identity_providers: Arc<IdentityProviders>) -> Router {
+    // Only on the admin listener in production when it is enabled
+    let router = if state.config.serves_health_publicly() {
+        health_routes()
+    } else {
+        Router::new()
+    };
+    let router = router
+        .route("/", get(landing_handler))
         .route("/dashboard", get(dashboard_handler))
+        // JSON for scripts and other services (cookie or Authorization: Bearer)
+        .route("/api/services", get(services_api_handler))
+        // Personal access tokens (browser session only; 404 unless enabled)
+        .route(
+            "/tokens",
+            get(tokens_page_handler).post(create_token_handler),
+        )
+        .route("/tokens/{id}/revoke", post(revoke_token_handler))
         .route("/auth/login", get(login_handler))
         .route("/auth/callback", get(callback_handler))
+        // Device authorization grant: CLIs request and poll, users approve in the browser
+        .route("/auth/device/code", post(device_code_handler))
+        .route("/auth/device/token", post(device_token_handler))
+        .route(
+            "/auth/device",
+            get(device_page_handler).post(device_decision_handler),
+        )
         // Support both POST (form submission, CSRF-safe) and GET (redirect continuation from oauth2-proxy)
         .route("/auth/logout", get(logout_handler).post(logout_handler))
         .route("/auth/logout/complete", get(logout_complete_handler))
+        // Single-service sign-out from a dashboard card (POST form only, keeps portal session)
+        .route("/auth/logout/service", post(service_sign_out_handler))
         .nest_service("/static", ServeDir::new("static"))
-        .layer(Extension(jwt_validator))
+        .layer(Extension(identity_providers))
+        .layer(Extension(state.audit.clone()))
+        // Rate limits (and the client IP the audit log records), inside the
+        // request span so rejections are logged with the request ID
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit,
+        ))
+        // Continue Traefik's trace and give each request an `http_request` span
+        .layer(middleware::from_fn(trace_request))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            security_headers,
+        ))
+        // Outermost: the request ID is known before the request span is created
+        .layer(middleware::from_fn(request_id));
+
+    // The extractor resolves `pat_` bearer tokens through this extension
+    let router = match state.token_store.clone() {
+        Some(token_store) => router.layer(Extension(token_store)),
+        None => router,
+    };
+    router.with_state(state)
+}
+
+/// Router of the admin listener (ADMIN_BIND_ADDRESS)
+///
+/// Health, readiness and metrics plus internal debug endpoints, which the
+/// public router never serves. `settings` are the effective settings shown
+/// by `/debug/config` (secrets redacted).
+pub fn create_admin_router(state: Arc<AppState>, settings: Vec<EffectiveSetting>) -> Router {
+    health_routes()
+        .route("/debug/config", get(debug_config_handler))
+        .layer(Extension(Arc::new(settings)))
         .with_state(state)
 }
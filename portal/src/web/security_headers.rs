@@ -0,0 +1,171 @@
+//! Security response headers and the CSP nonce
+//!
+//! Every response gets `X-Content-Type-Options: nosniff` plus the headers
+//! configured in [`SecurityHeadersConfig`] (HSTS in production only). The
+//! Content-Security-Policy only allows inline `<script>` and `<style>` tags
+//! carrying the request's nonce, which handlers pass to their templates via
+//! the [`CspNonce`] extractor.
+
+use crate::{config::SecurityHeadersConfig, AppState};
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use rand::RngCore;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+const PERMISSIONS_POLICY: HeaderName = HeaderName::from_static("permissions-policy");
+
+/// Per-request nonce for inline `<script nonce="...">` / `<style nonce="...">`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    /// 128 random bits, URL-safe base64 (safe in HTML attributes as is)
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        Self(base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            bytes,
+        ))
+    }
+}
+
+impl<S> FromRequestParts<S> for CspNonce
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    /// The nonce set by [`security_headers`] (a fresh, unused one without the middleware)
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<CspNonce>()
+            .cloned()
+            .unwrap_or_else(CspNonce::generate))
+    }
+}
+
+/// The Content-Security-Policy for one response (None if nothing is configured)
+pub fn content_security_policy(config: &SecurityHeadersConfig, nonce: &CspNonce) -> Option<String> {
+    let policy = config
+        .content_security_policy
+        .as_deref()
+        .map(|policy| policy.replace("{nonce}", &nonce.0));
+    let frame_ancestors = config
+        .frame_ancestors
+        .as_deref()
+        .map(|sources| format!("frame-ancestors {}", sources));
+    match (policy, frame_ancestors) {
+        (Some(policy), Some(directive)) => Some(format!(
+            "{}; {}",
+            policy.trim_end().trim_end_matches(';'),
+            directive
+        )),
+        (policy, directive) => policy.or(directive),
+    }
+}
+
+/// Middleware: generate the CSP nonce and set security headers on the response
+pub async fn security_headers(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let nonce = CspNonce::generate();
+    request.extensions_mut().insert(nonce.clone());
+
+    let mut response = next.run(request).await;
+    let config = &state.config.security_headers;
+    let headers = response.headers_mut();
+
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    set(
+        headers,
+        CONTENT_SECURITY_POLICY,
+        content_security_policy(config, &nonce),
+    );
+    set(headers, REFERRER_POLICY, config.referrer_policy.clone());
+    set(
+        headers,
+        PERMISSIONS_POLICY,
+        config.permissions_policy.clone(),
+    );
+    // Browsers ignore HSTS over plain HTTP; production is the HTTPS deployment
+    if state.config.is_production() {
+        set(headers, STRICT_TRANSPORT_SECURITY, config.hsts.clone());
+    }
+    response
+}
+
+/// Values are checked when the configuration is loaded
+fn set(headers: &mut HeaderMap, name: HeaderName, value: Option<String>) {
+    if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DEFAULT_CONTENT_SECURITY_POLICY, DEFAULT_FRAME_ANCESTORS};
+
+    fn config(csp: Option<&str>, frame_ancestors: Option<&str>) -> SecurityHeadersConfig {
+        SecurityHeadersConfig {
+            hsts: None,
+            content_security_policy: csp.map(str::to_string),
+            frame_ancestors: frame_ancestors.map(str::to_string),
+            referrer_policy: None,
+            permissions_policy: None,
+        }
+    }
+
+    #[test]
+    fn test_content_security_policy() {
+        let nonce = CspNonce("abc123".to_string());
+
+        let policy = content_security_policy(
+            &config(
+                Some(DEFAULT_CONTENT_SECURITY_POLICY),
+                Some(DEFAULT_FRAME_ANCESTORS),
+            ),
+            &nonce,
+        )
+        .unwrap();
+        assert!(policy.contains("script-src 'self' 'nonce-abc123'"));
+        assert!(policy.contains("style-src 'self' 'nonce-abc123'"));
+        assert!(!policy.contains("{nonce}"));
+        assert!(policy.ends_with("; frame-ancestors 'none'"));
+
+        // A trailing separator in a configured policy is not doubled
+        assert_eq!(
+            content_security_policy(&config(Some("default-src 'self';"), Some("'self'")), &nonce)
+                .unwrap(),
+            "default-src 'self'; frame-ancestors 'self'"
+        );
+        assert_eq!(
+            content_security_policy(&config(None, Some("'none'")), &nonce).unwrap(),
+            "frame-ancestors 'none'"
+        );
+        assert!(content_security_policy(&config(None, None), &nonce).is_none());
+    }
+
+    #[test]
+    fn test_nonce_is_fresh_and_attribute_safe() {
+        let a = CspNonce::generate();
+        let b = CspNonce::generate();
+        assert_ne!(a, b);
+        assert_eq!(a.0.len(), 22);
+        assert!(a
+            .0
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+}
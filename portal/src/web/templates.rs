@@ -1,10 +1,35 @@
 use crate::services::ServiceCard;
 use askama::Template;
 
+/// A sign-in button on the landing page
+#[derive(Debug, PartialEq)]
+pub struct LoginOption {
+    /// Element id suffix (`provider` or `provider-alias`)
+    pub id: String,
+    /// Button label
+    pub label: String,
+    /// `/auth/login` URL with `provider` and optional `idp`
+    pub href: String,
+    /// Matches the `last_login` cookie (pre-selected)
+    pub last_used: bool,
+}
+
 #[derive(Template)]
 #[template(path = "landing.html")]
 pub struct LandingTemplate {
     pub logo_url: Option<String>,
+    /// One entry per identity provider and brokered IdP; a single option
+    /// shows a plain "Sign In"
+    pub login_options: Vec<LoginOption>,
+    /// Nonce of the inline `<style>` (see `security_headers`)
+    pub csp_nonce: String,
+}
+
+impl LandingTemplate {
+    /// Whether one option is pre-selected (the others are shown as secondary)
+    pub fn has_last_used(&self) -> bool {
+        self.login_options.iter().any(|option| option.last_used)
+    }
 }
 
 /// A formatted time with both display and ISO formats
@@ -21,6 +46,8 @@ pub struct FormattedTime {
 pub struct DeploymentDisplay {
     /// Deployment ID (e.g., "prod", "staging") - always present
     pub deployment_id: String,
+    /// Environment name from the descriptor (e.g., "prod")
+    pub environment: String,
     /// Short commit SHA (first 7 chars)
     pub short_sha: Option<String>,
     /// Commit time with display and ISO formats
@@ -29,6 +56,16 @@ pub struct DeploymentDisplay {
     pub deployed_time: Option<FormattedTime>,
 }
 
+/// Service cards of a federated deployment, shown in their own section
+pub struct EnvironmentSection {
+    /// Deployment ID of the federated descriptor (card label and anchor)
+    pub deployment_id: String,
+    /// Environment name from the federated descriptor
+    pub environment: String,
+    /// Cards the user can access, per that descriptor's role requirements
+    pub services: Vec<ServiceCard>,
+}
+
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 pub struct DashboardTemplate {
@@ -37,4 +74,55 @@ pub struct DashboardTemplate {
     pub services: Vec<ServiceCard>,
     /// Deployment info for footer display
     pub deployment: DeploymentDisplay,
+    /// Federated deployments with at least one accessible service
+    pub environments: Vec<EnvironmentSection>,
+    /// Show the "Access tokens" link (PORTAL_TOKEN_STORE_PATH is set)
+    pub tokens_enabled: bool,
+    /// Nonce of the inline search `<script>` and htmx's indicator styles
+    pub csp_nonce: String,
+}
+
+/// A personal access token in the list (never the secret)
+pub struct TokenRow {
+    pub id: String,
+    pub name: String,
+    /// Creation date (`YYYY-MM-DD`, UTC)
+    pub created: String,
+    /// Expiry date (`YYYY-MM-DD`, UTC)
+    pub expires: String,
+    /// Comma-separated service ids, or "All services"
+    pub scope: String,
+}
+
+/// A service the new token can be restricted to
+pub struct ServiceOption {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Template)]
+#[template(path = "tokens.html")]
+pub struct TokensTemplate {
+    pub username: String,
+    pub tokens: Vec<TokenRow>,
+    /// Services the user can access (scope checkboxes)
+    pub services: Vec<ServiceOption>,
+    /// Token minted by this request, shown once
+    pub new_token: Option<String>,
+    /// Validation or store error of the create form
+    pub error: Option<String>,
+    pub default_days: u64,
+    pub max_days: u64,
+}
+
+#[derive(Template)]
+#[template(path = "device.html")]
+pub struct DeviceTemplate {
+    pub username: String,
+    /// Code from the CLI (prefilled from `verification_uri_complete`)
+    pub user_code: String,
+    /// Why the code could not be approved
+    pub error: Option<String>,
+    /// The user denied the request
+    pub denied: bool,
 }
@@ -0,0 +1,320 @@
+//! Personal access token pages (`/tokens`)
+//!
+//! Users mint, list and revoke their tokens here. All routes require the
+//! browser session: a personal access token can never mint or revoke tokens.
+//! The POST forms rely on the `SameSite=Lax` session cookie for CSRF
+//! protection, like single-service sign-out.
+
+use super::templates::{ServiceOption, TokenRow, TokensTemplate};
+use crate::{
+    audit::{AuditContext, AuditEvent, AuditEventType},
+    auth::{
+        extractors::{AuthenticatedUser, BrowserUser},
+        tokens::{format_unix_date, TokenInfo, TokenStore},
+    },
+    services::{filter_services_for_user, ServiceCard},
+    AppState,
+};
+use askama::Template;
+use axum::extract::{Form, Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Expiry preselected in the create form
+pub const DEFAULT_TOKEN_DAYS: u64 = 30;
+
+/// Longest expiry a token can be minted with
+pub const MAX_TOKEN_DAYS: u64 = 365;
+
+const MAX_TOKEN_NAME_LEN: usize = 64;
+
+/// A validated create-token form
+#[derive(Debug, PartialEq)]
+struct NewToken {
+    name: String,
+    days: u64,
+    /// None = every service the user can access
+    services: Option<Vec<String>>,
+}
+
+/// Validate the create form (`name`, `days`, repeated `service`)
+///
+/// Services must be among the ones the user can currently access.
+fn parse_token_form(
+    fields: &[(String, String)],
+    accessible: &[ServiceCard],
+) -> Result<NewToken, String> {
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.trim())
+            .unwrap_or_default()
+    };
+
+    let name = field("name");
+    if name.is_empty() {
+        return Err("Name is required".to_string());
+    }
+    if name.chars().count() > MAX_TOKEN_NAME_LEN {
+        return Err(format!(
+            "Name must be at most {} characters",
+            MAX_TOKEN_NAME_LEN
+        ));
+    }
+
+    let days = field("days")
+        .parse::<u64>()
+        .ok()
+        .filter(|days| (1..=MAX_TOKEN_DAYS).contains(days))
+        .ok_or_else(|| format!("Expiry must be between 1 and {} days", MAX_TOKEN_DAYS))?;
+
+    let mut services: Vec<String> = Vec::new();
+    for (_, id) in fields.iter().filter(|(k, _)| k == "service") {
+        if !accessible.iter().any(|card| &card.id == id) {
+            return Err(format!("Unknown service: {}", id));
+        }
+        if !services.contains(id) {
+            services.push(id.clone());
+        }
+    }
+
+    Ok(NewToken {
+        name: name.to_string(),
+        days,
+        services: (!services.is_empty()).then_some(services),
+    })
+}
+
+fn token_row(info: TokenInfo) -> TokenRow {
+    TokenRow {
+        id: info.id,
+        name: info.name,
+        created: format_unix_date(info.created_at),
+        expires: format_unix_date(info.expires_at),
+        scope: match info.services {
+            Some(services) => services.join(", "),
+            None => "All services".to_string(),
+        },
+    }
+}
+
+fn not_enabled() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        "Personal access tokens are not enabled",
+    )
+        .into_response()
+}
+
+/// Render the token page, optionally with a freshly minted token or an error
+async fn render_tokens_page(
+    state: &AppState,
+    store: &TokenStore,
+    user: &AuthenticatedUser,
+    new_token: Option<String>,
+    error: Option<String>,
+) -> Response {
+    let snapshot = state.descriptor.current().await;
+    let services = filter_services_for_user(&snapshot.services, &user.roles, &user.provider)
+        .into_iter()
+        .map(|card| ServiceOption {
+            id: card.id,
+            name: card.name,
+        })
+        .collect();
+
+    let template = TokensTemplate {
+        username: user
+            .claims
+            .preferred_username
+            .clone()
+            .unwrap_or_else(|| user.claims.sub.clone()),
+        tokens: store
+            .list(&user.provider, &user.claims.sub)
+            .await
+            .into_iter()
+            .map(token_row)
+            .collect(),
+        services,
+        new_token,
+        error,
+        default_days: DEFAULT_TOKEN_DAYS,
+        max_days: MAX_TOKEN_DAYS,
+    };
+    match template.render() {
+        Ok(html) => Html(html).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response(),
+    }
+}
+
+pub async fn tokens_page_handler(
+    State(state): State<Arc<AppState>>,
+    BrowserUser(user): BrowserUser,
+) -> Response {
+    let Some(store) = state.token_store.as_deref() else {
+        return not_enabled();
+    };
+    render_tokens_page(&state, store, &user, None, None).await
+}
+
+/// Mint a token and show it once
+pub async fn create_token_handler(
+    State(state): State<Arc<AppState>>,
+    context: AuditContext,
+    BrowserUser(user): BrowserUser,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Response {
+    let Some(store) = state.token_store.as_deref() else {
+        return not_enabled();
+    };
+
+    let snapshot = state.descriptor.current().await;
+    let accessible = filter_services_for_user(&snapshot.services, &user.roles, &user.provider);
+    let new_token = match parse_token_form(&fields, &accessible) {
+        Ok(new_token) => new_token,
+        Err(error) => return render_tokens_page(&state, store, &user, None, Some(error)).await,
+    };
+
+    match store
+        .mint(
+            &user.claims,
+            &user.provider,
+            &new_token.name,
+            Duration::from_secs(new_token.days * 86_400),
+            new_token.services,
+        )
+        .await
+    {
+        Ok((token, info)) => {
+            state.audit.record(
+                AuditEvent::new(AuditEventType::TokenMinted, &context)
+                    .user(&user.provider, &user.claims.sub)
+                    .target(info.id),
+            );
+            render_tokens_page(&state, store, &user, Some(token), None).await
+        }
+        Err(e) => {
+            tracing::warn!(
+                sub = %user.claims.sub,
+                error = %e,
+                "Failed to mint personal access token"
+            );
+            render_tokens_page(&state, store, &user, None, Some(e.to_string())).await
+        }
+    }
+}
+
+/// Revoke one of the user's tokens and return to the list
+pub async fn revoke_token_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    context: AuditContext,
+    BrowserUser(user): BrowserUser,
+) -> Response {
+    let Some(store) = state.token_store.as_deref() else {
+        return not_enabled();
+    };
+
+    match store.revoke(&user.provider, &user.claims.sub, &id).await {
+        Ok(true) => {
+            state.audit.record(
+                AuditEvent::new(AuditEventType::TokenRevoked, &context)
+                    .user(&user.provider, &user.claims.sub)
+                    .target(&id),
+            );
+            Redirect::to("/tokens").into_response()
+        }
+        Ok(false) => {
+            state.audit.record(
+                AuditEvent::new(AuditEventType::AccessDenied, &context)
+                    .user(&user.provider, &user.claims.sub)
+                    .reason("revoke of unknown or foreign token")
+                    .target(&id),
+            );
+            (StatusCode::NOT_FOUND, "Unknown token").into_response()
+        }
+        Err(e) => {
+            tracing::error!(
+                sub = %user.claims.sub,
+                token_id = %id,
+                error = %e,
+                "Failed to revoke personal access token"
+            );
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke token").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::descriptor::AuthType;
+
+    fn card(id: &str) -> ServiceCard {
+        ServiceCard {
+            id: id.to_string(),
+            name: id.to_string(),
+            url: format!("http://{}.localhost", id),
+            icon: "🔧".to_string(),
+            description: None,
+            protected: true,
+            auth_type: AuthType::Oauth2Proxy,
+            required_realm_roles: None,
+            identity_providers: None,
+        }
+    }
+
+    fn form(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_token_form() {
+        let accessible = [card("grafana"), card("dozzle")];
+        assert_eq!(
+            parse_token_form(
+                &form(&[
+                    ("name", " ci "),
+                    ("days", "90"),
+                    ("service", "grafana"),
+                    ("service", "grafana")
+                ]),
+                &accessible
+            ),
+            Ok(NewToken {
+                name: "ci".to_string(),
+                days: 90,
+                services: Some(vec!["grafana".to_string()]),
+            })
+        );
+        assert_eq!(
+            parse_token_form(&form(&[("name", "ci"), ("days", "1")]), &accessible)
+                .unwrap()
+                .services,
+            None
+        );
+
+        for (fields, error) in [
+            (vec![("name", ""), ("days", "30")], "Name is required"),
+            (
+                vec![("name", "ci"), ("days", "366")],
+                "Expiry must be between 1 and 365 days",
+            ),
+            (
+                vec![("name", "ci"), ("days", "30"), ("service", "admin")],
+                "Unknown service: admin",
+            ),
+        ] {
+            assert_eq!(
+                parse_token_form(&form(&fields), &accessible),
+                Err(error.to_string())
+            );
+        }
+    }
+}
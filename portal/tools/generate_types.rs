@@ -1,229 +1,1178 @@
 //! Generate Rust types from JSON Schema
 //!
-//! Usage: cargo run --bin generate-types
+//! Usage: cargo run --bin generate-types [-- --check]
 //!
-//! This tool reads the canonical JSON schema and generates Rust types.
-//! The generated file uses serde for serialization with camelCase field names.
+//! This tool reads the JSON schemas and generates Rust types:
+//! - `portal-descriptor.v2.schema.json` -> `descriptor_gen.rs` (the in-memory
+//!   model, with validators)
+//! - `portal-descriptor.schema.json` (v1) -> `descriptor_v1_gen.rs` (types
+//!   only; v1 descriptors are upgraded to v2 on load)
+//!
+//! The generated files use serde for serialization with camelCase field names.
+//!
+//! The generator walks the root `properties` and every `$defs` entry:
+//! - object definitions become structs (fields not in `required` are `Option`)
+//! - string definitions with `enum` become enums
+//! - `description` becomes the doc comment of the type or field
+//! - `pattern`, `format`, `minLength` and `minItems` become validators, called
+//!   from a generated `schema_violations` method on every struct
+//!
+//! `const` and conditional (`allOf`/`if`/`then`) rules are cross-field checks
+//! and are hand-written in `services::validation`, next to the generated
+//! checks that `Descriptor::validate` runs.
+//!
+//! With `--check`, nothing is written; the tool exits non-zero when a
+//! checked-in generated file differs from what its schema generates.
+//!
+//! Companion subcommand for descriptor signing (see `services::signature`):
+//!
+//! ```text
+//! cargo run --bin generate-types -- sign-descriptor --generate-key <signing-key-file>
+//! cargo run --bin generate-types -- sign-descriptor --key <signing-key-file> <descriptor.json>
+//! cargo run --bin generate-types -- sign-descriptor --key <signing-key-file> --envelope <descriptor.json>
+//! ```
+//!
+//! The first form writes a new base64 signing key and prints the public key to
+//! configure as `PORTAL_DESCRIPTOR_PUBLIC_KEY`. The second writes a detached
+//! `<descriptor.json>.sig`; the third prints a signed envelope to stdout.
+//!
+//! Companion subcommand for descriptor versions (see `services::migration`):
+//!
+//! ```text
+//! cargo run --bin generate-types -- migrate-descriptor <descriptor.json> [--output <file>]
+//! ```
+//!
+//! Rewrites a v1 descriptor file as the current version (in place unless
+//! `--output` is given). The descriptor must validate before it is rewritten.
+//! Only JSON files are rewritten; YAML and TOML descriptors keep their
+//! comments by being edited by hand.
 
+use portal::services::descriptor::{
+    Descriptor, DescriptorError, DescriptorFormat, DescriptorSource, CURRENT_VERSION,
+};
+use portal::services::signature::{
+    decode_signing_key, detached_signature_path, encode_base64, sign_envelope, sign_payload,
+};
+use serde::de::{Deserializer, MapAccess, Visitor};
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
+use std::process::ExitCode;
 
-const SCHEMA_PATH: &str = "../schema/portal-descriptor.schema.json";
-const OUTPUT_PATH: &str = "src/services/descriptor_gen.rs";
-
-#[derive(Debug, Deserialize)]
-struct JsonSchema {
-    #[serde(rename = "$defs")]
-    defs: Option<Defs>,
+/// A schema and the Rust module generated from it
+struct Target {
+    schema: &'static str,
+    output: &'static str,
+    /// Emit `schema_violations` and validators (only the current model is validated)
+    validators: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct Defs {
-    #[serde(rename = "authType")]
-    auth_type: Option<AuthTypeDef>,
-    // Note: Rust generator only extracts authType enum values
-    // Pattern validation (slug, httpUrl) is handled by serde's deny_unknown_fields
-    // and the portal's business logic rather than regex validation
-}
+const TARGETS: &[Target] = &[
+    // Current in-memory model
+    Target {
+        schema: "../schema/portal-descriptor.v2.schema.json",
+        output: "src/services/descriptor_gen.rs",
+        validators: true,
+    },
+    // v1 shape, parsed and upgraded to the current model on load
+    Target {
+        schema: "../schema/portal-descriptor.schema.json",
+        output: "src/services/descriptor_v1_gen.rs",
+        validators: false,
+    },
+];
 
-#[derive(Debug, Deserialize)]
-struct AuthTypeDef {
+/// Name of the struct generated for the schema root
+const ROOT_TYPE_NAME: &str = "Descriptor";
+
+/// Regex used for each supported `format` keyword
+const FORMAT_PATTERNS: &[(&str, &str)] = &[
+    (
+        "date-time",
+        r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})$",
+    ),
+    ("uri", r"^[A-Za-z][A-Za-z0-9+.-]*:[^\s]*$"),
+    ("email", r"^[^@\s]+@[^@\s]+$"),
+];
+
+// ============================================================================
+// Schema model (the subset of JSON Schema the generator understands)
+// ============================================================================
+
+/// A schema node; unknown keywords (`$id`, `const`, `allOf`, ...) are ignored
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaNode {
+    #[serde(rename = "type")]
+    node_type: Option<String>,
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    description: Option<String>,
     #[serde(rename = "enum")]
     enum_values: Option<Vec<String>>,
+    pattern: Option<String>,
+    format: Option<String>,
+    min_length: Option<usize>,
+    min_items: Option<usize>,
+    items: Option<Box<SchemaNode>>,
+    #[serde(default)]
+    properties: OrderedMap,
+    #[serde(default)]
+    required: Vec<String>,
+    additional_properties: Option<serde_json::Value>,
+    #[serde(rename = "$defs", default)]
+    defs: OrderedMap,
+}
+
+/// Schema object keeping declaration order (field and type order follow the schema)
+#[derive(Debug, Default)]
+struct OrderedMap(Vec<(String, SchemaNode)>);
+
+impl<'de> Deserialize<'de> for OrderedMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedMapVisitor;
+
+        impl<'de> Visitor<'de> for OrderedMapVisitor {
+            type Value = OrderedMap;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of schema nodes")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedMap, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedMap(entries))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedMapVisitor)
+    }
+}
+
+impl SchemaNode {
+    fn is_object(&self) -> bool {
+        self.node_type.as_deref() == Some("object") || !self.properties.0.is_empty()
+    }
+
+    fn is_string_enum(&self) -> bool {
+        self.node_type.as_deref() == Some("string") && self.enum_values.is_some()
+    }
+}
+
+// ============================================================================
+// Type resolution
+// ============================================================================
+
+/// Rust shape of a schema value, with the checks it needs
+enum Kind {
+    Bool,
+    Enum(String),
+    Struct(String),
+    Str(Vec<StrCheck>),
+    List(Box<Kind>, Option<usize>),
+}
+
+enum StrCheck {
+    MinLength(usize),
+    /// Index into `Generator::validators`
+    Pattern(usize),
+}
+
+/// A generated `is_*` validator backed by a regex
+struct Validator {
+    /// Snake-case base name (`slug` -> `is_slug`, `SLUG_PATTERN`)
+    name: String,
+    /// Name shown in violation messages (schema def or format name)
+    label: String,
+    pattern: String,
+    description: Option<String>,
+}
+
+impl Kind {
+    fn rust_type(&self) -> String {
+        match self {
+            Kind::Bool => "bool".to_string(),
+            Kind::Enum(name) | Kind::Struct(name) => name.clone(),
+            Kind::Str(_) => "String".to_string(),
+            Kind::List(item, _) => format!("Vec<{}>", item.rust_type()),
+        }
+    }
+}
+
+/// Path of a value in the generated checks, as a `format!` string and its arguments
+#[derive(Clone)]
+struct PathExpr {
+    fmt: String,
+    args: Vec<String>,
+}
+
+impl PathExpr {
+    fn field(json_name: &str) -> Self {
+        Self {
+            fmt: format!("{{}}.{}", json_name),
+            args: vec!["path".to_string()],
+        }
+    }
+
+    fn index(&self, var: &str) -> Self {
+        let mut args = self.args.clone();
+        args.push(var.to_string());
+        Self {
+            fmt: format!("{}[{{}}]", self.fmt),
+            args,
+        }
+    }
+
+    fn render(&self) -> String {
+        format!("&format!(\"{}\", {})", self.fmt, self.args.join(", "))
+    }
+}
+
+/// Helper functions emitted into the generated file (only when used)
+#[derive(Default)]
+struct UsedHelpers {
+    pattern: bool,
+    min_length: bool,
+    min_items: bool,
+}
+
+struct Generator<'a> {
+    schema: &'a SchemaNode,
+    schema_file: &'a str,
+    with_validators: bool,
+    validators: Vec<Validator>,
+    helpers: UsedHelpers,
+}
+
+impl<'a> Generator<'a> {
+    fn new(schema: &'a SchemaNode, schema_file: &'a str, with_validators: bool) -> Self {
+        Self {
+            schema,
+            schema_file,
+            with_validators,
+            validators: Vec::new(),
+            helpers: UsedHelpers::default(),
+        }
+    }
+
+    fn lookup_def(&self, reference: &str) -> Result<(&'a str, &'a SchemaNode), String> {
+        let name = reference.strip_prefix("#/$defs/").ok_or_else(|| {
+            format!(
+                "unsupported $ref '{}' (only #/$defs/... is supported)",
+                reference
+            )
+        })?;
+        self.schema
+            .defs
+            .0
+            .iter()
+            .find(|(def_name, _)| def_name == name)
+            .map(|(def_name, def)| (def_name.as_str(), def))
+            .ok_or_else(|| format!("$ref '{}' not found in $defs", reference))
+    }
+
+    /// Resolve a property to its Rust shape; `context` names inline validators
+    fn resolve(&mut self, node: &SchemaNode, context: &str) -> Result<Kind, String> {
+        if let Some(reference) = &node.reference {
+            let (name, def) = self.lookup_def(reference)?;
+            return self.resolve_node(def, name, true);
+        }
+        self.resolve_node(node, context, false)
+    }
+
+    /// `named` is true for `$defs` entries, false for inline property schemas
+    fn resolve_node(&mut self, node: &SchemaNode, name: &str, named: bool) -> Result<Kind, String> {
+        if node.is_object() {
+            return Ok(Kind::Struct(to_pascal_case(name)));
+        }
+        if node.is_string_enum() {
+            return Ok(Kind::Enum(to_pascal_case(name)));
+        }
+        match node.node_type.as_deref() {
+            Some("boolean") => Ok(Kind::Bool),
+            Some("string") => {
+                let mut checks = Vec::new();
+                if let Some(min) = node.min_length {
+                    checks.push(StrCheck::MinLength(min));
+                }
+                if let Some(pattern) = &node.pattern {
+                    let (label, description) = if named {
+                        (name, node.description.as_deref())
+                    } else {
+                        ("pattern", None)
+                    };
+                    let index = self.validator(name, label, pattern, description)?;
+                    checks.push(StrCheck::Pattern(index));
+                }
+                if let Some(format) = &node.format {
+                    let pattern = FORMAT_PATTERNS
+                        .iter()
+                        .find(|(f, _)| f == format)
+                        .map(|(_, p)| *p)
+                        .ok_or_else(|| format!("unsupported format '{}' in '{}'", format, name))?;
+                    let description = format!("JSON Schema `{}` format", format);
+                    let index = self.validator(format, format, pattern, Some(&description))?;
+                    checks.push(StrCheck::Pattern(index));
+                }
+                Ok(Kind::Str(checks))
+            }
+            Some("array") => {
+                let items = node
+                    .items
+                    .as_ref()
+                    .ok_or_else(|| format!("array '{}' has no items", name))?;
+                let item = self.resolve(items, &format!("{}_item", name))?;
+                Ok(Kind::List(Box::new(item), node.min_items))
+            }
+            other => Err(format!("unsupported type {:?} for '{}'", other, name)),
+        }
+    }
+
+    /// Register a regex validator once and return its index
+    fn validator(
+        &mut self,
+        name: &str,
+        label: &str,
+        pattern: &str,
+        description: Option<&str>,
+    ) -> Result<usize, String> {
+        let name = to_snake_case(name);
+        if let Some(index) = self.validators.iter().position(|v| v.name == name) {
+            return Ok(index);
+        }
+        regex::Regex::new(pattern).map_err(|e| format!("invalid pattern for '{}': {}", name, e))?;
+        self.validators.push(Validator {
+            name,
+            label: label.to_string(),
+            pattern: pattern.to_string(),
+            description: description.map(str::to_string),
+        });
+        Ok(self.validators.len() - 1)
+    }
+
+    // ------------------------------------------------------------------------
+    // Emission
+    // ------------------------------------------------------------------------
+
+    fn generate(mut self) -> Result<String, String> {
+        let mut types = String::new();
+        for (name, def) in &self.schema.defs.0 {
+            if def.is_string_enum() {
+                types.push_str(&generate_enum(name, def)?);
+            } else if def.is_object() {
+                types.push_str(&self.generate_struct(&to_pascal_case(name), name, def)?);
+            }
+        }
+        types.push_str(&self.generate_struct(ROOT_TYPE_NAME, "root", self.schema)?);
+
+        let mut output = generate_header(
+            self.schema_file,
+            self.with_validators,
+            self.with_validators && !self.validators.is_empty(),
+        );
+        output.push_str(&types);
+        if self.with_validators {
+            output.push_str(&self.generate_validators());
+            output.push_str(&self.generate_helpers());
+        }
+
+        // Single trailing newline
+        Ok(format!("{}\n", output.trim_end()))
+    }
+
+    fn generate_struct(
+        &mut self,
+        type_name: &str,
+        schema_name: &str,
+        node: &SchemaNode,
+    ) -> Result<String, String> {
+        let description = node
+            .description
+            .as_deref()
+            .ok_or_else(|| format!("'{}' needs a description", schema_name))?;
+        let mut serde_attrs = vec!["rename_all = \"camelCase\""];
+        if node.additional_properties == Some(serde_json::Value::Bool(false)) {
+            serde_attrs.push("deny_unknown_fields");
+        }
+
+        let mut fields = String::new();
+        let mut checks = String::new();
+        for (json_name, prop) in &node.properties.0 {
+            let field_name = to_snake_case(json_name);
+            let kind = self.resolve(
+                prop,
+                &format!("{}_{}", to_snake_case(type_name), field_name),
+            )?;
+            let optional = !node.required.contains(json_name);
+            let rust_field = rust_identifier(&field_name)?;
+
+            let doc = match (&prop.description, &prop.reference) {
+                (Some(description), _) => Some(description.clone()),
+                (None, Some(reference)) => self.lookup_def(reference)?.1.description.clone(),
+                (None, None) => None,
+            };
+            if let Some(doc) = doc {
+                fields.push_str(&doc_comment(&doc, "    "));
+            }
+            if to_camel_case(&field_name) != *json_name {
+                fields.push_str(&format!("    #[serde(rename = \"{}\")]\n", json_name));
+            }
+            if optional {
+                fields.push_str("    #[serde(skip_serializing_if = \"Option::is_none\")]\n");
+                fields.push_str(&format!(
+                    "    pub {}: Option<{}>,\n",
+                    rust_field,
+                    kind.rust_type()
+                ));
+            } else {
+                fields.push_str(&format!("    pub {}: {},\n", rust_field, kind.rust_type()));
+            }
+
+            let path = PathExpr::field(json_name);
+            if optional {
+                let body = self.emit_checks(&kind, "value", &path, 3, 0);
+                if !body.is_empty() {
+                    checks.push_str(&format!(
+                        "        if let Some(value) = &self.{} {{\n{}        }}\n",
+                        rust_field, body
+                    ));
+                }
+            } else {
+                checks.push_str(&self.emit_checks(
+                    &kind,
+                    &format!("&self.{}", rust_field),
+                    &path,
+                    2,
+                    0,
+                ));
+            }
+        }
+
+        let (path_param, violations_param) = if checks.is_empty() {
+            ("_path", "_violations")
+        } else {
+            ("path", "violations")
+        };
+        let params = format!(
+            "&self, {}: &str, {}: &mut Vec<DescriptorViolation>",
+            path_param, violations_param
+        );
+        let mut signature = format!("pub(crate) fn schema_violations({}) {{", params);
+        if signature.len() + 4 > 100 {
+            signature = format!(
+                "pub(crate) fn schema_violations(\n{}\n    ) {{",
+                params
+                    .split(", ")
+                    .map(|param| format!("        {},", param))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        let definition = format!(
+            r#"{doc}#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde({serde_attrs})]
+pub struct {type_name} {{
+{fields}}}
+
+"#,
+            doc = doc_comment(description, ""),
+            serde_attrs = serde_attrs.join(", "),
+        );
+        if !self.with_validators {
+            return Ok(definition);
+        }
+
+        Ok(format!(
+            r#"{definition}impl {type_name} {{
+    /// Collect violations of the schema's per-field rules (patterns, lengths)
+    {signature}
+{checks}    }}
+}}
+
+"#
+        ))
+    }
+
+    /// Emit the checks for one value; `depth` picks the loop index variable
+    fn emit_checks(
+        &mut self,
+        kind: &Kind,
+        value: &str,
+        path: &PathExpr,
+        indent: usize,
+        depth: usize,
+    ) -> String {
+        let pad = "    ".repeat(indent);
+        let mut out = String::new();
+        match kind {
+            Kind::Bool | Kind::Enum(_) => {}
+            Kind::Struct(_) => {
+                let receiver = value.trim_start_matches('&');
+                let args = [path.render(), "violations".to_string()];
+                let method = format!("{}.schema_violations", receiver);
+                let one_line = call(&pad, &method, &args);
+                // rustfmt splits `self.field.method(..)` chains past chain_width = 60
+                if receiver.contains('.') && one_line.trim().len() > 61 {
+                    out.push_str(&format!("{}{}\n", pad, receiver));
+                    out.push_str(&call(&format!("{}    ", pad), ".schema_violations", &args));
+                } else {
+                    out.push_str(&one_line);
+                }
+            }
+            Kind::Str(checks) => {
+                for check in checks {
+                    match check {
+                        StrCheck::MinLength(min) => {
+                            self.helpers.min_length = true;
+                            out.push_str(&call(
+                                &pad,
+                                "check_min_length",
+                                &[
+                                    value.to_string(),
+                                    min.to_string(),
+                                    path.render(),
+                                    "violations".to_string(),
+                                ],
+                            ));
+                        }
+                        StrCheck::Pattern(index) => {
+                            self.helpers.pattern = true;
+                            let validator = &self.validators[*index];
+                            out.push_str(&call(
+                                &pad,
+                                "check_pattern",
+                                &[
+                                    value.to_string(),
+                                    format!("is_{}", validator.name),
+                                    format!("\"{}\"", validator.label),
+                                    format!("{}_PATTERN", validator.name.to_uppercase()),
+                                    path.render(),
+                                    "violations".to_string(),
+                                ],
+                            ));
+                        }
+                    }
+                }
+            }
+            Kind::List(item, min_items) => {
+                if let Some(min) = min_items {
+                    self.helpers.min_items = true;
+                    out.push_str(&call(
+                        &pad,
+                        "check_min_items",
+                        &[
+                            format!("{}.len()", value.trim_start_matches('&')),
+                            min.to_string(),
+                            path.render(),
+                            "violations".to_string(),
+                        ],
+                    ));
+                }
+                let var = ["i", "j", "k"][depth.min(2)];
+                let body = self.emit_checks(item, "item", &path.index(var), indent + 1, depth + 1);
+                if !body.is_empty() {
+                    out.push_str(&format!(
+                        "{}for ({}, item) in {}.iter().enumerate() {{\n{}{}}}\n",
+                        pad,
+                        var,
+                        value.trim_start_matches('&'),
+                        body,
+                        pad
+                    ));
+                }
+            }
+        }
+        out
+    }
+
+    fn generate_validators(&self) -> String {
+        let mut out = String::new();
+        if self.validators.is_empty() {
+            return out;
+        }
+        out.push_str(
+            "// ============================================================================\n\
+             // Pattern and format validators\n\
+             // ============================================================================\n\n",
+        );
+        for validator in &self.validators {
+            let const_name = format!("{}_PATTERN", validator.name.to_uppercase());
+            let description = validator
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Inline schema pattern ({})", validator.name));
+            out.push_str(&doc_comment(&description, ""));
+            out.push_str(&format!(
+                r####"pub const {const_name}: &str = r#"{pattern}"#;
+
+/// Check a value against `{const_name}`
+pub fn is_{name}(value: &str) -> bool {{
+    static PATTERN: LazyLock<Regex> = LazyLock::new(|| compile({const_name}));
+    PATTERN.is_match(value)
+}}
+
+"####,
+                pattern = validator.pattern,
+                name = validator.name,
+            ));
+        }
+        out
+    }
+
+    fn generate_helpers(&self) -> String {
+        let mut out = String::new();
+        if !self.validators.is_empty() {
+            out.push_str(
+                r#"fn compile(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("schema pattern compiles")
+}
+
+"#,
+            );
+        }
+        if self.helpers.pattern {
+            out.push_str(
+                r#"fn check_pattern(
+    value: &str,
+    is_valid: fn(&str) -> bool,
+    name: &str,
+    pattern: &str,
+    path: &str,
+    violations: &mut Vec<DescriptorViolation>,
+) {
+    if !is_valid(value) {
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message: format!("must match {} `{}`: '{}'", name, pattern, value),
+        });
+    }
+}
+
+"#,
+            );
+        }
+        if self.helpers.min_length {
+            out.push_str(
+                r#"fn check_min_length(
+    value: &str,
+    min: usize,
+    path: &str,
+    violations: &mut Vec<DescriptorViolation>,
+) {
+    if value.chars().count() < min {
+        let message = if min == 1 {
+            "must not be empty".to_string()
+        } else {
+            format!("must be at least {} characters", min)
+        };
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message,
+        });
+    }
 }
 
-fn generate_header() -> String {
-    r#"//! GENERATED FILE - DO NOT EDIT
+"#,
+            );
+        }
+        if self.helpers.min_items {
+            out.push_str(
+                r#"fn check_min_items(len: usize, min: usize, path: &str, violations: &mut Vec<DescriptorViolation>) {
+    if len < min {
+        let message = if min == 1 {
+            "must contain at least one item".to_string()
+        } else {
+            format!("must contain at least {} items", min)
+        };
+        violations.push(DescriptorViolation {
+            path: path.to_string(),
+            message,
+        });
+    }
+}
+
+"#,
+            );
+        }
+        out
+    }
+}
+
+fn generate_header(schema_file: &str, with_violations: bool, with_regex: bool) -> String {
+    let (regex_import, lazy_lock_import) = if with_regex {
+        ("use regex::Regex;\n", "use std::sync::LazyLock;\n")
+    } else {
+        ("", "")
+    };
+    let violation_import = if with_violations {
+        "\nuse super::descriptor::DescriptorViolation;\n"
+    } else {
+        ""
+    };
+    format!(
+        r#"//! GENERATED FILE - DO NOT EDIT
 //!
-//! Generated from: schema/portal-descriptor.schema.json
+//! Generated from: schema/{schema_file}
 //!
 //! To regenerate, run: cargo run --bin generate-types
+//! To check for drift, run: cargo run --bin generate-types -- --check
 
-use serde::{Deserialize, Serialize};
-
+{regex_import}use serde::{{Deserialize, Serialize}};
+{lazy_lock_import}{violation_import}
 "#
-    .to_string()
-}
-
-fn generate_auth_type(schema: &JsonSchema) -> String {
-    let enum_values = schema
-        .defs
-        .as_ref()
-        .and_then(|d| d.auth_type.as_ref())
-        .and_then(|a| a.enum_values.as_ref())
-        .expect("authType enum not found in schema");
-
-    let variants: Vec<String> = enum_values
-        .iter()
-        .map(|v| {
-            let variant_name = match v.as_str() {
-                "none" => "None",
-                "oauth2-proxy" => "Oauth2Proxy",
-                "portal" => "Portal",
-                other => panic!("Unknown authType variant: {}", other),
-            };
-            format!("    /// Service has {} authentication\n    {},",
-                if v == "none" { "no" } else { v },
-                variant_name
-            )
-        })
-        .collect();
+    )
+}
 
-    format!(
-        r#"/// Authentication type for a service
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+fn generate_enum(name: &str, def: &SchemaNode) -> Result<String, String> {
+    let description = def
+        .description
+        .as_deref()
+        .ok_or_else(|| format!("'{}' needs a description", name))?;
+    let mut variants = String::new();
+    for value in def.enum_values.iter().flatten() {
+        let variant = to_pascal_case(value);
+        variants.push_str(&format!("    /// `\"{}\"`\n", value));
+        if to_kebab_case(&variant) != *value {
+            variants.push_str(&format!("    #[serde(rename = \"{}\")]\n", value));
+        }
+        variants.push_str(&format!("    {},\n", variant));
+    }
+
+    Ok(format!(
+        r#"{doc}#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
-pub enum AuthType {{
-{}
-}}
+pub enum {type_name} {{
+{variants}}}
 
 "#,
-        variants.join("\n")
-    )
+        doc = doc_comment(description, ""),
+        type_name = to_pascal_case(name),
+    ))
 }
 
-fn generate_portal_config() -> String {
-    r#"/// Portal configuration within the descriptor
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct PortalConfig {
-    /// Browser-visible URL for the portal
-    pub public_url: String,
+/// Emit a call statement, one argument per line past rustfmt's default widths
+fn call(pad: &str, function: &str, args: &[String]) -> String {
+    let joined = args.join(", ");
+    let single = format!("{}{}({});\n", pad, function, joined);
+    // max_width = 100 (plus the newline), fn_call_width = 60
+    if single.len() <= 101 && joined.len() <= 60 {
+        return single;
+    }
+    let mut out = format!("{}{}(\n", pad, function);
+    for arg in args {
+        out.push_str(&format!("{}    {},\n", pad, arg));
+    }
+    out.push_str(&format!("{});\n", pad));
+    out
 }
 
-"#
-    .to_string()
+fn doc_comment(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                format!("{}///\n", indent)
+            } else {
+                format!("{}/// {}\n", indent, line)
+            }
+        })
+        .collect()
 }
 
-fn generate_keycloak_config() -> String {
-    r#"/// Keycloak configuration within the descriptor
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct KeycloakConfig {
-    /// Browser-visible URL for Keycloak
-    pub public_url: String,
-    /// OIDC issuer URL (e.g., https://keycloak.example.com/realms/dev)
-    pub issuer_url: String,
-    /// Realm name
-    pub realm: String,
+// ============================================================================
+// Naming
+// ============================================================================
+
+/// `portalConfig` / `oauth2-proxy` -> `PortalConfig` / `Oauth2Proxy`
+fn to_pascal_case(name: &str) -> String {
+    name.split(['-', '_'])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
 }
 
-"#
-    .to_string()
+/// `deploymentId` / `date-time` -> `deployment_id` / `date_time`
+fn to_snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else if c == '-' {
+            out.push('_');
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
-fn generate_deployment_info() -> String {
-    r#"/// Deployment metadata for tracking what/when was deployed
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct DeploymentInfo {
-    /// Git commit SHA that was deployed (40-character hex)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub commit_sha: Option<String>,
-    /// When the commit was made (git committer date, ISO 8601 UTC)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub commit_at: Option<String>,
-    /// When the deployment happened (ISO 8601 UTC)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub deployed_at: Option<String>,
+/// Inverse of serde's `camelCase` field renaming
+fn to_camel_case(snake: &str) -> String {
+    let mut out = String::new();
+    let mut upper = false;
+    for c in snake.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
-"#
-    .to_string()
-}
-
-fn generate_service() -> String {
-    r#"/// A service entry in the descriptor
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct Service {
-    /// Stable identifier / slug (e.g., "demo", "api", "docs")
-    pub id: String,
-    /// Display name (e.g., "Demo App", "API Documentation")
-    pub name: String,
-    /// Fully-qualified, browser-visible URL
-    pub url: String,
-    /// Whether the service requires authentication
-    pub protected: bool,
-    /// How authentication is handled
-    pub auth_type: AuthType,
-    /// Optional grouping for UI organization
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub group: Option<String>,
-    /// Optional icon (emoji or icon name)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub icon: Option<String>,
-    /// Optional description
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub description: Option<String>,
-    /// Required realm roles to access this service (for UI filtering)
-    ///
-    /// Rules (enforced by schema):
-    /// - Required for authType: Oauth2Proxy and Portal services
-    /// - Forbidden for authType: None services
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub required_realm_roles: Option<Vec<String>>,
+/// Serde's `kebab-case` variant renaming
+fn to_kebab_case(pascal: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in pascal.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                out.push('-');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
-"#
-    .to_string()
-}
-
-fn generate_descriptor() -> String {
-    r#"/// Portal Descriptor v1 - Complete deployment descriptor
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", deny_unknown_fields)]
-pub struct Descriptor {
-    /// Schema version (currently "1")
-    pub version: String,
-    /// Deployment identifier (e.g., "prod", "staging", "local")
-    pub deployment_id: String,
-    /// Environment type (e.g., "prod", "dev")
-    pub environment: String,
-    /// Base domain (e.g., "localhost", "example.com")
-    pub base_domain: String,
-    /// Deployment metadata (commit, timestamps) - optional
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub deployment: Option<DeploymentInfo>,
-    /// Portal configuration
-    pub portal: PortalConfig,
-    /// Keycloak configuration
-    pub keycloak: KeycloakConfig,
-    /// Services to display (order is display order)
-    pub services: Vec<Service>,
+fn rust_identifier(name: &str) -> Result<String, String> {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+        "where", "while",
+    ];
+    match name {
+        "self" | "super" | "crate" | "Self" => {
+            Err(format!("field name '{}' is not supported", name))
+        }
+        _ if KEYWORDS.contains(&name) => Ok(format!("r#{}", name)),
+        _ => Ok(name.to_string()),
+    }
 }
 
-"#
-    .to_string()
+// ============================================================================
+// Entry points
+// ============================================================================
+
+/// Generate a module's content from schema JSON
+fn generate_from_schema(
+    schema_json: &str,
+    schema_file: &str,
+    with_validators: bool,
+) -> Result<String, String> {
+    let schema: SchemaNode =
+        serde_json::from_str(schema_json).map_err(|e| format!("failed to parse schema: {}", e))?;
+    Generator::new(&schema, schema_file, with_validators).generate()
 }
 
-fn main() {
+fn generate_target(target: &Target) -> Result<String, String> {
+    let schema_content = fs::read_to_string(target.schema)
+        .map_err(|e| format!("failed to read {}: {}", target.schema, e))?;
+    let schema_file = Path::new(target.schema)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(target.schema);
+    generate_from_schema(&schema_content, schema_file, target.validators)
+}
+
+fn generate_types(check: bool) -> Result<(), String> {
+    if check {
+        let mut stale = Vec::new();
+        for target in TARGETS {
+            let current = fs::read_to_string(target.output).unwrap_or_default();
+            if current != generate_target(target)? {
+                stale.push(target.output);
+            } else {
+                println!("{} is up to date with {}", target.output, target.schema);
+            }
+        }
+        if !stale.is_empty() {
+            return Err(format!(
+                "{} stale; run 'cargo run --bin generate-types' and commit the result",
+                stale.join(", ")
+            ));
+        }
+        return Ok(());
+    }
+
     println!("Generating Rust types from JSON Schema...");
-    println!("  Schema: {}", SCHEMA_PATH);
-    println!("  Output: {}", OUTPUT_PATH);
-
-    let schema_content = fs::read_to_string(SCHEMA_PATH)
-        .expect("Failed to read schema file");
-    let schema: JsonSchema = serde_json::from_str(&schema_content)
-        .expect("Failed to parse schema JSON");
-
-    let output = format!(
-        "{}{}{}{}{}{}{}",
-        generate_header(),
-        generate_auth_type(&schema),
-        generate_portal_config(),
-        generate_keycloak_config(),
-        generate_deployment_info(),
-        generate_service(),
-        generate_descriptor(),
+    for target in TARGETS {
+        let output = generate_target(target)?;
+        println!("  Schema: {}", target.schema);
+        println!("  Output: {}", target.output);
+
+        // Ensure output directory exists
+        if let Some(parent) = Path::new(target.output).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create output directory: {}", e))?;
+        }
+
+        fs::write(target.output, output)
+            .map_err(|e| format!("failed to write {}: {}", target.output, e))?;
+    }
+    println!("Done!");
+    Ok(())
+}
+
+/// Options for the `sign-descriptor` subcommand
+struct SignOptions {
+    key_path: Option<String>,
+    generate_key: Option<String>,
+    envelope: bool,
+    descriptor_path: Option<String>,
+}
+
+fn parse_sign_options(args: &[String]) -> Result<SignOptions, String> {
+    let mut options = SignOptions {
+        key_path: None,
+        generate_key: None,
+        envelope: false,
+        descriptor_path: None,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--key" => options.key_path = Some(iter.next().ok_or("--key needs a path")?.clone()),
+            "--generate-key" => {
+                options.generate_key =
+                    Some(iter.next().ok_or("--generate-key needs a path")?.clone())
+            }
+            "--envelope" => options.envelope = true,
+            other if other.starts_with("--") => return Err(format!("unknown option {}", other)),
+            other => options.descriptor_path = Some(other.to_string()),
+        }
+    }
+    Ok(options)
+}
+
+fn sign_descriptor(args: &[String]) -> Result<(), String> {
+    let options = parse_sign_options(args)?;
+
+    if let Some(path) = options.generate_key {
+        if Path::new(&path).exists() {
+            return Err(format!("refusing to overwrite existing key file {}", path));
+        }
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        fs::write(&path, encode_base64(signing_key.as_bytes()))
+            .map_err(|e| format!("failed to write {}: {}", path, e))?;
+        eprintln!("Signing key written to {} (keep it secret)", path);
+        println!("{}", encode_base64(signing_key.verifying_key().as_bytes()));
+        return Ok(());
+    }
+
+    let key_path = options.key_path.ok_or("--key is required")?;
+    let descriptor_path = options
+        .descriptor_path
+        .ok_or("descriptor path is required")?;
+
+    let key =
+        fs::read_to_string(&key_path).map_err(|e| format!("failed to read {}: {}", key_path, e))?;
+    let signing_key = decode_signing_key(&key)?;
+    let payload = fs::read_to_string(&descriptor_path)
+        .map_err(|e| format!("failed to read {}: {}", descriptor_path, e))?;
+
+    if options.envelope {
+        let envelope = serde_json::to_string(&sign_envelope(&payload, &signing_key))
+            .map_err(|e| format!("failed to serialize envelope: {}", e))?;
+        println!("{}", envelope);
+    } else {
+        let signature_path = detached_signature_path(&descriptor_path);
+        fs::write(&signature_path, sign_payload(&payload, &signing_key))
+            .map_err(|e| format!("failed to write {}: {}", signature_path, e))?;
+        eprintln!("Signature written to {}", signature_path);
+    }
+    Ok(())
+}
+
+fn migrate_descriptor(args: &[String]) -> Result<(), String> {
+    let mut input = None;
+    let mut output = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--output" => output = Some(iter.next().ok_or("--output needs a path")?.clone()),
+            other if other.starts_with("--") => return Err(format!("unknown option {}", other)),
+            other => input = Some(other.to_string()),
+        }
+    }
+    let input = input.ok_or("descriptor path is required")?;
+    let output = output.unwrap_or_else(|| input.clone());
+    if DescriptorFormat::from_path(&input) != DescriptorFormat::Json {
+        // Rewriting would drop comments; v1 -> v2 is additive, so a hand edit is enough
+        return Err(format!(
+            "only JSON descriptors are rewritten; change `version` to \"{}\" in {} by hand",
+            CURRENT_VERSION, input
+        ));
+    }
+
+    let json =
+        fs::read_to_string(&input).map_err(|e| format!("failed to read {}: {}", input, e))?;
+    let version = serde_json::from_str::<serde_json::Value>(&json)
+        .ok()
+        .and_then(|value| value.get("version")?.as_str().map(str::to_string));
+    if version.as_deref() == Some(CURRENT_VERSION) && output == input {
+        eprintln!("{} is already version {}", input, CURRENT_VERSION);
+        return Ok(());
+    }
+
+    let descriptor = Descriptor::from_json_with_source(&json, DescriptorSource::FilePath)
+        .map_err(|e| e.to_string())?;
+    descriptor.validate().map_err(|violations| {
+        DescriptorError::validation(DescriptorSource::FilePath, violations).to_string()
+    })?;
+
+    let rewritten = serde_json::to_string_pretty(&descriptor)
+        .map_err(|e| format!("failed to serialize descriptor: {}", e))?;
+    fs::write(&output, format!("{}\n", rewritten))
+        .map_err(|e| format!("failed to write {}: {}", output, e))?;
+    eprintln!(
+        "Rewrote {} (version {}) as version {}: {}",
+        input,
+        version.as_deref().unwrap_or("?"),
+        CURRENT_VERSION,
+        output
     );
 
-    // Ensure output directory exists
-    if let Some(parent) = Path::new(OUTPUT_PATH).parent() {
-        fs::create_dir_all(parent).expect("Failed to create output directory");
+    let signature_path = detached_signature_path(&output);
+    if Path::new(&signature_path).exists() {
+        eprintln!(
+            "warning: {} no longer matches; re-sign with sign-descriptor",
+            signature_path
+        );
     }
+    Ok(())
+}
 
-    fs::write(OUTPUT_PATH, output).expect("Failed to write output file");
-    println!("Done!");
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("sign-descriptor") => match sign_descriptor(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("sign-descriptor: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Some("migrate-descriptor") => match migrate_descriptor(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("migrate-descriptor: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        None | Some("--check") if args.len() <= 1 => match generate_types(!args.is_empty()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("generate-types: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!(
+                "usage: generate-types [--check] | generate-types sign-descriptor ... \
+                 | generate-types migrate-descriptor ..."
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_in_files_are_current() {
+        for target in TARGETS {
+            let current = fs::read_to_string(target.output).unwrap();
+            assert!(
+                generate_target(target).unwrap() == current,
+                "{} is stale; run 'cargo run --bin generate-types'",
+                target.output
+            );
+        }
+    }
+
+    #[test]
+    fn test_generates_structs_enums_and_checks() {
+        let schema = r##"{
+            "description": "Root",
+            "type": "object",
+            "additionalProperties": false,
+            "required": ["kind", "type", "tags"],
+            "properties": {
+                "kind": { "$ref": "#/$defs/kind" },
+                "type": { "type": "string", "pattern": "^[a-z]+$" },
+                "seenAt": { "type": "string", "format": "date-time" },
+                "tags": { "type": "array", "minItems": 2, "items": { "$ref": "#/$defs/slug" } }
+            },
+            "$defs": {
+                "slug": { "type": "string", "pattern": "^[a-z-]+$", "description": "Slug" },
+                "kind": { "type": "string", "enum": ["a-b", "c"], "description": "Kind" }
+            }
+        }"##;
+        let output = generate_from_schema(schema, "test.json", true).unwrap();
+
+        assert!(output.contains("pub enum Kind {"));
+        assert!(output.contains("    AB,"));
+        assert!(output.contains("#[serde(rename_all = \"camelCase\", deny_unknown_fields)]"));
+        assert!(output.contains("    pub r#type: String,"));
+        assert!(output.contains("    pub seen_at: Option<String>,"));
+        assert!(output.contains("    pub tags: Vec<String>,"));
+        assert!(output.contains("pub fn is_slug(value: &str) -> bool"));
+        assert!(output.contains("pub fn is_descriptor_type(value: &str) -> bool"));
+        assert!(output.contains("pub fn is_date_time(value: &str) -> bool"));
+        assert!(output.contains("check_min_items(self.tags.len(), 2,"));
+        assert!(output.contains("format!(\"{}.tags[{}]\", path, i)"));
+
+        let types_only = generate_from_schema(schema, "test.json", false).unwrap();
+        assert!(types_only.contains("pub struct Descriptor {"));
+        assert!(!types_only.contains("schema_violations"));
+        assert!(!types_only.contains("Regex"));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_schema() {
+        let unknown_format = r#"{
+            "description": "Root",
+            "properties": { "a": { "type": "string", "format": "hostname" } }
+        }"#;
+        assert!(generate_from_schema(unknown_format, "test.json", true)
+            .unwrap_err()
+            .contains("unsupported format 'hostname'"));
+
+        let missing_ref = r##"{
+            "description": "Root",
+            "properties": { "a": { "$ref": "#/$defs/missing" } }
+        }"##;
+        assert!(generate_from_schema(missing_ref, "test.json", true)
+            .unwrap_err()
+            .contains("not found"));
+    }
+
+    #[test]
+    fn test_naming() {
+        assert_eq!(to_pascal_case("oauth2-proxy"), "Oauth2Proxy");
+        assert_eq!(to_pascal_case("portalConfig"), "PortalConfig");
+        assert_eq!(to_snake_case("requiredRealmRoles"), "required_realm_roles");
+        assert_eq!(to_camel_case("required_realm_roles"), "requiredRealmRoles");
+        assert_eq!(to_kebab_case("Oauth2Proxy"), "oauth2-proxy");
+    }
 }
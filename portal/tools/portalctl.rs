@@ -0,0 +1,584 @@
+//! Descriptor operations from the command line
+//!
+//! Usage:
+//!
+//! ```text
+//! portalctl validate <descriptor.json>
+//! portalctl summary <descriptor.json>
+//! portalctl diff <old.json> <new.json>
+//! portalctl render-for-roles [--provider <id>] <descriptor.json> [<role>...]
+//! ```
+//!
+//! Descriptors are loaded exactly as the portal loads `PORTAL_DESCRIPTOR_PATH`
+//! (signed envelopes and detached `.sig` files, format by extension or a
+//! directory of fragments, versioned parsing, v1 upgrade, contract rule
+//! validation), so a descriptor that passes here is one the portal will boot
+//! with. Signatures are verified when PORTAL_DESCRIPTOR_PUBLIC_KEY is set, as
+//! in the portal; without it envelopes are unwrapped without a check.
+//!
+//! Every command prints a single JSON document on stdout with an `ok` field,
+//! so deploy scripts can gate on the exit code and read the details:
+//!
+//! | Exit code | Meaning |
+//! |-----------|---------|
+//! | 0 | Success (`diff`: descriptors are identical) |
+//! | 1 | Descriptor is invalid (`diff`: descriptors differ) |
+//! | 2 | Usage error or unreadable file (`diff`: either descriptor is invalid) |
+
+use portal::services::descriptor::{
+    Descriptor, DescriptorError, DescriptorFormat, DescriptorSource,
+};
+use portal::services::signature::detached_signature_path;
+use portal::services::{
+    filter_services_for_user, load_fragment_dir, services_from_descriptor, SignaturePolicy,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+const EXIT_OK: u8 = 0;
+const EXIT_FAILED: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+const USAGE: &str = "usage: portalctl validate <file> | summary <file> | diff <old> <new> \
+                     | render-for-roles [--provider <id>] <file> [<role>...]";
+
+/// JSON document for stdout and the process exit code
+#[derive(Debug)]
+struct Report {
+    body: Value,
+    code: u8,
+}
+
+impl Report {
+    fn ok(mut body: Value) -> Self {
+        body["ok"] = Value::Bool(true);
+        Self {
+            body,
+            code: EXIT_OK,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            body: json!({ "ok": false, "error": message.into() }),
+            code: EXIT_ERROR,
+        }
+    }
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+/// Why a descriptor file could not be loaded
+enum LoadError {
+    /// The file could not be read
+    Unreadable(String),
+    /// The file was read but failed to parse or validate
+    Invalid(DescriptorError),
+}
+
+impl LoadError {
+    /// Report for this error; `invalid_code` is the exit code for invalid descriptors
+    fn report(self, path: &str, invalid_code: u8) -> Report {
+        match self {
+            LoadError::Unreadable(message) => Report {
+                body: json!({ "ok": false, "file": path, "error": message }),
+                code: EXIT_ERROR,
+            },
+            LoadError::Invalid(error) => Report {
+                body: json!({
+                    "ok": false,
+                    "file": path,
+                    "error": error.message,
+                    "fieldPath": error.field_path,
+                    "violations": error.violations,
+                }),
+                code: invalid_code,
+            },
+        }
+    }
+}
+
+/// Load a descriptor file or fragment directory, unwrapping and verifying
+/// signatures with `policy`
+fn load(path: &str, policy: &SignaturePolicy) -> Result<Descriptor, LoadError> {
+    if Path::new(path).is_dir() {
+        let merged = load_fragment_dir(path, policy).map_err(LoadError::Invalid)?;
+        return match merged.descriptor.validate() {
+            Ok(()) => Ok(merged.descriptor),
+            Err(violations) => Err(LoadError::Invalid(DescriptorError::validation(
+                DescriptorSource::FilePath,
+                merged.attribute(violations),
+            ))),
+        };
+    }
+
+    let raw = fs::read_to_string(path)
+        .map_err(|e| LoadError::Unreadable(format!("failed to read {}: {}", path, e)))?;
+    let signature_path = detached_signature_path(path);
+    let detached = match fs::read_to_string(&signature_path) {
+        Ok(signature) => Some(signature),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(LoadError::Unreadable(format!(
+                "failed to read {}: {}",
+                signature_path, e
+            )))
+        }
+    };
+    let content = policy
+        .verify(&raw, detached.as_deref(), DescriptorSource::FilePath)
+        .map_err(LoadError::Invalid)?;
+    let descriptor = Descriptor::from_str_with_format(
+        &content,
+        DescriptorFormat::from_path(path),
+        DescriptorSource::FilePath,
+    )
+    .map_err(LoadError::Invalid)?;
+    descriptor.validate().map_err(|violations| {
+        LoadError::Invalid(DescriptorError::validation(
+            DescriptorSource::FilePath,
+            violations,
+        ))
+    })?;
+    Ok(descriptor)
+}
+
+// ============================================================================
+// Diff
+// ============================================================================
+
+/// Differences between two descriptors, keyed by service id
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DescriptorDiff {
+    /// Top-level fields (other than `services`) whose value changed
+    changed_fields: Vec<String>,
+    /// Ids of services only in the new descriptor
+    added: Vec<String>,
+    /// Ids of services only in the old descriptor
+    removed: Vec<String>,
+    /// Services in both descriptors whose definition changed
+    changed: Vec<ServiceChange>,
+    /// Whether services in both descriptors are displayed in a different order
+    reordered: bool,
+}
+
+impl DescriptorDiff {
+    fn is_empty(&self) -> bool {
+        self.changed_fields.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && !self.reordered
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ServiceChange {
+    id: String,
+    /// Service fields whose value changed (camelCase, as in the descriptor)
+    fields: Vec<String>,
+    /// Roles now required that were not before
+    roles_added: Vec<String>,
+    /// Roles no longer required
+    roles_removed: Vec<String>,
+}
+
+fn diff_descriptors(old: &Descriptor, new: &Descriptor) -> DescriptorDiff {
+    let mut diff = DescriptorDiff {
+        changed_fields: changed_keys(&to_value(old), &to_value(new), &["services"]),
+        ..Default::default()
+    };
+
+    let find = |descriptor: &'_ Descriptor, id: &str| {
+        descriptor
+            .services
+            .iter()
+            .position(|service| service.id == id)
+    };
+
+    for service in &new.services {
+        if find(old, &service.id).is_none() {
+            diff.added.push(service.id.clone());
+        }
+    }
+
+    let mut common = Vec::new();
+    for old_service in &old.services {
+        let Some(index) = find(new, &old_service.id) else {
+            diff.removed.push(old_service.id.clone());
+            continue;
+        };
+        common.push(index);
+
+        let new_service = &new.services[index];
+        let fields = changed_keys(&to_value(old_service), &to_value(new_service), &[]);
+        if fields.is_empty() {
+            continue;
+        }
+        let old_roles = old_service.required_realm_roles.as_deref().unwrap_or(&[]);
+        let new_roles = new_service.required_realm_roles.as_deref().unwrap_or(&[]);
+        diff.changed.push(ServiceChange {
+            id: old_service.id.clone(),
+            fields,
+            roles_added: missing_from(new_roles, old_roles),
+            roles_removed: missing_from(old_roles, new_roles),
+        });
+    }
+    // Common services in old order must appear at increasing positions in new
+    diff.reordered = common.windows(2).any(|pair| pair[0] > pair[1]);
+
+    diff
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("descriptor types serialize to JSON")
+}
+
+/// Keys of two JSON objects whose values differ (absent counts as different)
+fn changed_keys(old: &Value, new: &Value, skip: &[&str]) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|key| !skip.contains(&key.as_str()) && old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+/// Entries of `roles` that are not in `other`, in order
+fn missing_from(roles: &[String], other: &[String]) -> Vec<String> {
+    roles
+        .iter()
+        .filter(|role| !other.contains(role))
+        .cloned()
+        .collect()
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+fn validate(path: &str, policy: &SignaturePolicy) -> Report {
+    match load(path, policy) {
+        Ok(descriptor) => Report::ok(json!({ "file": path, "version": descriptor.version })),
+        Err(e) => e.report(path, EXIT_FAILED),
+    }
+}
+
+fn summary(path: &str, policy: &SignaturePolicy) -> Report {
+    match load(path, policy) {
+        Ok(descriptor) => Report::ok(json!({ "file": path, "summary": descriptor.summary() })),
+        Err(e) => e.report(path, EXIT_FAILED),
+    }
+}
+
+fn diff(old_path: &str, new_path: &str, policy: &SignaturePolicy) -> Report {
+    let old = match load(old_path, policy) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return e.report(old_path, EXIT_ERROR),
+    };
+    let new = match load(new_path, policy) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return e.report(new_path, EXIT_ERROR),
+    };
+
+    let diff = diff_descriptors(&old, &new);
+    let identical = diff.is_empty();
+    let mut report = Report::ok(json!({ "identical": identical, "diff": diff }));
+    if !identical {
+        report.code = EXIT_FAILED;
+    }
+    report
+}
+
+/// Identity provider assumed by `render-for-roles` without `--provider`
+const DEFAULT_PROVIDER: &str = "default";
+
+fn render_for_roles(
+    path: &str,
+    provider: &str,
+    roles: &[String],
+    policy: &SignaturePolicy,
+) -> Report {
+    match load(path, policy) {
+        Ok(descriptor) => {
+            let services =
+                filter_services_for_user(&services_from_descriptor(&descriptor), roles, provider);
+            Report::ok(json!({
+                "file": path,
+                "provider": provider,
+                "roles": roles,
+                "services": services
+            }))
+        }
+        Err(e) => e.report(path, EXIT_FAILED),
+    }
+}
+
+fn run(args: &[String]) -> Report {
+    let public_key = std::env::var("PORTAL_DESCRIPTOR_PUBLIC_KEY").ok();
+    match SignaturePolicy::from_base64_key(public_key.as_deref()) {
+        Ok(policy) => execute(args, &policy),
+        Err(e) => Report::error(format!("PORTAL_DESCRIPTOR_PUBLIC_KEY: {}", e)),
+    }
+}
+
+fn execute(args: &[String], policy: &SignaturePolicy) -> Report {
+    match args {
+        [command, path] if command == "validate" => validate(path, policy),
+        [command, path] if command == "summary" => summary(path, policy),
+        [command, old, new] if command == "diff" => diff(old, new, policy),
+        [command, flag, provider, path, roles @ ..]
+            if command == "render-for-roles" && flag == "--provider" =>
+        {
+            render_for_roles(path, provider, roles, policy)
+        }
+        [command, path, roles @ ..] if command == "render-for-roles" => {
+            render_for_roles(path, DEFAULT_PROVIDER, roles, policy)
+        }
+        _ => Report::error(USAGE),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let report = run(&args);
+    match serde_json::to_string_pretty(&report.body) {
+        Ok(body) => println!("{}", body),
+        Err(e) => {
+            eprintln!("portalctl: failed to serialize output: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+    ExitCode::from(report.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portal::services::signature::sign_envelope;
+
+    const DESCRIPTOR: &str = r#"{
+        "version": "1",
+        "deploymentId": "local",
+        "environment": "dev",
+        "baseDomain": "localhost",
+        "portal": { "publicUrl": "http://localhost" },
+        "keycloak": {
+            "publicUrl": "http://keycloak.localhost",
+            "issuerUrl": "http://keycloak.localhost/realms/dev",
+            "realm": "dev"
+        },
+        "services": [
+            {
+                "id": "demo",
+                "name": "Demo App",
+                "url": "http://demo.localhost",
+                "protected": true,
+                "authType": "oauth2-proxy",
+                "requiredRealmRoles": ["dev"]
+            },
+            {
+                "id": "docs",
+                "name": "Docs",
+                "url": "http://docs.localhost",
+                "protected": false,
+                "authType": "none"
+            }
+        ]
+    }"#;
+
+    /// Write `json` to a temp file unique to the calling test
+    fn write_temp(name: &str, json: &str) -> String {
+        let path = std::env::temp_dir().join(format!("portalctl_{}.json", name));
+        fs::write(&path, json).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_reports_violations() {
+        let valid = write_temp("validate_ok", DESCRIPTOR);
+        let report = run(&args(&["validate", &valid]));
+        assert_eq!(report.code, EXIT_OK);
+        assert_eq!(report.body["ok"], true);
+        assert_eq!(report.body["version"], "2");
+
+        let invalid = write_temp(
+            "validate_invalid",
+            &DESCRIPTOR.replace("http://demo.localhost", "demo.localhost"),
+        );
+        let report = run(&args(&["validate", &invalid]));
+        assert_eq!(report.code, EXIT_FAILED);
+        assert_eq!(report.body["ok"], false);
+        assert_eq!(report.body["violations"][0]["path"], "$.services[0].url");
+
+        let report = run(&args(&["validate", "/nonexistent/descriptor.json"]));
+        assert_eq!(report.code, EXIT_ERROR);
+        assert!(report.body["error"]
+            .as_str()
+            .unwrap()
+            .contains("failed to read"));
+
+        let report = run(&args(&["validate"]));
+        assert_eq!(report.code, EXIT_ERROR);
+        assert_eq!(report.body["error"], USAGE);
+    }
+
+    #[test]
+    fn test_validate_unwraps_signed_envelopes() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let envelope = sign_envelope(DESCRIPTOR, &key);
+        let path = write_temp(
+            "validate_envelope",
+            &serde_json::to_string(&envelope).unwrap(),
+        );
+
+        // Unwrapped without a key, like the portal in development
+        let report = execute(&args(&["validate", &path]), &SignaturePolicy::default());
+        assert_eq!(report.code, EXIT_OK, "{}", report.body);
+
+        let trusted = SignaturePolicy::with_public_key(key.verifying_key());
+        let report = execute(&args(&["summary", &path]), &trusted);
+        assert_eq!(report.code, EXIT_OK, "{}", report.body);
+        assert_eq!(report.body["summary"]["totalServices"], 2);
+
+        let other = ed25519_dalek::SigningKey::from_bytes(&[8; 32]);
+        let untrusted = SignaturePolicy::with_public_key(other.verifying_key());
+        let report = execute(&args(&["validate", &path]), &untrusted);
+        assert_eq!(report.code, EXIT_FAILED);
+        assert!(report.body["error"]
+            .as_str()
+            .unwrap()
+            .contains("signature verification failed"));
+
+        // A plain file is unsigned once a key is configured
+        let plain = write_temp("validate_unsigned", DESCRIPTOR);
+        let report = execute(&args(&["validate", &plain]), &trusted);
+        assert_eq!(report.code, EXIT_FAILED);
+        assert!(report.body["error"]
+            .as_str()
+            .unwrap()
+            .contains("not signed"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let path = write_temp("summary", DESCRIPTOR);
+        let report = run(&args(&["summary", &path]));
+        assert_eq!(report.code, EXIT_OK);
+        assert_eq!(report.body["summary"]["deploymentId"], "local");
+        assert_eq!(report.body["summary"]["totalServices"], 2);
+        assert_eq!(report.body["summary"]["protectedServices"], 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = write_temp("diff_old", DESCRIPTOR);
+        let report = run(&args(&["diff", &old, &old]));
+        assert_eq!(report.code, EXIT_OK);
+        assert_eq!(report.body["identical"], true);
+
+        let mut changed: Value = serde_json::from_str(DESCRIPTOR).unwrap();
+        changed["environment"] = json!("staging");
+        changed["services"][0]["name"] = json!("Demo");
+        changed["services"][0]["requiredRealmRoles"] = json!(["ops"]);
+        changed["services"][1] = json!({
+            "id": "wiki",
+            "name": "Wiki",
+            "url": "http://wiki.localhost",
+            "protected": false,
+            "authType": "none"
+        });
+        let new = write_temp("diff_new", &changed.to_string());
+
+        let report = run(&args(&["diff", &old, &new]));
+        assert_eq!(report.code, EXIT_FAILED);
+        assert_eq!(report.body["ok"], true);
+        let diff = &report.body["diff"];
+        assert_eq!(diff["changedFields"], json!(["environment"]));
+        assert_eq!(diff["added"], json!(["wiki"]));
+        assert_eq!(diff["removed"], json!(["docs"]));
+        assert_eq!(diff["changed"][0]["id"], "demo");
+        assert_eq!(
+            diff["changed"][0]["fields"],
+            json!(["name", "requiredRealmRoles"])
+        );
+        assert_eq!(diff["changed"][0]["rolesAdded"], json!(["ops"]));
+        assert_eq!(diff["changed"][0]["rolesRemoved"], json!(["dev"]));
+        assert_eq!(diff["reordered"], false);
+    }
+
+    #[test]
+    fn test_diff_detects_reordering() {
+        let old = write_temp("reorder_old", DESCRIPTOR);
+        let mut swapped: Value = serde_json::from_str(DESCRIPTOR).unwrap();
+        swapped["services"].as_array_mut().unwrap().reverse();
+        let new = write_temp("reorder_new", &swapped.to_string());
+
+        let report = run(&args(&["diff", &old, &new]));
+        assert_eq!(report.code, EXIT_FAILED);
+        assert_eq!(report.body["diff"]["reordered"], true);
+        assert_eq!(report.body["diff"]["changed"], json!([]));
+
+        let invalid = write_temp("reorder_invalid", "{}");
+        let report = run(&args(&["diff", &old, &invalid]));
+        assert_eq!(report.code, EXIT_ERROR);
+        assert_eq!(report.body["file"], invalid);
+    }
+
+    #[test]
+    fn test_render_for_roles() {
+        let path = write_temp("render", DESCRIPTOR);
+        let ids = |report: &Report| -> Vec<String> {
+            report.body["services"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|service| service["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let report = run(&args(&["render-for-roles", &path]));
+        assert_eq!(report.code, EXIT_OK);
+        assert_eq!(ids(&report), vec!["docs"]);
+
+        let report = run(&args(&["render-for-roles", &path, "dev"]));
+        assert_eq!(ids(&report), vec!["demo", "docs"]);
+        assert_eq!(report.body["roles"], json!(["dev"]));
+        assert_eq!(report.body["provider"], "default");
+
+        // v2 restricts demo to the default provider
+        let v2 = DESCRIPTOR
+            .replace(r#""version": "1""#, r#""version": "2""#)
+            .replace(
+                r#""requiredRealmRoles": ["dev"]"#,
+                r#""requiredRealmRoles": ["dev"], "identityProviders": ["default"]"#,
+            );
+        let path = write_temp("render_v2", &v2);
+        let report = run(&args(&["render-for-roles", &path, "dev"]));
+        assert_eq!(ids(&report), vec!["demo", "docs"]);
+        let report = run(&args(&[
+            "render-for-roles",
+            "--provider",
+            "contractors",
+            &path,
+            "dev",
+        ]));
+        assert_eq!(ids(&report), vec!["docs"]);
+        assert_eq!(report.body["provider"], "contractors");
+    }
+}